@@ -5,6 +5,7 @@ use warnings;
 
 use Cwd 'abs_path';
 use Encode;
+use JSON qw(to_json);
 use POSIX ":sys_wait_h";
 
 use Proxmox::Install::ISOEnv;
@@ -675,6 +676,75 @@ my sub setup_root_password {
     }
 }
 
+my sub setup_first_boot_hook {
+    my ($targetdir) = @_;
+
+    my $first_boot = Proxmox::Install::Config::get_first_boot();
+    return if !defined($first_boot);
+
+    my $iso_env = Proxmox::Install::ISOEnv::get();
+    my $src = "$iso_env->{locations}->{run}/proxmox-first-boot-hook";
+    die "first-boot hook executable '$src' not found\n" if ! -f $src;
+
+    my $hookdir = "/var/lib/proxmox-first-boot";
+    my $hook = "$hookdir/proxmox-first-boot-hook";
+    mkdir "$targetdir$hookdir";
+    syscmd("cp $src $targetdir$hook") == 0 ||
+	die "unable to copy first-boot hook executable\n";
+    chmod(0755, "$targetdir$hook");
+
+    my $unit_deps;
+    if (($first_boot->{ordering} // '') eq 'before-network') {
+	$unit_deps = "DefaultDependencies=no\n"
+	    ."After=local-fs.target\n"
+	    ."Wants=network-pre.target\n"
+	    ."Before=network-pre.target\n";
+    } else {
+	$unit_deps = "Wants=network-online.target\n"
+	    ."After=network-online.target\n";
+    }
+
+    my $unit = "[Unit]\n"
+	."Description=Proxmox First Boot Hook\n"
+	."ConditionPathExists=$hook\n"
+	.$unit_deps
+	."\n[Service]\n"
+	."Type=oneshot\n"
+	."RemainAfterExit=yes\n"
+	."ExecStart=$hook\n"
+	."ExecStartPost=/bin/rm -f $hook\n"
+	."\n[Install]\n"
+	."WantedBy=multi-user.target\n";
+
+    file_write_all("$targetdir/etc/systemd/system/proxmox-first-boot.service", $unit);
+    syscmd("chroot $targetdir systemctl enable proxmox-first-boot.service") == 0 ||
+	die "unable to enable first-boot hook service\n";
+}
+
+my sub persist_installer_logs {
+    my ($targetdir) = @_;
+
+    my $logdir = "$targetdir/var/log/proxmox-installer";
+    mkdir $logdir;
+    chmod(0700, $logdir);
+
+    my $iso_env = Proxmox::Install::ISOEnv::get();
+    my @files = (
+	glob("/tmp/install-low-level*.log"),
+	"/tmp/auto_installer.log",
+	"/tmp/fetch_answer.log",
+	"$iso_env->{locations}->{run}/answer.toml", # already redacted by the auto-installer
+    );
+    for my $file (@files) {
+	next if ! -f $file;
+	syscmd("cp $file $logdir/") == 0 || warn "unable to copy '$file' into target\n";
+    }
+
+    my $config = Proxmox::Install::Config::get_redacted();
+    file_write_all(
+	"$logdir/low-level-config.json", to_json($config, { canonical => 1, pretty => 1, utf8 => 1 }));
+}
+
 sub extract_data {
     my $iso_env = Proxmox::Install::ISOEnv::get();
     my $run_env = Proxmox::Install::RunEnv::get();
@@ -981,10 +1051,13 @@ sub extract_data {
 	my $hostname = Proxmox::Install::Config::get_hostname();
 	my $domain = Proxmox::Install::Config::get_domain();
 	my $ip_addr = Proxmox::Install::Config::get_ip_addr();
+	my $ip6_addr = Proxmox::Install::Config::get_ip6_addr();
 
 	my $hosts =
 	    "127.0.0.1 localhost.localdomain localhost\n" .
-	    "$ip_addr $hostname.$domain $hostname\n\n" .
+	    "$ip_addr $hostname.$domain $hostname\n";
+	$hosts .= "$ip6_addr $hostname.$domain $hostname\n" if defined($ip6_addr);
+	$hosts .= "\n" .
 	    "# The following lines are desirable for IPv6 capable hosts\n\n" .
 	    "::1     ip6-localhost ip6-loopback\n" .
 	    "fe00::0 ip6-localnet\n" .
@@ -1009,28 +1082,115 @@ sub extract_data {
 	my $ethdev = Proxmox::Install::Config::get_mngmt_nic();
 	my $cidr = Proxmox::Install::Config::get_cidr();
 	my $gateway = Proxmox::Install::Config::get_gateway();
+	my $vlan = Proxmox::Install::Config::get_mngmt_vlan();
+	my $bonds = Proxmox::Install::Config::get_bonds() // [];
+	my $bridge_ports = Proxmox::Install::Config::get_bridge_ports() // [];
+	my $vlans = Proxmox::Install::Config::get_vlans() // [];
+
+	# the management address is placed directly on the interface only if neither a bridge nor a
+	# VLAN sits on top of it
+	my $address_on_ethdev = !$iso_env->{cfg}->{bridged_network} && !defined($vlan);
+
+	my $configured_ifaces = { $ethdev => 1 };
+	for my $bond (@$bonds) {
+	    my $members = $bond->{members};
+	    for my $member (@$members) {
+		$ifaces .= "iface $member $ntype manual\n\n";
+		$configured_ifaces->{$member} = 1;
+	    }
+
+	    my $has_address = $address_on_ethdev && $bond->{name} eq $ethdev;
+	    my $method = $has_address ? 'static' : 'manual';
+
+	    $ifaces .= "auto $bond->{name}\n" .
+		"iface $bond->{name} $ntype $method\n";
+	    $ifaces .= "\taddress $cidr\n\tgateway $gateway\n" if $has_address;
+	    $ifaces .= "\tbond-slaves " . join(' ', @$members) . "\n" .
+		"\tbond-miimon 100\n" .
+		"\tbond-mode $bond->{mode}\n";
+	    $ifaces .= "\tbond-xmit-hash-policy $bond->{xmit_hash_policy}\n"
+		if defined($bond->{xmit_hash_policy});
+	    $ifaces .= "\n";
+	    $configured_ifaces->{$bond->{name}} = 1;
+	}
+	my $is_bond = grep { $_->{name} eq $ethdev } @$bonds;
 
 	if ($iso_env->{cfg}->{bridged_network}) {
-	    $ifaces .= "iface $ethdev $ntype manual\n";
+	    # bonds used as additional bridge ports have already been configured above
+	    for my $port (grep { !$configured_ifaces->{$_} } @$bridge_ports) {
+		$ifaces .= "iface $port $ntype manual\n\n";
+		$configured_ifaces->{$port} = 1;
+	    }
+	    my $ports = join(' ', $ethdev, @$bridge_ports);
+
+	    $ifaces .= "iface $ethdev $ntype manual\n" if !$is_bond;
+
+	    if (defined($vlan)) {
+		$ifaces .=
+		    "\nauto vmbr0\niface vmbr0 $ntype manual\n" .
+		    "\tbridge-ports $ports\n" .
+		    "\tbridge-stp off\n" .
+		    "\tbridge-fd 0\n" .
+		    "\tbridge-vlan-aware yes\n" .
+		    "\tbridge-vids 2-4094\n" .
+		    "\nauto vmbr0.$vlan\niface vmbr0.$vlan $ntype static\n" .
+		    "\taddress $cidr\n" .
+		    "\tgateway $gateway\n";
+	    } else {
+		$ifaces .=
+		    "\nauto vmbr0\niface vmbr0 $ntype static\n" .
+		    "\taddress $cidr\n" .
+		    "\tgateway $gateway\n" .
+		    "\tbridge-ports $ports\n" .
+		    "\tbridge-stp off\n" .
+		    "\tbridge-fd 0\n";
+	    }
+	} elsif (defined($vlan)) {
+	    $ifaces .= "auto $ethdev\niface $ethdev $ntype manual\n" if !$is_bond;
 
-	    $ifaces .=
-		"\nauto vmbr0\niface vmbr0 $ntype static\n" .
+	    $ifaces .= "\nauto $ethdev.$vlan\n" .
+		"iface $ethdev.$vlan $ntype static\n" .
 		"\taddress $cidr\n" .
-		"\tgateway $gateway\n" .
-		"\tbridge-ports $ethdev\n" .
-		"\tbridge-stp off\n" .
-		"\tbridge-fd 0\n";
-	} else {
+		"\tgateway $gateway\n";
+	} elsif (!$is_bond) {
 	    $ifaces .= "auto $ethdev\n" .
 		"iface $ethdev $ntype static\n" .
 		"\taddress $cidr\n" .
 		"\tgateway $gateway\n";
 	}
 
+	# dual-stack setup, add the IPv6 configuration to the interface carrying the IPv4 address
+	my $cidr6 = Proxmox::Install::Config::get_cidr6();
+	if (defined($cidr6)) {
+	    my $gateway6 = Proxmox::Install::Config::get_gateway6();
+	    my $addr_iface = $iso_env->{cfg}->{bridged_network} ? 'vmbr0' : $ethdev;
+	    $addr_iface .= ".$vlan" if defined($vlan);
+
+	    $ifaces .= "\niface $addr_iface inet6 static\n" .
+		"\taddress $cidr6\n" .
+		"\tgateway $gateway6\n";
+	}
+
+	for my $vlan_iface (@$vlans) {
+	    my ($parent, $id, $vlan_cidr) = $vlan_iface->@{qw(parent id cidr)};
+	    if (!$configured_ifaces->{$parent}) {
+		$ifaces .= "\nauto $parent\niface $parent $ntype manual\n";
+		$configured_ifaces->{$parent} = 1;
+	    }
+
+	    $ifaces .= "\nauto $parent.$id\n";
+	    if (defined($vlan_cidr)) {
+		my $family = $vlan_cidr =~ m/:/ ? 'inet6' : 'inet';
+		$ifaces .= "iface $parent.$id $family static\n\taddress $vlan_cidr\n";
+	    } else {
+		$ifaces .= "iface $parent.$id $ntype manual\n";
+	    }
+	}
+
 	my $ipconf = $run_env->{ipconf};
 	foreach my $iface (sort keys %{$ipconf->{ifaces}}) {
 	    my $name = $ipconf->{ifaces}->{$iface}->{name};
-	    next if $name eq $ethdev;
+	    next if $configured_ifaces->{$name};
 
 	    $ifaces .= "\niface $name $ntype manual\n";
 	}
@@ -1042,7 +1202,9 @@ sub extract_data {
 	# configure dns
 
 	my $dnsserver = Proxmox::Install::Config::get_dns();
-	my $resolvconf = "search $domain\nnameserver $dnsserver\n";
+	my $dns_additional = Proxmox::Install::Config::get_dns_additional() // [];
+	my $resolvconf = "search $domain\n";
+	$resolvconf .= "nameserver $_\n" for ($dnsserver, @$dns_additional);
 	file_write_all("$targetdir/etc/resolv.conf", $resolvconf);
 
 	# configure fstab
@@ -1210,6 +1372,17 @@ _EOD
 	# allow ssh root login
 	syscmd(['sed', '-i', 's/^#\?PermitRootLogin.*/PermitRootLogin yes/', "$targetdir/etc/ssh/sshd_config"]);
 
+	# save the public SSH host keys, e.g. for the auto-installer post-installation webhook
+	my $ssh_host_keys = {};
+	for my $type (qw(rsa ecdsa ed25519)) {
+	    my $key = file_read_firstline("$targetdir/etc/ssh/ssh_host_${type}_key.pub");
+	    $ssh_host_keys->{$type} = $key if defined($key);
+	}
+	file_write_all(
+	    "$iso_env->{locations}->{run}/ssh-host-keys.json",
+	    to_json($ssh_host_keys, { canonical => 1, utf8 => 1 }),
+	);
+
 	if ($iso_env->{product} eq 'pmg') {
 	    # install initial clamav DB
 	    my $srcdir = "${proxmox_cddir}/proxmox/clamav";
@@ -1332,6 +1505,8 @@ _EOD
 	    file_write_all("$targetdir/root/.ssh/authorized_keys", join("\n", @$ssh_keys));
 	}
 
+	setup_first_boot_hook($targetdir);
+
 	my $mailto = Proxmox::Install::Config::get_mailto();
 	if ($iso_env->{product} eq 'pmg') {
 	    # save admin email
@@ -1379,6 +1554,8 @@ _EOD
 	    chroot_chown($targetdir, $user_cfg_fn, user => 'root', group => 'backup');
 	    chroot_chmod($targetdir, $user_cfg_fn, mode => '0640');
 	}
+
+	persist_installer_logs($targetdir);
     };
 
     my $err = $@;
@@ -43,6 +43,9 @@ my sub parse_kernel_cmdline {
 	}
     }
 
+    # answer file location for pre-seeding the TUI and the key for encrypted answer files, not
+    # meant for the installed system
+    $cmdline =~ s/\bproxmox-answer-(?:url|cert-fingerprint|key)=\S+\s?//gi;
     $cmdline =~ s/(?:BOOT_IMAGE|root|ramdisk_size|splash|vga)=\S+\s?//gi;
     $cmdline =~ s/ro|rw|quiet|proxdebug|proxtui|nomodeset//gi;
 
@@ -99,12 +102,23 @@ my sub init_cfg {
 	# FIXME: fix call sites and remove below, it's just an ugly relict of GTK GUI and time
 	# pressure on creating the single source of truth for installation config
 	mngmt_nic_id => undef,
+	mngmt_vlan => undef,
+	bonds => [],
+	bridge_ports => [],
+	vlans => [],
 	hostname => undef,
 	domain => undef,
 	cidr => undef,
 	gateway => undef,
 	dns => undef,
+	# optional IPv6 configuration for dual-stack setups, `cidr` and `gateway` are IPv4 then
+	cidr6 => undef,
+	gateway6 => undef,
+	dns_additional => [],
 	target_cmdline => undef,
+
+	# first-boot hook
+	first_boot => undef,
     };
 
     $initial = parse_kernel_cmdline($initial);
@@ -133,6 +147,17 @@ sub get {
     return defined($k) ? $_cfg->{$k} : $_cfg;
 }
 
+# returns a copy of the config with secrets replaced, e.g. for logging or persisting it
+sub get_redacted {
+    my $cfg = { get()->%* };
+
+    if (my $password = $cfg->{root_password}) {
+	$cfg->{root_password} = { map { $_ => '<redacted>' } keys $password->%* };
+    }
+
+    return $cfg;
+}
+
 sub set_key {
     my ($k, $v) = @_;
     my $cfg = get();
@@ -225,6 +250,18 @@ sub get_mngmt_nic { return get('mngmt_nic'); }
 sub set_mngmt_nic_id { set_key('mngmt_nic_id', $_[0]); }
 sub get_mngmt_nic_id { return get('mngmt_nic_id'); }
 
+sub set_mngmt_vlan { set_key('mngmt_vlan', $_[0]); }
+sub get_mngmt_vlan { return get('mngmt_vlan'); }
+
+sub set_bonds { set_key('bonds', $_[0]); }
+sub get_bonds { return get('bonds'); }
+
+sub set_bridge_ports { set_key('bridge_ports', $_[0]); }
+sub get_bridge_ports { return get('bridge_ports'); }
+
+sub set_vlans { set_key('vlans', $_[0]); }
+sub get_vlans { return get('vlans'); }
+
 sub set_hostname { set_key('hostname', $_[0]); }
 sub get_hostname { return get('hostname'); }
 
@@ -249,6 +286,21 @@ sub get_ip_version { # virtual config
     return Proxmox::Sys::Net::parse_ip_address($ip);
 }
 
+sub set_cidr6 { set_key('cidr6', $_[0]); }
+sub get_cidr6 { return get('cidr6'); }
+
+sub get_ip6_addr { #'virtual config
+    my $cidr = get('cidr6') // return;
+    my ($ip, $mask) = split('/', $cidr);
+    return $ip;
+}
+
+sub set_gateway6 { set_key('gateway6', $_[0]); }
+sub get_gateway6 { return get('gateway6'); }
+
+sub set_dns_additional { set_key('dns_additional', $_[0]); }
+sub get_dns_additional { return get('dns_additional'); }
+
 sub set_gateway { set_key('gateway', $_[0]); }
 sub get_gateway { return get('gateway'); }
 
@@ -258,6 +310,9 @@ sub get_dns { return get('dns'); }
 sub set_target_cmdline { set_key('target_cmdline', $_[0]); }
 sub get_target_cmdline { return get('target_cmdline'); }
 
+sub set_first_boot { set_key('first_boot', $_[0]); }
+sub get_first_boot { return get('first_boot'); }
+
 sub set_existing_storage_auto_rename { set_key('existing_storage_auto_rename', $_[0]); }
 sub get_existing_storage_auto_rename { return get('existing_storage_auto_rename'); }
 
@@ -2,27 +2,57 @@ use anyhow::{bail, format_err, Result};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use glob::Pattern;
 use regex::Regex;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
 use std::{
     collections::BTreeMap,
     fs,
     io::{self, Read},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use proxmox_auto_installer::{
-    answer::Answer,
-    answer::FilterMatch,
+    encryption, installer_logs,
+    signature::{self, SIGNATURE_SUFFIX},
     sysinfo::SysInfo,
+    template,
     utils::{
-        get_matched_udev_indexes, get_nic_list, get_single_udev_index, AutoInstSettings,
-        FetchAnswerFrom, HttpOptions,
+        self, get_nic_list, AutoInstSettings, FetchAnswerFrom, HttpOptions, NfsOptions, TftpOptions,
     },
+    validate,
+};
+
+use proxmox_installer_common::{
+    answer::Answer,
+    answer::{DiskFilter, DiskTake, FilterMatch, NumericFilter},
+    options::Disk,
+    setup::{read_json, LocaleInfo, RuntimeInfo, SetupInfo},
+    udevinfo::{get_matched_disks, get_matched_udev_indexes, get_single_udev_index, UdevInfo},
+    RUNTIME_DIR,
 };
 
+mod serve;
+
 static PROXMOX_ISO_FLAG: &str = "/auto-installer-capable";
 
+// locations of the HTTP client authentication files within the ISO
+static ISO_CLIENT_CERT: &str = "/http-client-cert.pem";
+static ISO_CLIENT_KEY: &str = "/http-client-key.pem";
+static ISO_HMAC_SECRET: &str = "/http-hmac-secret";
+static ISO_CA_CERTS: &str = "/http-ca-certs.pem";
+static ISO_SIGNING_KEYS: &str = "/answer-signing-keys.pub";
+
+/// Files of a hardware snapshot, as found in '/run/proxmox-installer' during the installation.
+const SNAPSHOT_FILES: [&str; 4] = [
+    "iso-info.json",
+    "locales.json",
+    "run-env-info.json",
+    "run-env-udev.json",
+];
+
 /// This tool can be used to prepare a Proxmox installation ISO for automated installations.
 /// Additional uses are to validate the format of an answer file or to test match filters and
 /// print information on the properties to match against for the current hardware.
@@ -35,11 +65,16 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    PrepareIso(CommandPrepareISO),
+    PrepareIso(Box<CommandPrepareISO>),
     ValidateAnswer(CommandValidateAnswer),
+    SignAnswer(CommandSignAnswer),
+    EncryptAnswer(CommandEncryptAnswer),
+    Answer(CommandAnswer),
     DeviceMatch(CommandDeviceMatch),
     DeviceInfo(CommandDeviceInfo),
     SystemInfo(CommandSystemInfo),
+    CaptureHardware(CommandCaptureHardware),
+    Serve(CommandServe),
 }
 
 /// Show device information that can be used for filters
@@ -57,14 +92,23 @@ struct CommandDeviceInfo {
 /// *          Match any number of characters
 /// [a], [0-9] Specifc character or range of characters
 /// [!a]       Negate a specific character of range
+/// !          At the start of the filter, negate the whole filter
 ///
 /// To avoid globbing characters being interpreted by the shell, use single quotes.
 /// Multiple filters can be defined.
 ///
+/// Disks can additionally be filtered by their size and logical block size, and be limited to
+/// the smallest or largest ones, like with the 'filter_size', 'filter_block_size',
+/// 'take_smallest' and 'take_largest' options of the answer file.
+///
 /// Examples:
 /// Match disks against the serial number and device name, both must match:
 ///
 /// proxmox-auto-install-assistant match --filter-match all disk 'ID_SERIAL_SHORT=*2222*' 'DEVNAME=*nvme*'
+///
+/// Match the two largest NVMe disks with at least 800 GiB, which do not carry a ZFS label:
+///
+/// proxmox-auto-install-assistant match --filter-match all --size '>=800' --take-largest 2 disk 'DEVNAME=*nvme*' 'ID_FS_TYPE=!zfs_member'
 #[derive(Args, Debug)]
 #[command(verbatim_doc_comment)]
 struct CommandDeviceMatch {
@@ -78,15 +122,228 @@ struct CommandDeviceMatch {
     /// Defines if any filter or all filters must match.
     #[arg(long, value_enum, default_value_t=FilterMatch::Any)]
     filter_match: FilterMatch,
+
+    /// Only match disks of the given size in GiB, e.g. '>=800'
+    #[arg(long, value_parser = parse_numeric_filter)]
+    size: Option<NumericFilter>,
+
+    /// Only match disks with the given logical block size in bytes, e.g. '4096'
+    #[arg(long, value_parser = parse_numeric_filter)]
+    block_size: Option<NumericFilter>,
+
+    /// Only use the given number of the smallest matching disks
+    #[arg(long, conflicts_with = "take_largest")]
+    take_smallest: Option<NonZeroUsize>,
+
+    /// Only use the given number of the largest matching disks
+    #[arg(long)]
+    take_largest: Option<NonZeroUsize>,
 }
 
 /// Validate if an answer file is formatted correctly.
+///
+/// All problems found are reported, along with the key and the position in the answer file.
+/// Besides the format, the answer is also checked for problems which would otherwise only show
+/// up during the installation, like RAID levels not matching the number of disks in 'disk_list'
+/// or unknown udev properties in filters.
+///
+/// Country, keyboard layout and timezone can only be checked if the 'locales.json' from the
+/// installation environment is provided via '--locales'.
 #[derive(Args, Debug)]
 struct CommandValidateAnswer {
     /// Path to the answer file
     path: PathBuf,
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+    /// Output format, 'json' prints a machine-readable report of all problems found
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Pretty)]
+    format: OutputFormat,
+    /// Path to the 'locales.json' of the installation environment, to verify locale settings
+    #[arg(long)]
+    locales: Option<PathBuf>,
+}
+
+/// Sign an answer file, for ISOs prepared with '--signing-key'.
+///
+/// The signature is written to the answer file path with a '.sig' suffix, e.g. 'answer.toml.sig',
+/// where it is looked up for the 'partition', 'tftp' and 'nfs' modes. For the 'http' mode, the
+/// server must send it in the 'X-Proxmox-Answer-Signature' header. A lookup table for an answer
+/// file template must be signed the same way.
+///
+/// The key is an Ed25519 private key in the PEM format, as generated with:
+/// 'openssl genpkey -algorithm ed25519 -out answer-signing.key'
+///
+/// The public key to pass to '--signing-key' is then created with:
+/// 'openssl pkey -in answer-signing.key -pubout -out answer-signing.pub'
+#[derive(Args, Debug)]
+struct CommandSignAnswer {
+    /// Path to the answer file, or lookup table
+    path: PathBuf,
+
+    /// Path to the private key to sign with
+    #[arg(long)]
+    key: PathBuf,
+
+    /// Path to write the signature to, defaults to the answer file path with a '.sig' suffix
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+/// Encrypt an answer file with a passphrase.
+///
+/// The encrypted answer file is used in place of the plain one, e.g. as 'answer.toml' on the
+/// 'proxmox-ais' partition or with the '--answer-file' parameter of 'prepare-iso'. A lookup table
+/// for an answer file template can be encrypted the same way. To also sign it, sign the encrypted
+/// file.
+///
+/// During the installation, the passphrase is taken from the 'proxmox-answer-key=<passphrase>'
+/// kernel command line parameter, from the file 'answer.key' on a partition labeled
+/// 'proxmox-key', or else asked for on the console. The logs of a failed installation are then
+/// not stored on the 'proxmox-ais' partition, only uploaded if configured.
+#[derive(Args, Debug)]
+struct CommandEncryptAnswer {
+    /// Path to the answer file, or lookup table
+    path: PathBuf,
+
+    /// Path to write the encrypted answer file to, defaults to the answer file path with an
+    /// '.enc' suffix
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Read the passphrase from the file instead of asking for it, e.g. the 'answer.key' for the
+    /// key partition
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+}
+
+/// Serve answer files via HTTPS, for the 'http' fetch-from mode.
+///
+/// The installer posts its system information, the answer file is then chosen from the answer
+/// directory by the rules file, a TOML file with an optional 'default' answer file and a list of
+/// '[[rule]]' sections. Each rule names the 'answer' file and at least one of the DMI system
+/// 'serial', the DMI system 'uuid' or a 'mac' address of the host:
+///
+/// default = "default.toml"{n}
+/// {n}
+/// [[rule]]{n}
+/// serial = "CZ1234"{n}
+/// answer = "pve1.toml"
+///
+/// Templates are rendered for the host, with the 'answer-lookup.csv' from the answer directory if
+/// present. Each answer file is validated before it is served. Answer files with a signature file
+/// next to them, e.g. 'pve1.toml.sig', are served unchanged with the signature. Signed templates
+/// and encrypted answer files cannot be validated and are served as is. Answer files are read on
+/// every request, changes to the rules need a restart.
+///
+/// The installer should be authenticated, as otherwise any client can fetch the answer file of a
+/// host by claiming its serial number or MAC address. With '--hmac-secret', only requests signed
+/// with the same secret as given to 'prepare-iso --hmac-secret' are answered. With '--client-ca',
+/// only clients with a certificate issued by one of the given CAs are accepted, see 'prepare-iso
+/// --client-cert'.
+///
+/// On start, the fingerprint of the certificate is printed, to pass to 'prepare-iso
+/// --cert-fingerprint'. A self-signed certificate can be created with:
+/// 'openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 3650
+/// -subj /CN=answer-server -keyout key.pem -out cert.pem'
+#[derive(Args, Debug)]
+struct CommandServe {
+    /// Directory containing the answer files
+    dir: PathBuf,
+
+    /// Path to the rules file, defaults to 'rules.toml' in the answer directory
+    #[arg(long)]
+    rules: Option<PathBuf>,
+
+    /// Address and port to listen on
+    #[arg(long, default_value = "0.0.0.0:8443")]
+    listen: std::net::SocketAddr,
+
+    /// Path to the PEM-encoded TLS certificate
+    #[arg(long)]
+    cert: PathBuf,
+
+    /// Path to the PEM-encoded private key of the certificate
+    #[arg(long)]
+    key: PathBuf,
+
+    /// Path to the shared secret requests must be signed with
+    #[arg(long)]
+    hmac_secret: Option<PathBuf>,
+
+    /// Path to the PEM-encoded CA certificates client certificates must be issued by
+    #[arg(long)]
+    client_ca: Option<PathBuf>,
+}
+
+/// Work with answer files
+#[derive(Args, Debug)]
+struct CommandAnswer {
+    #[command(subcommand)]
+    command: AnswerCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum AnswerCommands {
+    DryRun(CommandAnswerDryRun),
+    Render(CommandAnswerRender),
+}
+
+/// Render an answer file template for a host.
+///
+/// Templates are marked by a top-level 'template = true' before the first section, which is removed
+/// on rendering. Placeholders in the form of '{{ name }}' are replaced by the system information of
+/// the host, e.g. '{{ dmi.system.serial }}' or '{{ mac.eno1 }}', see the 'system-info' command.
+/// Additional per-host values can be provided with a lookup table, a CSV file whose first column
+/// names the variable to identify the host by. The other columns are available as
+/// '{{ host.<column> }}'. Values are escaped to be used within double-quoted strings.
+///
+/// During the installation, the lookup table is read from 'answer-lookup.csv' next to the answer
+/// file, on the ISO or the partition.
+#[derive(Args, Debug)]
+struct CommandAnswerRender {
+    /// Path to the answer file template
+    path: PathBuf,
+
+    /// Path to the lookup table
+    #[arg(long)]
+    lookup: Option<PathBuf>,
+
+    /// Path to the system information of the host, e.g. the 'sysinfo.json' of a hardware
+    /// snapshot. Defaults to the current system.
+    #[arg(long)]
+    sysinfo: Option<PathBuf>,
+}
+
+/// Parse an answer file against a hardware snapshot, without installing anything.
+///
+/// Prints the configuration the low-level installer would get for the hardware in the snapshot,
+/// for example which disks and NIC were selected by the filters of the answer file. The root
+/// password is redacted.
+///
+/// The snapshot is either a tar archive or a directory containing the files 'iso-info.json',
+/// 'locales.json', 'run-env-info.json' and 'run-env-udev.json', as found in
+/// '/run/proxmox-installer' during the installation. Snapshots can be created with the
+/// 'capture-hardware' command.
+///
+/// 'iso-info.json' and 'locales.json' depend on the installation ISO, not the hardware. If the
+/// snapshot was not captured in the installation environment, they must be provided with the
+/// '--iso-info' and '--locales' parameters.
+#[derive(Args, Debug)]
+struct CommandAnswerDryRun {
+    /// Path to the answer file
+    path: PathBuf,
+
+    /// Path to the hardware snapshot, a tar archive or a directory
+    #[arg(long)]
+    snapshot: PathBuf,
+
+    /// Path to the 'iso-info.json' of the installation environment, overrides the snapshot
+    #[arg(long)]
+    iso_info: Option<PathBuf>,
+
+    /// Path to the 'locales.json' of the installation environment, overrides the snapshot
+    #[arg(long)]
+    locales: Option<PathBuf>,
 }
 
 /// Prepare an ISO for automated installation.
@@ -96,7 +353,13 @@ struct CommandValidateAnswer {
 /// * integrated into the ISO itself ('iso'){n}
 /// * present on a partition / file-system with the label 'PROXMOX-AIS' (Proxmox
 /// Automated Installer Source) ('partition'){n}
-/// * requested via an HTTP Post request ('http').
+/// * requested via an HTTP Post request ('http'){n}
+/// * fetched from a TFTP server ('tftp'){n}
+/// * read from an NFS export ('nfs').
+///
+/// Multiple sources can be given, separated by commas or by repeating the parameter, e.g.
+/// '--fetch-from partition,http,iso'. They are tried in the given order, the first one providing
+/// a valid answer file is used.
 ///
 /// The URL for the HTTP mode can be defined for the ISO with the '--url' argument. If not present,
 /// it will try to get a URL from a DHCP option (250, TXT) or by querying a DNS TXT record for the
@@ -110,6 +373,45 @@ struct CommandValidateAnswer {
 /// to retrieve the URL. For example, the DNS TXT record for the fingerprint will only be used, if
 /// no one was configured with the '--cert-fingerprint' parameter and if the URL was retrieved via
 /// the DNS TXT record.
+///
+/// Instead of pinning the certificate, the server can be verified against one or more CA
+/// certificates placed in the ISO with '--ca-certs', optionally requiring the certificate to be
+/// valid for the name given with '--server-name'. Similar to the certificate fingerprint, the
+/// SHA256 fingerprint of a CA can be provided via a DHCP option (253, TXT) or a DNS TXT record at
+/// 'proxmox-auto-installer-ca-fingerprint.{search domain}'. The server must then send this CA
+/// certificate as part of its certificate chain.
+///
+/// Before fetching the answer file via HTTP, the installer waits for a network link and a DHCP
+/// lease, failed attempts are retried with exponential backoff. This can be tuned with the
+/// '--network-wait', '--http-retries', '--http-retry-delay', '--http-timeout' and
+/// '--http-deadline' arguments.
+///
+/// To let the answer server authenticate the installer, a client certificate and key can be
+/// placed in the ISO with '--client-cert' and '--client-key', to be used for mutual TLS. With
+/// '--hmac-secret', each request is signed with the shared secret from the given file. The
+/// hex-encoded HMAC-SHA256 over the UNIX timestamp, a newline and the request body is sent as
+/// 'X-Proxmox-Signature: sha256=<signature>' header, the timestamp as 'X-Proxmox-Timestamp'.
+/// The 'serve' command can check both, with its '--client-ca' and '--hmac-secret' arguments.
+///
+/// For the TFTP and NFS modes, the server can be defined with the '--tftp-server' and
+/// '--nfs-server' arguments, otherwise the boot server provided via DHCP is used, i.e. the TFTP
+/// server name (option 66) or the 'next-server' address. The answer file is looked up by the MAC
+/// address of each network interface, e.g. 'aa-bb-cc-dd-ee-ff.toml', then by the DMI system UUID,
+/// e.g. '4c4c4544-0042-3010-8057-b4c04f4e4a32.toml', falling back to 'answer.toml'. The directory
+/// on the TFTP server is set with '--tftp-path', the NFS export with '--nfs-export'.
+///
+/// Answer files can be encrypted with the 'encrypt-answer' command, for any fetch-from mode.
+///
+/// With '--signing-key', answer files are only accepted if they are signed by one of the given
+/// keys, see the 'sign-answer' command. This applies to all modes except 'iso', as the answer file
+/// in the ISO is trusted like the keys themselves.
+///
+/// An executable to be run once on the first boot of the installed system can be placed in the ISO
+/// with the '--on-first-boot' parameter. It is used if the answer file sets the first-boot hook
+/// source to 'from-iso'.
+///
+/// The answer file can be a template with per-host placeholders, see the 'answer render' command.
+/// The lookup table for it is included with the '--answer-lookup' parameter.
 #[derive(Args, Debug)]
 struct CommandPrepareISO {
     /// Path to the source ISO to prepare
@@ -120,15 +422,20 @@ struct CommandPrepareISO {
     #[arg(long)]
     output: Option<PathBuf>,
 
-    /// Where the automatic installer should fetch the answer file from.
-    #[arg(long, value_enum)]
-    fetch_from: FetchAnswerFrom,
+    /// Where the automatic installer should fetch the answer file from, in order of preference.
+    #[arg(long, value_enum, value_delimiter = ',', required = true)]
+    fetch_from: Vec<FetchAnswerFrom>,
 
     /// Include the specified answer file in the ISO. Requires the '--fetch-from'  parameter
-    /// to be set to 'iso'.
+    /// to include 'iso'.
     #[arg(long)]
     answer_file: Option<PathBuf>,
 
+    /// Include the specified lookup table for answer file templates in the ISO. Requires the
+    /// '--answer-file' parameter.
+    #[arg(long)]
+    answer_lookup: Option<PathBuf>,
+
     /// Specify URL for fetching the answer file via HTTP
     #[arg(long)]
     url: Option<String>,
@@ -137,10 +444,82 @@ struct CommandPrepareISO {
     #[arg(long)]
     cert_fingerprint: Option<String>,
 
+    /// Number of retries if fetching the answer file via HTTP fails. [default: 3]
+    #[arg(long)]
+    http_retries: Option<u32>,
+
+    /// Delay before the first retry in seconds, doubled for each following retry, up to 60
+    /// seconds. [default: 5]
+    #[arg(long)]
+    http_retry_delay: Option<u64>,
+
+    /// Timeout for a single HTTP request in seconds. [default: 60]
+    #[arg(long)]
+    http_timeout: Option<u64>,
+
+    /// Time limit in seconds for fetching the answer file via HTTP, including all retries.
+    #[arg(long)]
+    http_deadline: Option<u64>,
+
+    /// Time to wait for a network link and a DHCP lease in seconds before fetching the answer
+    /// file via HTTP, 0 to disable. [default: 60]
+    #[arg(long)]
+    network_wait: Option<u64>,
+
+    /// Public key trusted to sign answer files, in the PEM format. Can be given multiple times. If
+    /// set, only answer files with a valid signature are accepted, see the 'sign-answer' command.
+    #[arg(long)]
+    signing_key: Vec<PathBuf>,
+
+    /// PEM-encoded CA certificates to include in the ISO, to verify the answer server against
+    /// instead of the CAs trusted by the live system.
+    #[arg(long)]
+    ca_certs: Option<PathBuf>,
+
+    /// Name the certificate of the answer server must be valid for, instead of the host of the
+    /// URL.
+    #[arg(long)]
+    server_name: Option<String>,
+
+    /// PEM-encoded client certificate to include in the ISO, to authenticate against the answer
+    /// server via mutual TLS. Requires '--client-key'.
+    #[arg(long)]
+    client_cert: Option<PathBuf>,
+
+    /// PEM-encoded private key of the client certificate to include in the ISO.
+    #[arg(long)]
+    client_key: Option<PathBuf>,
+
+    /// File containing a shared secret to include in the ISO, to sign the HTTP requests with.
+    #[arg(long)]
+    hmac_secret: Option<PathBuf>,
+
+    /// TFTP server to fetch the answer file from, optionally with port
+    #[arg(long)]
+    tftp_server: Option<String>,
+
+    /// Directory on the TFTP server containing the answer files
+    #[arg(long)]
+    tftp_path: Option<String>,
+
+    /// NFS server to fetch the answer file from
+    #[arg(long)]
+    nfs_server: Option<String>,
+
+    /// Exported directory on the NFS server containing the answer files, required for the 'nfs'
+    /// mode
+    #[arg(long)]
+    nfs_export: Option<String>,
+
     /// Staging directory to use for preparing the new ISO file. Defaults to the directory of the
     /// input ISO file.
     #[arg(long)]
     tmp: Option<String>,
+
+    /// Executable file to include in the ISO, to be run once on the first boot of the installed
+    /// system. Requires 'source = "from-iso"' in the '[first-boot]' section of the answer file.
+    #[arg(long)]
+    on_first_boot: Option<PathBuf>,
 }
 
 /// Show the system information that can be used to identify a host.
@@ -151,6 +530,26 @@ struct CommandPrepareISO {
 #[derive(Args, Debug)]
 struct CommandSystemInfo {}
 
+/// Capture a snapshot of the hardware of this system.
+///
+/// The snapshot is written as tar archive, containing the same information the auto-installer
+/// reads from '/run/proxmox-installer' during the installation:{n}
+/// * 'run-env-info.json': disks, memory, boot type, CPU virtualization support and network{n}
+/// * 'run-env-udev.json': udev properties of all disks and network interfaces{n}
+/// * 'sysinfo.json': the system information, see the 'system-info' command
+///
+/// 'iso-info.json' and 'locales.json' depend on the installation ISO and are only included if
+/// the command is run in the installation environment.
+///
+/// The snapshot can be used with the 'answer dry-run' command to test answer files. Once
+/// extracted, it can also be used as test fixture, like the ones in
+/// 'proxmox-auto-installer/tests/resources'.
+#[derive(Args, Debug)]
+struct CommandCaptureHardware {
+    /// Path to write the tar archive to
+    output: PathBuf,
+}
+
 #[derive(Args, Debug)]
 struct GlobalOpts {
     /// Output format
@@ -177,6 +576,60 @@ enum OutputFormat {
     Json,
 }
 
+/// Information about the installation environment, as read by the auto-installer.
+struct HardwareSnapshot {
+    setup_info: Option<SetupInfo>,
+    locales: Option<LocaleInfo>,
+    runtime_info: RuntimeInfo,
+    udev_info: UdevInfo,
+}
+
+/// A block device which can be used as installation target.
+struct BlockDevice {
+    /// Name of the device in '/sys/block'
+    sys_name: String,
+    /// Device name as reported by udev
+    name: String,
+    udev_props: BTreeMap<String, String>,
+}
+
+/// Size, model and logical block size of a disk, as read by the low-level installer.
+struct DiskDetails {
+    /// Size in sectors of 512 bytes
+    sectors: u64,
+    model: String,
+    logical_bsize: Option<usize>,
+}
+
+impl BlockDevice {
+    fn sys_path(&self) -> PathBuf {
+        PathBuf::from("/sys/block").join(&self.sys_name)
+    }
+
+    fn dev_path(&self) -> String {
+        match self.udev_props.get("DEVNAME") {
+            Some(devname) => devname.clone(),
+            None => format!("/dev/{}", self.name),
+        }
+    }
+
+    fn details(&self) -> Option<DiskDetails> {
+        let sys_path = self.sys_path();
+        let model = fs::read_to_string(sys_path.join("device/model"))
+            .unwrap_or_default()
+            .trim()
+            .chars()
+            .take(30)
+            .collect();
+
+        Some(DiskDetails {
+            sectors: read_sys_value(&sys_path.join("size"))?,
+            model,
+            logical_bsize: read_sys_value(&sys_path.join("queue/logical_block_size")),
+        })
+    }
+}
+
 #[derive(Serialize)]
 struct Devs {
     disks: Option<BTreeMap<String, BTreeMap<String, String>>>,
@@ -188,9 +641,17 @@ fn main() {
     let res = match &args.command {
         Commands::PrepareIso(args) => prepare_iso(args),
         Commands::ValidateAnswer(args) => validate_answer(args),
+        Commands::SignAnswer(args) => sign_answer(args),
+        Commands::EncryptAnswer(args) => encrypt_answer(args),
+        Commands::Answer(args) => match &args.command {
+            AnswerCommands::DryRun(args) => dry_run_answer(args),
+            AnswerCommands::Render(args) => render_answer(args),
+        },
         Commands::DeviceInfo(args) => info(args),
         Commands::DeviceMatch(args) => match_filter(args),
         Commands::SystemInfo(args) => show_system_info(args),
+        Commands::CaptureHardware(args) => capture_hardware(args),
+        Commands::Serve(args) => serve_answers(args),
     };
     if let Err(err) = res {
         eprintln!("{err}");
@@ -244,10 +705,30 @@ fn match_filter(args: &CommandDeviceMatch) -> Result<()> {
     }
 
     // align return values
+    let take = match (args.take_smallest, args.take_largest) {
+        (Some(count), _) => Some(DiskTake::Smallest(count.get())),
+        (None, Some(count)) => Some(DiskTake::Largest(count.get())),
+        (None, None) => None,
+    };
+    let disk_options = args.size.is_some() || args.block_size.is_some() || take.is_some();
+
     let result = match args.r#type {
+        Devicetype::Disk if disk_options => {
+            let filter = DiskFilter {
+                udev: filters,
+                filter_match: args.filter_match.clone(),
+                size: args.size,
+                block_size: args.block_size,
+                take,
+            };
+            get_matched_disks(&filter, &devs, &get_disk_list()?)
+        }
         Devicetype::Disk => {
             get_matched_udev_indexes(&filters, &devs, args.filter_match == FilterMatch::All)
         }
+        Devicetype::Network if disk_options => {
+            bail!("Size, block size and take options are only supported for disks.")
+        }
         Devicetype::Network => get_single_udev_index(&filters, &devs).map(|r| vec![r]),
     };
 
@@ -259,13 +740,489 @@ fn match_filter(args: &CommandDeviceMatch) -> Result<()> {
 }
 
 fn validate_answer(args: &CommandValidateAnswer) -> Result<()> {
-    let answer = parse_answer(&args.path)?;
+    let contents = fs::read_to_string(&args.path)
+        .map_err(|err| format_err!("Reading answer file {:?} failed: {err}", args.path))?;
+
+    let locales: Option<LocaleInfo> = match &args.locales {
+        Some(path) => Some(
+            read_json(path).map_err(|err| format_err!("Reading locales {path:?} failed: {err}"))?,
+        ),
+        None => None,
+    };
+
+    let (answer, report) = validate::validate_answer(&contents, locales.as_ref());
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Pretty => {
+            for problem in &report.problems {
+                println!("{problem}");
+            }
+            if report.valid {
+                println!("The answer file was validated successfully, no errors found!");
+            }
+        }
+    }
+
     if args.debug {
-        println!("Parsed data from answer file:\n{:#?}", answer);
+        if let Some(answer) = answer {
+            println!("Parsed data from answer file:\n{:#?}", answer);
+        }
+    }
+
+    if !report.valid {
+        bail!("Validation of answer file {:?} failed.", args.path);
+    }
+    Ok(())
+}
+
+fn dry_run_answer(args: &CommandAnswerDryRun) -> Result<()> {
+    let contents = fs::read_to_string(&args.path)
+        .map_err(|err| format_err!("Reading answer file {:?} failed: {err}", args.path))?;
+    let answer: Answer =
+        toml::from_str(&contents).map_err(|err| format_err!("Error parsing answer file: {err}"))?;
+
+    let snapshot = read_snapshot(&args.snapshot)?;
+
+    let setup_info: SetupInfo = match (&args.iso_info, snapshot.setup_info) {
+        (Some(path), _) => {
+            read_json(path).map_err(|err| format_err!("Reading ISO info {path:?} failed: {err}"))?
+        }
+        (None, Some(setup_info)) => setup_info,
+        (None, None) => {
+            bail!("Hardware snapshot does not contain 'iso-info.json', use '--iso-info' instead.")
+        }
+    };
+    let locales: LocaleInfo = match (&args.locales, snapshot.locales) {
+        (Some(path), _) => {
+            read_json(path).map_err(|err| format_err!("Reading locales {path:?} failed: {err}"))?
+        }
+        (None, Some(locales)) => locales,
+        (None, None) => {
+            bail!("Hardware snapshot does not contain 'locales.json', use '--locales' instead.")
+        }
+    };
+
+    let config = utils::parse_answer(
+        &answer,
+        &snapshot.udev_info,
+        &snapshot.runtime_info,
+        &locales,
+        &setup_info,
+    )
+    .map_err(|err| format_err!("Answer file is not applicable to the hardware snapshot: {err}"))?;
+
+    let config = installer_logs::redact_config(&serde_json::to_string(&config)?)?;
+    println!("{config}");
+    Ok(())
+}
+
+fn sign_answer(args: &CommandSignAnswer) -> Result<()> {
+    let contents = fs::read_to_string(&args.path)
+        .map_err(|err| format_err!("Reading answer file {:?} failed: {err}", args.path))?;
+    let key = fs::read_to_string(&args.key)
+        .map_err(|err| format_err!("Reading private key {:?} failed: {err}", args.key))?;
+
+    // lookup tables can only be checked together with the template
+    let is_lookup_table = args.path.extension().is_some_and(|ext| ext == "csv");
+    if !is_lookup_table {
+        check_answer_file(&args.path)?;
+    }
+
+    let (sig, public_key) = signature::sign(contents.as_bytes(), &key)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => {
+            let mut path = args.path.clone().into_os_string();
+            path.push(SIGNATURE_SUFFIX);
+            PathBuf::from(path)
+        }
+    };
+    fs::write(&output, format!("{sig}\n"))
+        .map_err(|err| format_err!("Writing signature {output:?} failed: {err}"))?;
+
+    println!("Signature written to {output:?}, signed with public key:");
+    print!("{}", public_key.to_pem());
+    Ok(())
+}
+
+fn encrypt_answer(args: &CommandEncryptAnswer) -> Result<()> {
+    let contents = fs::read_to_string(&args.path)
+        .map_err(|err| format_err!("Reading answer file {:?} failed: {err}", args.path))?;
+    if encryption::is_encrypted(&contents) {
+        bail!("The answer file {:?} is already encrypted.", args.path);
+    }
+
+    // lookup tables can only be checked together with the template
+    let is_lookup_table = args.path.extension().is_some_and(|ext| ext == "csv");
+    if !is_lookup_table {
+        check_answer_file(&args.path)?;
+    }
+
+    let passphrase = match &args.key_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format_err!("Reading key file {path:?} failed: {err}"))?
+            .trim_end_matches(['\n', '\r'])
+            .to_owned(),
+        None => {
+            let passphrase = encryption::read_passphrase("Passphrase: ")?;
+            if encryption::read_passphrase("Repeat passphrase: ")? != passphrase {
+                bail!("The passphrases do not match.");
+            }
+            passphrase
+        }
+    };
+
+    let encrypted = encryption::encrypt(&contents, &passphrase)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => {
+            let mut path = args.path.clone().into_os_string();
+            path.push(".enc");
+            PathBuf::from(path)
+        }
+    };
+    fs::write(&output, encrypted)
+        .map_err(|err| format_err!("Writing encrypted answer file {output:?} failed: {err}"))?;
+
+    println!("Encrypted answer file written to {output:?}.");
+    Ok(())
+}
+
+fn serve_answers(args: &CommandServe) -> Result<()> {
+    let rules = match &args.rules {
+        Some(rules) => rules.clone(),
+        None => args.dir.join("rules.toml"),
+    };
+    serve::AnswerServer::new(&args.dir, &rules, args.hmac_secret.as_deref())?.run(
+        args.listen,
+        &args.cert,
+        &args.key,
+        args.client_ca.as_deref(),
+    )
+}
+
+fn render_answer(args: &CommandAnswerRender) -> Result<()> {
+    let contents = fs::read_to_string(&args.path)
+        .map_err(|err| format_err!("Reading answer file {:?} failed: {err}", args.path))?;
+    if !template::is_template(&contents) {
+        bail!("The answer file is not marked as template with 'template = true'.");
+    }
+
+    let mut variables = match &args.sysinfo {
+        Some(path) => {
+            let sysinfo: Value = read_json(path)
+                .map_err(|err| format_err!("Reading system info {path:?} failed: {err}"))?;
+            template::variables_from_sysinfo(&sysinfo)
+        }
+        None => template::system_variables()?,
+    };
+    if let Some(path) = &args.lookup {
+        let table = fs::read_to_string(path)
+            .map_err(|err| format_err!("Reading lookup table {path:?} failed: {err}"))?;
+        template::apply_lookup_table(&table, &mut variables)?;
     }
+
+    print!("{}", template::render(&contents, &variables)?);
     Ok(())
 }
 
+fn read_snapshot(path: &Path) -> Result<HardwareSnapshot> {
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+
+    if path.is_dir() {
+        for name in SNAPSHOT_FILES {
+            let file = path.join(name);
+            if file.exists() {
+                files.insert(name.to_owned(), fs::read(&file)?);
+            }
+        }
+    } else {
+        let file = fs::File::open(path)
+            .map_err(|err| format_err!("Opening hardware snapshot {path:?} failed: {err}"))?;
+        let mut archive = tar::Archive::new(file);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = match entry.path()?.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            if SNAPSHOT_FILES.contains(&name.as_str()) {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                files.insert(name, data);
+            }
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(
+        files: &BTreeMap<String, Vec<u8>>,
+        name: &str,
+    ) -> Result<Option<T>> {
+        match files.get(name) {
+            Some(data) => serde_json::from_slice(data).map(Some).map_err(|err| {
+                format_err!("Parsing '{name}' of the hardware snapshot failed: {err}")
+            }),
+            None => Ok(None),
+        }
+    }
+
+    let mut runtime_info: RuntimeInfo = parse(&files, "run-env-info.json")?
+        .ok_or_else(|| format_err!("Hardware snapshot does not contain 'run-env-info.json'"))?;
+    runtime_info.disks.sort();
+    if runtime_info.disks.is_empty() {
+        bail!("Hardware snapshot does not contain any supported hard disks.");
+    }
+
+    Ok(HardwareSnapshot {
+        setup_info: parse(&files, "iso-info.json")?,
+        locales: parse(&files, "locales.json")?,
+        runtime_info,
+        udev_info: parse(&files, "run-env-udev.json")?
+            .ok_or_else(|| format_err!("Hardware snapshot does not contain 'run-env-udev.json'"))?,
+    })
+}
+
+/// The hardware data written by 'capture-hardware'.
+struct CapturedHardware {
+    /// Disks usable as installation target, in the order of the low-level installer
+    disks: Vec<(BlockDevice, DiskDetails)>,
+    efi: bool,
+    hvm_supported: bool,
+    kernel_cmdline: String,
+    network: Value,
+    /// Total memory in MiB
+    total_memory: u64,
+    nics: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl CapturedHardware {
+    fn collect() -> Result<Self> {
+        let disks = get_block_devices()?
+            .into_iter()
+            .filter_map(|disk| {
+                let details = disk.details()?;
+                Some((disk, details))
+            })
+            .collect();
+
+        Ok(Self {
+            disks,
+            efi: Path::new("/sys/firmware/efi").exists(),
+            hvm_supported: get_cpu_hvm_support()?,
+            kernel_cmdline: fs::read_to_string("/proc/cmdline")?.trim().to_owned(),
+            network: get_network_info()?,
+            total_memory: get_total_memory()?,
+            nics: get_nics()?,
+        })
+    }
+
+    /// Returns the contents of 'run-env-info.json' and 'run-env-udev.json', in the same format
+    /// as written by the low-level installer.
+    fn to_run_env(&self) -> (Value, Value) {
+        let mut disk_list = vec![];
+        let mut disk_props = BTreeMap::new();
+        for (index, (disk, details)) in self.disks.iter().enumerate() {
+            disk_list.push(json!([
+                index,
+                disk.dev_path(),
+                details.sectors,
+                details.model,
+                details.logical_bsize,
+                disk.sys_path(),
+            ]));
+            disk_props.insert(index.to_string(), &disk.udev_props);
+        }
+
+        let run_env = json!({
+            "boot_type": if self.efi { "efi" } else { "bios" },
+            "disks": disk_list,
+            "hvm_supported": self.hvm_supported as u8,
+            "kernel_cmdline": self.kernel_cmdline,
+            "network": self.network,
+            "total_memory": self.total_memory,
+        });
+        let udev = json!({
+            "disks": disk_props,
+            "nics": self.nics,
+        });
+
+        (run_env, udev)
+    }
+}
+
+fn capture_hardware(args: &CommandCaptureHardware) -> Result<()> {
+    let (run_env, udev) = CapturedHardware::collect()?.to_run_env();
+    let sysinfo = SysInfo::get().map_err(|err| format_err!("Error fetching system info: {err}"))?;
+
+    let file = fs::File::create(&args.output)
+        .map_err(|err| format_err!("Creating {:?} failed: {err}", args.output))?;
+    let mut archive = tar::Builder::new(file);
+
+    append_to_archive(
+        &mut archive,
+        "run-env-info.json",
+        &serde_json::to_vec(&run_env)?,
+    )?;
+    append_to_archive(
+        &mut archive,
+        "run-env-udev.json",
+        &serde_json::to_vec(&udev)?,
+    )?;
+    append_to_archive(
+        &mut archive,
+        "sysinfo.json",
+        &serde_json::to_vec_pretty(&sysinfo)?,
+    )?;
+
+    for name in ["iso-info.json", "locales.json"] {
+        match fs::read(PathBuf::from(RUNTIME_DIR).join(name)) {
+            Ok(data) => append_to_archive(&mut archive, name, &data)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                println!("Not running in the installation environment, skipping '{name}'.")
+            }
+            Err(err) => bail!("Reading '{name}' failed: {err}"),
+        }
+    }
+    archive.finish()?;
+
+    println!("Hardware snapshot written to {:?}.", args.output);
+    Ok(())
+}
+
+fn append_to_archive(archive: &mut tar::Builder<fs::File>, name: &str, data: &[u8]) -> Result<()> {
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+fn read_sys_value<T: std::str::FromStr>(path: &Path) -> Option<T> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Returns the total memory in MiB, like the low-level installer does.
+fn get_total_memory() -> Result<u64> {
+    let re_total = Regex::new(r"(?m)^MemTotal:\s+(\d+)\s*kB")?;
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+
+    Ok(re_total
+        .captures(&meminfo)
+        .and_then(|caps| caps[1].parse::<u64>().ok())
+        .map(|kib| kib / 1024)
+        .unwrap_or(512))
+}
+
+fn get_cpu_hvm_support() -> Result<bool> {
+    let re_flags = Regex::new(r"(?m)^flags\s*:.*(vmx|svm)")?;
+    Ok(re_flags.is_match(&fs::read_to_string("/proc/cpuinfo")?))
+}
+
+/// Collects interfaces, default routes and DNS servers, in the format of the low-level installer.
+fn get_network_info() -> Result<Value> {
+    Ok(network_info(
+        run_ip_json(&["address", "show"])?,
+        run_ip_json(&["-4", "route", "show"])?,
+        run_ip_json(&["-6", "route", "show"])?,
+        &fs::read_to_string("/etc/resolv.conf").unwrap_or_default(),
+    ))
+}
+
+/// Builds the network info from the output of 'ip --json' for addresses, IPv4 and IPv6 routes,
+/// and the contents of '/etc/resolv.conf'.
+fn network_info(
+    links: Vec<Value>,
+    routes4: Vec<Value>,
+    routes6: Vec<Value>,
+    resolv_conf: &str,
+) -> Value {
+    let mut interfaces = serde_json::Map::new();
+    for link in links {
+        let name = match link["ifname"].as_str() {
+            Some(name) if name != "lo" => name,
+            _ => continue,
+        };
+        let state = link["operstate"]
+            .as_str()
+            .unwrap_or_default()
+            .to_uppercase();
+
+        let mut addresses = vec![];
+        if state == "UP" {
+            for addr in link["addr_info"].as_array().into_iter().flatten() {
+                if addr["scope"] == "link" {
+                    continue;
+                }
+                addresses.push(json!({
+                    "family": addr["family"],
+                    "address": addr["local"],
+                    "prefix": addr["prefixlen"],
+                }));
+            }
+        }
+
+        let mut interface = json!({
+            "index": link["ifindex"],
+            "name": name,
+            "mac": link["address"],
+            "state": state,
+        });
+        if !addresses.is_empty() {
+            interface["addresses"] = Value::Array(addresses);
+        }
+        interfaces.insert(name.to_owned(), interface);
+    }
+
+    let mut routes = serde_json::Map::new();
+    for (family_routes, key) in [(routes4, "gateway4"), (routes6, "gateway6")] {
+        let default = family_routes
+            .into_iter()
+            .find(|route| route["dst"] == "default");
+        if let Some(route) = default {
+            routes.insert(
+                key.to_owned(),
+                json!({ "dev": route["dev"], "gateway": route["gateway"] }),
+            );
+        }
+    }
+
+    let mut dns = vec![];
+    let mut domain = None;
+    for line in resolv_conf.lines() {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("nameserver"), Some(server)) => dns.push(server.to_owned()),
+            (Some("domain"), Some(name)) if domain.is_none() => domain = Some(name.to_owned()),
+            _ => {}
+        }
+    }
+
+    json!({
+        "interfaces": interfaces,
+        "routes": routes,
+        "dns": { "domain": domain, "dns": dns },
+    })
+}
+
+fn run_ip_json(args: &[&str]) -> Result<Vec<Value>> {
+    let output = Command::new("ip").arg("--json").args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "Running 'ip {}' failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
 fn show_system_info(_args: &CommandSystemInfo) -> Result<()> {
     match SysInfo::as_json_pretty() {
         Ok(res) => println!("{res}"),
@@ -278,28 +1235,103 @@ fn prepare_iso(args: &CommandPrepareISO) -> Result<()> {
     check_prepare_requirements(args)?;
     let uuid = get_iso_uuid(&args.input)?;
 
-    if args.fetch_from == FetchAnswerFrom::Iso && args.answer_file.is_none() {
+    let fetch_from = |mode| args.fetch_from.contains(&mode);
+    for (index, mode) in args.fetch_from.iter().enumerate() {
+        if args.fetch_from[..index].contains(mode) {
+            bail!("The fetch-from '{mode:?}' mode is given more than once.");
+        }
+    }
+
+    if fetch_from(FetchAnswerFrom::Iso) && args.answer_file.is_none() {
         bail!("Missing path to the answer file required for the fetch-from 'iso' mode.");
     }
-    if args.url.is_some() && args.fetch_from != FetchAnswerFrom::Http {
-        bail!(
-            "Setting a URL is incompatible with the fetch-from '{:?}' mode, only works with the 'http' mode",
-            args.fetch_from,
-        );
+    if args.url.is_some() && !fetch_from(FetchAnswerFrom::Http) {
+        bail!("Setting a URL only works with the fetch-from 'http' mode.");
+    }
+    if args.cert_fingerprint.is_some() && !fetch_from(FetchAnswerFrom::Http) {
+        bail!("Setting a certificate fingerprint only works with the fetch-from 'http' mode.");
     }
-    if args.cert_fingerprint.is_some() && args.fetch_from != FetchAnswerFrom::Http {
+    let http_policy = [
+        args.http_retries.map(u64::from),
+        args.http_retry_delay,
+        args.http_timeout,
+        args.http_deadline,
+        args.network_wait,
+    ];
+    if http_policy.iter().any(Option::is_some) && !fetch_from(FetchAnswerFrom::Http) {
+        bail!("Setting the HTTP retry or network-wait policy only works with the fetch-from 'http' mode.");
+    }
+    if (args.client_cert.is_some() || args.client_key.is_some() || args.hmac_secret.is_some())
+        && !fetch_from(FetchAnswerFrom::Http)
+    {
+        bail!("Setting HTTP client authentication only works with the fetch-from 'http' mode.");
+    }
+    if (args.ca_certs.is_some() || args.server_name.is_some()) && !fetch_from(FetchAnswerFrom::Http)
+    {
         bail!(
-            "Setting a certificate fingerprint incompatible is fetch-from '{:?}' mode, only works for 'http' mode.",
-            args.fetch_from,
+            "Setting CA certificates or a server name only works with the fetch-from 'http' mode."
         );
     }
-    if args.answer_file.is_some() && args.fetch_from != FetchAnswerFrom::Iso {
-        bail!("You must set '--fetch-from' to 'iso' to place the answer file directly in the ISO.");
+    if args.ca_certs.is_some() && args.cert_fingerprint.is_some() {
+        bail!("The '--ca-certs' and '--cert-fingerprint' parameters are mutually exclusive.");
+    }
+    if !args.signing_key.is_empty() && args.fetch_from == [FetchAnswerFrom::Iso] {
+        bail!("Answer files in the ISO are not signed, signing keys need another fetch-from mode.");
+    }
+    if args.client_cert.is_some() != args.client_key.is_some() {
+        bail!("The '--client-cert' and '--client-key' parameters must be used together.");
+    }
+    if args.http_timeout == Some(0) {
+        bail!("The HTTP timeout must be at least one second.");
+    }
+    if (args.tftp_server.is_some() || args.tftp_path.is_some())
+        && !fetch_from(FetchAnswerFrom::Tftp)
+    {
+        bail!("Setting a TFTP server or path only works with the fetch-from 'tftp' mode.");
+    }
+    if (args.nfs_server.is_some() || args.nfs_export.is_some()) && !fetch_from(FetchAnswerFrom::Nfs)
+    {
+        bail!("Setting an NFS server or export only works with the fetch-from 'nfs' mode.");
+    }
+    if fetch_from(FetchAnswerFrom::Nfs) && args.nfs_export.is_none() {
+        bail!("Missing NFS export required for the fetch-from 'nfs' mode.");
+    }
+    if args.answer_file.is_some() && !fetch_from(FetchAnswerFrom::Iso) {
+        bail!("You must add 'iso' to '--fetch-from' to place the answer file directly in the ISO.");
+    }
+    if args.answer_lookup.is_some() && args.answer_file.is_none() {
+        bail!("Including a lookup table requires the '--answer-file' parameter.");
     }
 
     if let Some(file) = &args.answer_file {
         println!("Checking provided answer file...");
-        parse_answer(file)?;
+        check_answer_file(file)?;
+    }
+    if let Some(file) = &args.on_first_boot {
+        if !file.is_file() {
+            bail!("First-boot executable {file:?} does not exist or is not a file.");
+        }
+    }
+    for file in [&args.client_cert, &args.client_key, &args.hmac_secret]
+        .into_iter()
+        .flatten()
+    {
+        if !file.is_file() {
+            bail!("Client authentication file {file:?} does not exist or is not a file.");
+        }
+    }
+    if let Some(file) = &args.ca_certs {
+        check_ca_certs(file)?;
+    }
+    let mut signing_keys = String::new();
+    for file in &args.signing_key {
+        let keys = fs::read_to_string(file)
+            .map_err(|err| format_err!("Could not read signing key {file:?}: {err}"))
+            .and_then(|keys| signature::parse_public_keys(&keys))
+            .map_err(|err| format_err!("Invalid signing key {file:?}: {err}"))?;
+        for key in keys {
+            signing_keys.push_str(&key.to_pem());
+        }
     }
 
     let iso_target = final_iso_location(args);
@@ -326,7 +1358,38 @@ fn prepare_iso(args: &CommandPrepareISO) -> Result<()> {
         http: HttpOptions {
             url: args.url.clone(),
             cert_fingerprint: args.cert_fingerprint.clone(),
+            retries: args.http_retries,
+            retry_delay: args.http_retry_delay,
+            timeout: args.http_timeout,
+            deadline: args.http_deadline,
+            network_wait: args.network_wait,
+            client_cert: args
+                .client_cert
+                .as_ref()
+                .map(|_| format!("/cdrom{ISO_CLIENT_CERT}")),
+            client_key: args
+                .client_key
+                .as_ref()
+                .map(|_| format!("/cdrom{ISO_CLIENT_KEY}")),
+            hmac_secret: args
+                .hmac_secret
+                .as_ref()
+                .map(|_| format!("/cdrom{ISO_HMAC_SECRET}")),
+            ca_certs: args
+                .ca_certs
+                .as_ref()
+                .map(|_| format!("/cdrom{ISO_CA_CERTS}")),
+            server_name: args.server_name.clone(),
+        },
+        tftp: TftpOptions {
+            server: args.tftp_server.clone(),
+            path: args.tftp_path.clone(),
+        },
+        nfs: NfsOptions {
+            server: args.nfs_server.clone(),
+            export: args.nfs_export.clone(),
         },
+        signing_keys: (!signing_keys.is_empty()).then(|| format!("/cdrom{ISO_SIGNING_KEYS}")),
     };
     let mut instmode_file_tmp = tmp_base.clone();
     instmode_file_tmp.push("auto-installer-mode.toml");
@@ -339,10 +1402,36 @@ fn prepare_iso(args: &CommandPrepareISO) -> Result<()> {
         &uuid,
     )?;
 
+    if !signing_keys.is_empty() {
+        let mut keys_file_tmp = tmp_base.clone();
+        keys_file_tmp.push("answer-signing-keys.pub");
+        fs::write(&keys_file_tmp, &signing_keys)?;
+        inject_file_to_iso(&tmp_iso, &keys_file_tmp, ISO_SIGNING_KEYS, &uuid)?;
+    }
+
     if let Some(answer_file) = &args.answer_file {
         inject_file_to_iso(&tmp_iso, answer_file, "/answer.toml", &uuid)?;
     }
 
+    if let Some(lookup_table) = &args.answer_lookup {
+        inject_file_to_iso(&tmp_iso, lookup_table, "/answer-lookup.csv", &uuid)?;
+    }
+
+    if let Some(first_boot) = &args.on_first_boot {
+        inject_file_to_iso(&tmp_iso, first_boot, "/proxmox-first-boot", &uuid)?;
+    }
+
+    for (file, location) in [
+        (&args.client_cert, ISO_CLIENT_CERT),
+        (&args.client_key, ISO_CLIENT_KEY),
+        (&args.hmac_secret, ISO_HMAC_SECRET),
+        (&args.ca_certs, ISO_CA_CERTS),
+    ] {
+        if let Some(file) = file {
+            inject_file_to_iso(&tmp_iso, file, location, &uuid)?;
+        }
+    }
+
     println!("Moving prepared ISO to target location...");
     fs::rename(&tmp_iso, &iso_target)?;
     println!("Final ISO is available at {iso_target:?}.");
@@ -350,16 +1439,32 @@ fn prepare_iso(args: &CommandPrepareISO) -> Result<()> {
     Ok(())
 }
 
+/// Checks that the file contains at least one PEM-encoded certificate.
+fn check_ca_certs(file: &PathBuf) -> Result<()> {
+    let content = fs::read_to_string(file)
+        .map_err(|err| format_err!("Could not read CA certificates {file:?}: {err}"))?;
+    if !content.contains("-----BEGIN CERTIFICATE-----") {
+        bail!("No PEM-encoded certificate found in {file:?}.");
+    }
+    Ok(())
+}
+
 fn final_iso_location(args: &CommandPrepareISO) -> PathBuf {
     if let Some(specified) = args.output.clone() {
         return specified;
     }
-    let mut suffix: String = match args.fetch_from {
-        FetchAnswerFrom::Http => "auto-from-http",
-        FetchAnswerFrom::Iso => "auto-from-iso",
-        FetchAnswerFrom::Partition => "auto-from-partition",
-    }
-    .into();
+    let modes: Vec<&str> = args
+        .fetch_from
+        .iter()
+        .map(|mode| match mode {
+            FetchAnswerFrom::Http => "http",
+            FetchAnswerFrom::Iso => "iso",
+            FetchAnswerFrom::Partition => "partition",
+            FetchAnswerFrom::Tftp => "tftp",
+            FetchAnswerFrom::Nfs => "nfs",
+        })
+        .collect();
+    let mut suffix = format!("auto-from-{}", modes.join("-"));
 
     if args.url.is_some() {
         suffix.push_str("-url");
@@ -430,6 +1535,14 @@ fn get_iso_uuid(iso: &PathBuf) -> Result<String> {
 }
 
 fn get_disks() -> Result<BTreeMap<String, BTreeMap<String, String>>> {
+    Ok(get_block_devices()?
+        .into_iter()
+        .map(|disk| (disk.name, disk.udev_props))
+        .collect())
+}
+
+/// Returns all disks usable for the installation, sorted by their name in '/sys/block'.
+fn get_block_devices() -> Result<Vec<BlockDevice>> {
     let unwantend_block_devs = vec![
         "ram[0-9]*",
         "loop[0-9]*",
@@ -447,10 +1560,12 @@ fn get_disks() -> Result<BTreeMap<String, BTreeMap<String, String>>> {
     let re_name = Regex::new(r"(?m)^N: (.*)$")?;
     let re_props = Regex::new(r"(?m)^E: ([^=]+)=(.*)$")?;
 
-    let mut disks: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut disks = vec![];
+
+    let mut entries = fs::read_dir("/sys/block")?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
 
-    'outer: for entry in fs::read_dir("/sys/block")? {
-        let entry = entry.unwrap();
+    'outer: for entry in entries {
         let filename = entry.file_name().into_string().unwrap();
 
         for p in &unwantend_block_devs {
@@ -477,7 +1592,7 @@ fn get_disks() -> Result<BTreeMap<String, BTreeMap<String, String>>> {
             continue 'outer;
         };
 
-        let mut name = filename;
+        let mut name = filename.clone();
         if let Some(cap) = re_name.captures(&output) {
             if let Some(res) = cap.get(1) {
                 name = String::from(res.as_str());
@@ -494,7 +1609,33 @@ fn get_disks() -> Result<BTreeMap<String, BTreeMap<String, String>>> {
             }
         }
 
-        disks.insert(name, udev_props);
+        disks.push(BlockDevice {
+            sys_name: filename,
+            name,
+            udev_props,
+        });
+    }
+    Ok(disks)
+}
+
+fn parse_numeric_filter(filter: &str) -> Result<NumericFilter, &'static str> {
+    NumericFilter::try_from(filter.to_owned())
+}
+
+/// Returns the disks, with the name as reported by udev as index, like the keys of `get_disks`.
+fn get_disk_list() -> Result<Vec<Disk>> {
+    let mut disks = vec![];
+    for disk in get_block_devices()? {
+        if let Some(details) = disk.details() {
+            disks.push(Disk {
+                index: disk.name.clone(),
+                path: disk.dev_path(),
+                model: (!details.model.is_empty()).then_some(details.model),
+                // same conversion as done for the disk list of the low-level installer
+                size: (details.sectors as f64 * 512.) / 1024. / 1024. / 1024.,
+                block_size: details.logical_bsize,
+            });
+        }
     }
     Ok(disks)
 }
@@ -562,6 +1703,21 @@ fn parse_answer(path: &PathBuf) -> Result<Answer> {
     }
 }
 
+/// Parses the answer file, unless it can only be checked once decrypted or rendered.
+fn check_answer_file(path: &PathBuf) -> Result<()> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format_err!("Reading answer file {path:?} failed: {err}"))?;
+
+    if encryption::is_encrypted(&contents) {
+        println!("The answer file is encrypted, it can only be checked once decrypted.");
+    } else if template::is_template(&contents) {
+        println!("The answer file is a template, it can only be checked once rendered.");
+    } else {
+        parse_answer(path)?;
+    }
+    Ok(())
+}
+
 fn check_prepare_requirements(args: &CommandPrepareISO) -> Result<()> {
     match Path::try_exists(&args.input) {
         Ok(true) => (),
@@ -591,3 +1747,128 @@ fn check_prepare_requirements(args: &CommandPrepareISO) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captured_hardware_round_trip() {
+        let links = json!([
+            {
+                "ifindex": 1,
+                "ifname": "lo",
+                "operstate": "UNKNOWN",
+                "address": "00:00:00:00:00:00",
+                "addr_info": [{ "family": "inet", "local": "127.0.0.1", "prefixlen": 8, "scope": "host" }]
+            },
+            {
+                "ifindex": 2,
+                "ifname": "eno1",
+                "operstate": "UP",
+                "address": "b4:2e:99:ac:ad:b4",
+                "addr_info": [
+                    { "family": "inet", "local": "192.168.1.114", "prefixlen": 24, "scope": "global" },
+                    { "family": "inet6", "local": "2001:db8::114", "prefixlen": 64, "scope": "global" },
+                    { "family": "inet6", "local": "fe80::b62e:99ff:feac:adb4", "prefixlen": 64, "scope": "link" }
+                ]
+            },
+            {
+                "ifindex": 3,
+                "ifname": "eno2",
+                "operstate": "DOWN",
+                "address": "b4:2e:99:ac:ad:b5",
+                "addr_info": []
+            }
+        ]);
+        let routes4 = json!([
+            { "dst": "default", "gateway": "192.168.1.1", "dev": "eno1" },
+            { "dst": "192.168.1.0/24", "dev": "eno1" }
+        ]);
+        let routes6 = json!([{ "dst": "default", "gateway": "2001:db8::1", "dev": "eno1" }]);
+        let resolv_conf = "domain example.com\nsearch example.com\nnameserver 192.168.1.1\n";
+
+        let disk = |index: usize, name: &str, model: &str| {
+            let udev_props = BTreeMap::from([
+                ("DEVNAME".to_owned(), format!("/dev/{name}")),
+                ("ID_MODEL".to_owned(), model.to_owned()),
+            ]);
+            let device = BlockDevice {
+                sys_name: name.to_owned(),
+                name: name.to_owned(),
+                udev_props,
+            };
+            let details = DiskDetails {
+                sectors: 468862128 * (index as u64 + 1),
+                model: model.to_owned(),
+                logical_bsize: Some(512),
+            };
+            (device, details)
+        };
+
+        let hardware = CapturedHardware {
+            disks: vec![
+                disk(0, "sda", "SAMSUNG MZ7KM240"),
+                disk(1, "nvme0n1", "KXG60ZNV512G"),
+            ],
+            efi: true,
+            hvm_supported: true,
+            kernel_cmdline: "BOOT_IMAGE=/boot/linux26 ro quiet".to_owned(),
+            network: network_info(
+                serde_json::from_value(links).unwrap(),
+                serde_json::from_value(routes4).unwrap(),
+                serde_json::from_value(routes6).unwrap(),
+                resolv_conf,
+            ),
+            total_memory: 64 * 1024,
+            nics: BTreeMap::from([(
+                "eno1".to_owned(),
+                BTreeMap::from([("ID_NET_NAME_MAC".to_owned(), "enxb42e99acadb4".to_owned())]),
+            )]),
+        };
+        let (run_env, udev) = hardware.to_run_env();
+
+        let runtime_info: RuntimeInfo = serde_json::from_value(run_env).unwrap();
+        assert_eq!(runtime_info.disks.len(), 2);
+        assert_eq!(runtime_info.disks[1].path, "/dev/nvme0n1");
+        assert_eq!(runtime_info.disks[1].block_size, Some(512));
+        assert!(runtime_info.hvm_supported);
+        assert_eq!(runtime_info.total_memory, 64 * 1024);
+
+        let network = &runtime_info.network;
+        assert_eq!(network.interfaces.len(), 2);
+        assert_eq!(
+            network.interfaces["eno1"].addresses.as_ref().unwrap().len(),
+            2
+        );
+        assert!(network.interfaces["eno2"].addresses.is_none());
+        let routes = network.routes.as_ref().unwrap();
+        assert_eq!(routes.gateway4.as_ref().unwrap().dev, "eno1");
+        assert_eq!(
+            routes.gateway6.as_ref().unwrap().gateway.to_string(),
+            "2001:db8::1"
+        );
+        assert_eq!(network.dns.domain.as_deref(), Some("example.com"));
+
+        let udev_info: UdevInfo = serde_json::from_value(udev).unwrap();
+        assert_eq!(udev_info.disks["1"]["ID_MODEL"], "KXG60ZNV512G");
+        assert_eq!(udev_info.nics["eno1"]["ID_NET_NAME_MAC"], "enxb42e99acadb4");
+    }
+
+    #[test]
+    fn device_match_take_at_least_one() {
+        let parse = |take: &str, count: &str| {
+            let args = [
+                "proxmox-auto-install-assistant",
+                "device-match",
+                take,
+                count,
+            ];
+            Cli::try_parse_from(args.into_iter().chain(["disk", "DEVNAME=*nvme*"]))
+        };
+
+        assert!(parse("--take-smallest", "0").is_err());
+        assert!(parse("--take-largest", "0").is_err());
+        assert!(parse("--take-largest", "2").is_ok());
+    }
+}
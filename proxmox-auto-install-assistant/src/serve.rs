@@ -0,0 +1,636 @@
+//! A minimal HTTPS server providing answer files for the 'http' fetch-from mode.
+//!
+//! The installer sends its system information as JSON in a POST request, the answer file is then
+//! chosen by the rules file:
+//!
+//! ```text
+//! # used if no rule matches, optional
+//! default = "default.toml"
+//!
+//! [[rule]]
+//! serial = "CZ1234"
+//! answer = "pve1.toml"
+//!
+//! [[rule]]
+//! mac = "aa:bb:cc:dd:ee:ff"
+//! answer = "pve-template.toml"
+//! ```
+//!
+//! A rule matches if all of its `serial`, `uuid` and `mac` criteria match the DMI system serial,
+//! the DMI system UUID and the MAC address of any network interface, the first matching rule is
+//! used.
+//!
+//! As the system information is only claimed by the client, the answer files should be protected
+//! by authenticating the installer, with an HMAC secret or client certificates, as configured with
+//! `prepare-iso --hmac-secret` or `--client-cert`.
+
+use anyhow::{bail, format_err, Result};
+use rustls::{server::AllowAnyAuthenticatedClient, ServerConfig, ServerConnection, StreamOwned};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use proxmox_auto_installer::{
+    encryption,
+    signature::{HTTP_SIGNATURE_HEADER, SIGNATURE_SUFFIX},
+    template::{self, Variables},
+    validate,
+};
+use proxmox_installer_common::http;
+
+/// Lookup table for answer file templates, in the answer directory.
+const LOOKUP_TABLE_FILE: &str = "answer-lookup.csv";
+
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Upper limit for the request body, the system information is usually a few KiB.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const MAX_HEADER_LINES: usize = 100;
+/// Upper limit for the request line and each header line.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Rules {
+    default: Option<String>,
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    serial: Option<String>,
+    uuid: Option<String>,
+    mac: Option<String>,
+    answer: String,
+}
+
+impl Rule {
+    fn matches(&self, variables: &Variables) -> bool {
+        let serial = self
+            .serial
+            .as_ref()
+            .map(|serial| variables.get("dmi.system.serial") == Some(serial));
+        let uuid = self.uuid.as_ref().map(|uuid| {
+            variables
+                .get("dmi.system.uuid")
+                .is_some_and(|value| value.eq_ignore_ascii_case(uuid))
+        });
+        let mac = self.mac.as_ref().map(|mac| {
+            variables
+                .iter()
+                .filter(|(name, _)| name.starts_with("mac."))
+                .any(|(_, value)| value.eq_ignore_ascii_case(mac))
+        });
+
+        [serial, uuid, mac]
+            .into_iter()
+            .flatten()
+            .all(|matched| matched)
+    }
+}
+
+impl Rules {
+    fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format_err!("Reading rules file {path:?} failed: {err}"))?;
+        let rules: Rules = toml::from_str(&content)
+            .map_err(|err| format_err!("Parsing rules file {path:?} failed: {err}"))?;
+
+        for rule in &rules.rules {
+            if rule.serial.is_none() && rule.uuid.is_none() && rule.mac.is_none() {
+                bail!(
+                    "Rule for '{}' needs at least one of 'serial', 'uuid' or 'mac'.",
+                    rule.answer
+                );
+            }
+        }
+        for answer in rules
+            .rules
+            .iter()
+            .map(|rule| &rule.answer)
+            .chain(&rules.default)
+        {
+            let path = Path::new(answer);
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                bail!("Answer file '{answer}' must be relative to the answer directory.");
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Returns the answer file for the host, if any rule matches or a default is set.
+    fn find(&self, variables: &Variables) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(variables))
+            .map(|rule| rule.answer.as_str())
+            .or(self.default.as_deref())
+    }
+}
+
+struct Request {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    body: String,
+    signature: Option<String>,
+}
+
+impl Response {
+    fn error(status: &'static str, body: &str) -> Self {
+        Self {
+            status,
+            body: format!("{body}\n"),
+            signature: None,
+        }
+    }
+}
+
+/// Serves answer files from the directory, as chosen by the rules.
+pub struct AnswerServer {
+    dir: PathBuf,
+    rules: Rules,
+    /// Shared secret requests must be signed with, see [`http::verify_request_signature`]
+    hmac_secret: Option<Vec<u8>>,
+}
+
+impl AnswerServer {
+    pub fn new(dir: &Path, rules: &Path, hmac_secret: Option<&Path>) -> Result<Self> {
+        if !dir.is_dir() {
+            bail!("Answer directory {dir:?} does not exist.");
+        }
+        let hmac_secret = match hmac_secret {
+            Some(path) => Some(
+                http::read_hmac_secret(path)
+                    .map_err(|err| format_err!("Reading HMAC secret failed: {err}"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            rules: Rules::load(rules)?,
+            hmac_secret,
+        })
+    }
+
+    /// Accepts connections until the process is terminated, each in its own thread. With CA
+    /// certificates for clients, only clients with a certificate issued by one of them are
+    /// accepted.
+    pub fn run(
+        self,
+        listen: SocketAddr,
+        cert: &Path,
+        key: &Path,
+        client_ca: Option<&Path>,
+    ) -> Result<()> {
+        let (tls_config, fingerprint) = tls_config(cert, key, client_ca)?;
+        let listener = TcpListener::bind(listen)
+            .map_err(|err| format_err!("Listening on {listen} failed: {err}"))?;
+
+        println!("Listening on https://{listen}/");
+        println!("TLS certificate fingerprint, for 'prepare-iso --cert-fingerprint':");
+        println!("{fingerprint}");
+        if self.hmac_secret.is_none() && client_ca.is_none() {
+            println!("Warning: no client authentication configured, any client can fetch answers.");
+        }
+
+        self.serve(listener, tls_config);
+        Ok(())
+    }
+
+    fn serve(self, listener: TcpListener, tls_config: Arc<ServerConfig>) {
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log(None, &format!("accepting connection failed: {err}"));
+                    continue;
+                }
+            };
+            let server = Arc::clone(&server);
+            let tls_config = Arc::clone(&tls_config);
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(err) = server.handle_connection(stream, tls_config) {
+                    log(peer, &format!("request failed: {err}"));
+                }
+            });
+        }
+    }
+
+    fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+        tls_config: Arc<ServerConfig>,
+    ) -> Result<()> {
+        let peer = stream.peer_addr().ok();
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut conn = ServerConnection::new(tls_config)?;
+        // complete the handshake first, to tell rejected client certificates from bad requests
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)
+                .map_err(|err| format_err!("TLS handshake failed: {err}"))?;
+        }
+        let mut stream = StreamOwned::new(conn, stream);
+
+        let response = match read_request(&mut stream) {
+            Ok(request) => self.handle_request(peer, &request),
+            Err(err) => {
+                log(peer, &format!("bad request: {err}"));
+                Response::error("400 Bad Request", &err.to_string())
+            }
+        };
+
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+             Connection: close\r\n",
+            response.status,
+            response.body.len()
+        );
+        if let Some(signature) = &response.signature {
+            head.push_str(&format!(
+                "{HTTP_SIGNATURE_HEADER}: {}\r\n",
+                signature.trim()
+            ));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(response.body.as_bytes())?;
+        stream.conn.send_close_notify();
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Authenticates the request, if configured, and answers it with the answer file for the host.
+    fn handle_request(&self, peer: Option<SocketAddr>, request: &Request) -> Response {
+        if let Some(secret) = &self.hmac_secret {
+            let result = http::verify_request_signature(
+                secret,
+                request.header(http::TIMESTAMP_HEADER),
+                request.header(http::SIGNATURE_HEADER),
+                &request.body,
+            );
+            if let Err(err) = result {
+                log(peer, &format!("authentication failed: {err}"));
+                return Response::error("401 Unauthorized", "Authentication failed.");
+            }
+        }
+
+        match serde_json::from_slice(&request.body) {
+            Ok(sysinfo) => self.answer_for(peer, &sysinfo),
+            Err(err) => {
+                log(peer, &format!("bad request: {err}"));
+                Response::error("400 Bad Request", &err.to_string())
+            }
+        }
+    }
+
+    fn answer_for(&self, peer: Option<SocketAddr>, sysinfo: &Value) -> Response {
+        let variables = template::variables_from_sysinfo(sysinfo);
+        let host = format!(
+            "serial '{}', uuid '{}'",
+            variables
+                .get("dmi.system.serial")
+                .map_or("-", String::as_str),
+            variables.get("dmi.system.uuid").map_or("-", String::as_str),
+        );
+
+        let name = match self.rules.find(&variables) {
+            Some(name) => name,
+            None => {
+                log(peer, &format!("{host}: no matching rule"));
+                return Response::error("404 Not Found", "No answer file for this host.");
+            }
+        };
+
+        match self.load_answer(name, variables) {
+            Ok(response) => {
+                log(peer, &format!("{host}: serving '{name}'"));
+                response
+            }
+            Err(err) => {
+                log(peer, &format!("{host}: not serving '{name}': {err}"));
+                Response::error(
+                    "500 Internal Server Error",
+                    "Failed to provide the answer file for this host.",
+                )
+            }
+        }
+    }
+
+    /// Reads the answer file, renders it if it is a template and validates the result.
+    ///
+    /// Signed answer files are served unchanged along with their signature, signed templates and
+    /// encrypted answer files without validation, to be rendered and decrypted by the installer.
+    fn load_answer(&self, name: &str, mut variables: Variables) -> Result<Response> {
+        let path = self.dir.join(name);
+        let content = fs::read_to_string(&path)
+            .map_err(|err| format_err!("reading {path:?} failed: {err}"))?;
+
+        let mut signature_path = path.into_os_string();
+        signature_path.push(SIGNATURE_SUFFIX);
+        let signature = match fs::read_to_string(&signature_path) {
+            Ok(signature) => Some(signature),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => bail!("reading signature {signature_path:?} failed: {err}"),
+        };
+
+        // encrypted answers cannot be validated, and signed templates cannot be rendered, as the
+        // signature covers the template itself
+        if encryption::is_encrypted(&content)
+            || (signature.is_some() && template::is_template(&content))
+        {
+            return Ok(Response {
+                status: "200 OK",
+                body: content,
+                signature,
+            });
+        }
+
+        let answer = if template::is_template(&content) {
+            match fs::read_to_string(self.dir.join(LOOKUP_TABLE_FILE)) {
+                Ok(table) => template::apply_lookup_table(&table, &mut variables)?,
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => bail!("reading lookup table failed: {err}"),
+            }
+            template::render(&content, &variables)?
+        } else {
+            content
+        };
+
+        let (_, report) = validate::validate_answer(&answer, None);
+        if !report.valid {
+            let problems: Vec<String> = report.problems.iter().map(|p| p.to_string()).collect();
+            bail!("validation failed: {}", problems.join("; "));
+        }
+
+        Ok(Response {
+            status: "200 OK",
+            body: answer,
+            signature,
+        })
+    }
+}
+
+/// Reads a POST request and returns its headers and body, the system information of the host.
+fn read_request(stream: &mut impl Read) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let request_line = read_line(&mut reader)?;
+    let method = request_line.split_whitespace().next().unwrap_or_default();
+    if method != "POST" {
+        bail!("unsupported method '{method}'");
+    }
+
+    let mut headers = Vec::new();
+    for _ in 0..MAX_HEADER_LINES {
+        let line = read_line(&mut reader)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    let mut request = Request {
+        headers,
+        body: Vec::new(),
+    };
+
+    let content_length = match request.header("content-length") {
+        Some(length) => Some(length.parse::<usize>()?),
+        None => None,
+    };
+    let length = match content_length {
+        Some(length) if length <= MAX_BODY_SIZE => length,
+        Some(length) => bail!("request body too large: {length} bytes"),
+        None => bail!("missing content length"),
+    };
+    request.body = vec![0u8; length];
+    reader.read_exact(&mut request.body)?;
+
+    Ok(request)
+}
+
+/// Reads a single line of the request, up to [`MAX_LINE_LENGTH`] bytes.
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE_LENGTH as u64 + 1)
+        .read_line(&mut line)?;
+    if line.len() > MAX_LINE_LENGTH {
+        bail!("request line too long");
+    }
+    Ok(line)
+}
+
+/// Builds the TLS config from the PEM-encoded certificate chain and key, returning it along with
+/// the SHA256 fingerprint of the certificate. Client certificates are required if CA certificates
+/// to verify them against are given.
+fn tls_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<(Arc<ServerConfig>, String)> {
+    let content =
+        fs::read(cert).map_err(|err| format_err!("Reading certificate {cert:?} failed: {err}"))?;
+    let certs = http::parse_certs(&content)
+        .map_err(|err| format_err!("Parsing certificate {cert:?} failed: {err}"))?;
+    let fingerprint = Sha256::digest(&certs[0].0)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(":");
+
+    let content = fs::read(key).map_err(|err| format_err!("Reading key {key:?} failed: {err}"))?;
+    let key = http::parse_private_key(&content)
+        .map_err(|err| format_err!("Parsing key {key:?} failed: {err}"))?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(path) => {
+            let content = fs::read(path)
+                .map_err(|err| format_err!("Reading client CA {path:?} failed: {err}"))?;
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in http::parse_certs(&content)
+                .map_err(|err| format_err!("Parsing client CA {path:?} failed: {err}"))?
+            {
+                roots
+                    .add(&cert)
+                    .map_err(|err| format_err!("Invalid client CA in {path:?}: {err}"))?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(certs, key)?;
+
+    Ok((Arc::new(config), fingerprint))
+}
+
+fn log(peer: Option<SocketAddr>, message: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    match peer {
+        Some(peer) => println!("[{timestamp}] {peer}: {message}"),
+        None => println!("[{timestamp}] {message}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(serial: &str, macs: &[&str]) -> Variables {
+        let mut variables = Variables::new();
+        variables.insert("dmi.system.serial".to_owned(), serial.to_owned());
+        variables.insert(
+            "dmi.system.uuid".to_owned(),
+            "4C4C4544-0042-3010-8057-B4C04F4E4A32".to_owned(),
+        );
+        for (index, mac) in macs.iter().enumerate() {
+            variables.insert(format!("mac.eno{index}"), mac.to_string());
+        }
+        variables
+    }
+
+    #[test]
+    fn match_rules() {
+        let rules: Rules = toml::from_str(
+            r#"
+            default = "default.toml"
+
+            [[rule]]
+            serial = "CZ1234"
+            answer = "by-serial.toml"
+
+            [[rule]]
+            mac = "AA:BB:CC:DD:EE:FF"
+            answer = "by-mac.toml"
+
+            [[rule]]
+            serial = "CZ9999"
+            uuid = "4c4c4544-0042-3010-8057-b4c04f4e4a32"
+            answer = "by-both.toml"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(rules.find(&host("CZ1234", &[])), Some("by-serial.toml"));
+        assert_eq!(
+            rules.find(&host("CZ0000", &["11:22:33:44:55:66", "aa:bb:cc:dd:ee:ff"])),
+            Some("by-mac.toml")
+        );
+        assert_eq!(rules.find(&host("CZ9999", &[])), Some("by-both.toml"));
+        assert_eq!(rules.find(&host("CZ0000", &[])), Some("default.toml"));
+
+        let rules = Rules {
+            default: None,
+            rules: vec![],
+        };
+        assert_eq!(rules.find(&host("CZ1234", &[])), None);
+    }
+
+    #[test]
+    fn serve_on_localhost() {
+        let resources = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/resources/serve");
+        let server = AnswerServer::new(
+            &resources.join("answers"),
+            &resources.join("answers/rules.toml"),
+            Some(&resources.join("hmac-secret")),
+        )
+        .unwrap();
+        // certificate and key in one file
+        let (tls_config, fingerprint) = tls_config(
+            &resources.join("localhost.pem"),
+            &resources.join("localhost.pem"),
+            Some(&resources.join("client-ca.pem")),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || server.serve(listener, tls_config));
+
+        let client_cert = fs::read(resources.join("client.pem")).unwrap();
+        let options = http::PostOptions {
+            client_cert: Some((client_cert.clone(), client_cert)),
+            hmac_secret: Some(b"answer-secret".to_vec()),
+            ..Default::default()
+        };
+        let fetch = |serial: &str, options: &http::PostOptions| {
+            let payload = serde_json::json!({ "dmi": { "system": { "serial": serial } } });
+            http::post_with_options(&url, Some(&fingerprint), payload.to_string(), options)
+                .map(|response| response.body)
+                .map_err(|err| err.to_string())
+        };
+
+        let answer = fetch("CZ1234", &options).unwrap();
+        assert!(answer.contains("fqdn = \"pve1.example.com\""));
+        assert!(fetch("CZ0000", &options).unwrap_err().contains("404"));
+
+        let wrong_secret = http::PostOptions {
+            hmac_secret: Some(b"other-secret".to_vec()),
+            ..options.clone()
+        };
+        assert!(fetch("CZ1234", &wrong_secret).unwrap_err().contains("401"));
+        let unsigned = http::PostOptions {
+            hmac_secret: None,
+            ..options.clone()
+        };
+        assert!(fetch("CZ1234", &unsigned).unwrap_err().contains("401"));
+
+        let no_client_cert = http::PostOptions {
+            client_cert: None,
+            ..options
+        };
+        assert!(fetch("CZ1234", &no_client_cert).is_err());
+    }
+
+    #[test]
+    fn read_post_request() {
+        let request =
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 14\r\n\r\n{\"dmi\": {}}   ";
+        let request = read_request(&mut request.as_bytes()).unwrap();
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.body, b"{\"dmi\": {}}   ");
+
+        assert!(read_request(&mut "GET / HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request(&mut "POST / HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+
+        let long_header = format!("POST / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(10000));
+        assert!(read_request(&mut long_header.as_bytes()).is_err());
+    }
+}
@@ -1,41 +1,59 @@
 use anyhow::{bail, format_err, Result};
-use log::{error, info, LevelFilter};
+use log::{debug, error, info, warn};
 use std::{
     env,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
-    process::ExitCode,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
 };
 
-use proxmox_installer_common::setup::{
-    installer_setup, read_json, spawn_low_level_installer, LocaleInfo, RuntimeInfo, SetupInfo,
+use proxmox_installer_common::{
+    answer::{Answer, FirstBootHookInfo, FirstBootHookSourceMode, PostNotificationHookInfo},
+    http,
+    setup::{
+        installer_setup, read_json, spawn_low_level_installer, InstallConfig, RuntimeInfo,
+        FIRST_BOOT_HOOK_FILE,
+    },
+    udevinfo::UdevInfo,
 };
 
 use proxmox_auto_installer::{
-    answer::Answer,
+    installer_logs::{self, InstallerLogs},
     log::AutoInstLogger,
-    udevinfo::UdevInfo,
+    post_hook::PostHookInfo,
+    progress::ProgressReporter,
+    summary::installation_summary,
+    sysinfo::SysInfo,
     utils::{parse_answer, LowLevelMessage},
 };
 
 static LOGGER: AutoInstLogger = AutoInstLogger;
 
+/// File name of the first-boot hook, if placed on the ISO by `prepare-iso` or on the
+/// 'proxmox-ais' partition
+static FIRST_BOOT_HOOK_SOURCE_FILE: &str = "proxmox-first-boot";
+
 pub fn init_log() -> Result<()> {
-    AutoInstLogger::init("/tmp/auto_installer.log")?;
+    let level = AutoInstLogger::init("/tmp/auto_installer.log")?;
     log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(LevelFilter::Info))
+        .map(|()| log::set_max_level(level))
         .map_err(|err| format_err!(err))
 }
 
-fn auto_installer_setup(in_test_mode: bool) -> Result<(Answer, UdevInfo)> {
+fn runtime_dir(in_test_mode: bool) -> PathBuf {
     let base_path = if in_test_mode { "./testdir" } else { "/" };
     let mut path = PathBuf::from(base_path);
 
     path.push("run");
     path.push("proxmox-installer");
+    path
+}
 
+fn auto_installer_setup(in_test_mode: bool) -> Result<(Answer, UdevInfo)> {
     let udev_info: UdevInfo = {
-        let mut path = path.clone();
+        let mut path = runtime_dir(in_test_mode);
         path.push("run-env-udev.json");
 
         read_json(&path)
@@ -52,6 +70,14 @@ fn auto_installer_setup(in_test_mode: bool) -> Result<(Answer, UdevInfo)> {
     let answer: Answer =
         toml::from_str(&buffer).map_err(|err| format_err!("Failed parsing answer file: {err}"))?;
 
+    // keep a copy for diagnosing the installation later on, see the `installer_logs` module
+    let path = runtime_dir(in_test_mode).join(installer_logs::ANSWER_FILE);
+    if let Err(err) = installer_logs::redact_answer(&buffer)
+        .and_then(|redacted| fs::write(&path, redacted).map_err(|err| err.into()))
+    {
+        warn!("Failed to save redacted answer file: {err}");
+    }
+
     Ok((answer, udev_info))
 }
 
@@ -83,19 +109,263 @@ fn main() -> ExitCode {
         }
     };
 
-    match run_installation(&answer, &locales, &runtime_info, &udevadm_info, &setup_info) {
+    if let Some(level) = answer.global.log_level {
+        AutoInstLogger::set_fallback_level(level.into());
+    }
+
+    let mut reporter = match ProgressReporter::new(&answer, &runtime_info) {
+        Ok(reporter) => reporter,
+        Err(err) => {
+            // Not a fatal error, the installation can proceed without it
+            warn!("Failed to set up progress reporting: {err}");
+            None
+        }
+    };
+
+    AutoInstLogger::set_phase("answer");
+    let config = match parse_answer(&answer, &udevadm_info, &runtime_info, &locales, &setup_info) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Installation failed: {err}");
+            return installation_failed(&answer, reporter, &err.to_string(), in_test_mode);
+        }
+    };
+    if let Some(reporter) = reporter.as_mut() {
+        reporter.set_config(&config, &runtime_info);
+    }
+
+    if let Some(timeout) = answer.global.confirm_timeout {
+        AutoInstLogger::set_phase("confirmation");
+        let summary = installation_summary(&config, &runtime_info, &udevadm_info);
+        info!("Installation summary:\n{summary}");
+
+        match confirm_installation(&summary, timeout) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!("Installation aborted on the console.");
+                report_failure(reporter, "Installation aborted on the console.");
+                return ExitCode::FAILURE;
+            }
+            Err(err) => {
+                // without a way to abort, do not wipe any disks
+                let err = format!("could not show confirmation countdown: {err}");
+                error!("Installation aborted: {err}");
+                return installation_failed(&answer, reporter, &err, in_test_mode);
+            }
+        }
+    }
+
+    if let Some(first_boot) = &answer.first_boot {
+        AutoInstLogger::set_phase("first-boot-hook");
+        if let Err(err) = setup_first_boot_hook(first_boot, &setup_info.locations.iso, in_test_mode)
+        {
+            let err = format!("could not set up first-boot hook: {err}");
+            error!("Installation failed: {err}");
+            return installation_failed(&answer, reporter, &err, in_test_mode);
+        }
+    }
+
+    AutoInstLogger::set_phase("installation");
+    match run_installation(&config, reporter.as_mut()) {
         Ok(_) => info!("Installation done."),
         Err(err) => {
             error!("Installation failed: {err}");
-            return exit_failure(answer.global.reboot_on_error);
+            return installation_failed(&answer, reporter, &err.to_string(), in_test_mode);
         }
     }
+    if let Some(reporter) = reporter {
+        reporter.finish();
+    }
 
-    // TODO: (optionally) do a HTTP post with basic system info, like host SSH public key(s) here
+    if let Some(hook) = &answer.post_installation_webhook {
+        AutoInstLogger::set_phase("post-installation");
+        // Not a fatal error, the installation itself was successful after all
+        if let Err(err) = send_post_installation_webhook(hook, &config, &runtime_info) {
+            warn!("Failed to send post-installation webhook: {err}");
+        }
+    }
 
     ExitCode::SUCCESS
 }
 
+fn send_post_installation_webhook(
+    hook: &PostNotificationHookInfo,
+    config: &InstallConfig,
+    runtime_info: &RuntimeInfo,
+) -> Result<()> {
+    info!("Gathering information for post-installation webhook");
+    let info = PostHookInfo::gather(config, runtime_info)?;
+    let payload = serde_json::to_string(&info)?;
+
+    info!("Sending POST request to '{}'.", hook.url);
+    http::post(&hook.url, hook.cert_fingerprint.as_deref(), payload)?;
+    info!("Post-installation webhook sent successfully.");
+
+    Ok(())
+}
+
+/// Retrieves the first-boot hook executable from the configured source and places it in the
+/// runtime directory, from where the low-level installer copies it into the installed system.
+fn setup_first_boot_hook(
+    first_boot: &FirstBootHookInfo,
+    iso_path: &Path,
+    in_test_mode: bool,
+) -> Result<()> {
+    info!("Setting up first-boot hook from {:?}", first_boot.source);
+
+    let content = match first_boot.source {
+        FirstBootHookSourceMode::FromAnswer => first_boot
+            .script
+            .clone()
+            .ok_or_else(|| format_err!("no inline script set"))?
+            .into_bytes(),
+        FirstBootHookSourceMode::FromIso => {
+            let path = iso_path.join(FIRST_BOOT_HOOK_SOURCE_FILE);
+            fs::read(&path).map_err(|err| format_err!("failed to read {path:?}: {err}"))?
+        }
+        FirstBootHookSourceMode::FromPartition => {
+            installer_logs::mount_answer_partition(false)
+                .map_err(|err| format_err!("failed to mount 'proxmox-ais' partition: {err}"))?;
+            let path =
+                Path::new(installer_logs::ANSWER_PARTITION_MP).join(FIRST_BOOT_HOOK_SOURCE_FILE);
+            fs::read(&path).map_err(|err| format_err!("failed to read {path:?}: {err}"))?
+        }
+        FirstBootHookSourceMode::FromUrl => {
+            let url = first_boot
+                .url
+                .as_ref()
+                .ok_or_else(|| format_err!("no URL set"))?;
+            info!("Sending POST request to '{url}'.");
+            http::post(
+                url,
+                first_boot.cert_fingerprint.as_deref(),
+                SysInfo::as_json()?,
+            )?
+            .into_bytes()
+        }
+    };
+
+    if content.is_empty() {
+        bail!("first-boot hook is empty");
+    }
+
+    let path = runtime_dir(in_test_mode).join(FIRST_BOOT_HOOK_FILE);
+    fs::write(&path, content)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+    Ok(())
+}
+
+/// Shows the installation summary on the console and counts down, giving an operator standing at
+/// the machine the chance to abort the installation by pressing any key.
+///
+/// Returns `false` if the installation was aborted.
+fn confirm_installation(summary: &str, timeout: u64) -> Result<bool> {
+    let mut console = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .or_else(|_| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/console")
+        })
+        .map_err(|err| format_err!("failed to open console: {err}"))?;
+
+    writeln!(
+        console,
+        "\nThe following installation will be performed:\n\n{summary}"
+    )?;
+
+    // read single key presses without waiting for a newline, with reads timing out after a second
+    let saved_mode = stty(&console, &["-g"])?;
+    stty(&console, &["-icanon", "-echo", "min", "0", "time", "10"])?;
+
+    let mut countdown = || -> Result<bool> {
+        for remaining in (1..=timeout).rev() {
+            write!(
+                console,
+                "\rStarting installation in {remaining:>3} seconds, press any key to abort.. "
+            )?;
+            console.flush()?;
+
+            let mut key = [0u8; 1];
+            if console.read(&mut key)? > 0 {
+                writeln!(console)?;
+                return Ok(false);
+            }
+        }
+        writeln!(console)?;
+        Ok(true)
+    };
+    let result = countdown();
+
+    stty(&console, &[saved_mode.trim()])?;
+    result
+}
+
+/// Runs `stty` on the given terminal, returning its output.
+fn stty(terminal: &File, args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(terminal.try_clone()?)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "stty failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Reports a failed installation to the progress-report target, if any, and waits until it has
+/// been sent.
+fn report_failure(reporter: Option<ProgressReporter>, message: &str) {
+    if let Some(mut reporter) = reporter {
+        reporter.report_failure(message);
+        reporter.finish();
+    }
+}
+
+/// Reports the failed installation and saves the installer logs, either by uploading them to the
+/// configured endpoint or, as fallback, on the 'proxmox-ais' partition unless the answer file was
+/// encrypted.
+fn installation_failed(
+    answer: &Answer,
+    reporter: Option<ProgressReporter>,
+    message: &str,
+    in_test_mode: bool,
+) -> ExitCode {
+    report_failure(reporter, message);
+
+    let logs = InstallerLogs::collect(&runtime_dir(in_test_mode));
+    let uploaded = match &answer.log_upload {
+        Some(info) => match logs.upload(info) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Failed to upload installer logs: {err}");
+                false
+            }
+        },
+        None => false,
+    };
+    if !uploaded && !in_test_mode {
+        if installer_logs::answer_was_encrypted() {
+            info!("Not saving installer logs on the answer partition, the answer was encrypted.");
+        } else {
+            match logs.store_on_partition() {
+                Ok(path) => info!("Saved installer logs to {path:?}"),
+                Err(err) => warn!("Could not save installer logs on the answer partition: {err}"),
+            }
+        }
+    }
+
+    exit_failure(answer.global.reboot_on_error)
+}
+
 /// When we exit with a failure, the installer will not automatically reboot.
 /// Default value for reboot_on_error is false
 fn exit_failure(reboot_on_error: bool) -> ExitCode {
@@ -107,13 +377,9 @@ fn exit_failure(reboot_on_error: bool) -> ExitCode {
 }
 
 fn run_installation(
-    answer: &Answer,
-    locales: &LocaleInfo,
-    runtime_info: &RuntimeInfo,
-    udevadm_info: &UdevInfo,
-    setup_info: &SetupInfo,
+    config: &InstallConfig,
+    mut reporter: Option<&mut ProgressReporter>,
 ) -> Result<()> {
-    let config = parse_answer(answer, udevadm_info, runtime_info, locales, setup_info)?;
     info!("Calling low-level installer");
 
     let mut child = match spawn_low_level_installer(false) {
@@ -134,7 +400,7 @@ fn run_installation(
             .take()
             .ok_or(format_err!("failed to get stdin writer"))?;
 
-        serde_json::to_writer(&mut writer, &config)
+        serde_json::to_writer(&mut writer, config)
             .map_err(|err| format_err!("failed to serialize install config: {err}"))?;
         writeln!(writer).map_err(|err| format_err!("failed to write install config: {err}"))?;
 
@@ -147,10 +413,15 @@ fn run_installation(
                 Ok(msg) => msg,
                 Err(_) => {
                     // Not a fatal error, so don't abort the installation by returning
+                    debug!("low-level installer: {line}");
                     continue;
                 }
             };
 
+            if let Some(reporter) = reporter.as_mut() {
+                reporter.report(&msg);
+            }
+
             match msg.clone() {
                 LowLevelMessage::Info { message } => info!("{message}"),
                 LowLevelMessage::Error { message } => error!("{message}"),
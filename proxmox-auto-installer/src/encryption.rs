@@ -0,0 +1,203 @@
+//! Encryption of answer files with a passphrase.
+//!
+//! An encrypted answer file keeps its usual file name, e.g. `answer.toml`, so that it can be used
+//! with any fetch-from mode, and is recognized by its armor:
+//!
+//! ```text
+//! -----BEGIN PROXMOX ENCRYPTED ANSWER-----
+//! AQAJJ8C9...
+//! -----END PROXMOX ENCRYPTED ANSWER-----
+//! ```
+//!
+//! The armored data consists of a version byte, the PBKDF2-HMAC-SHA256 iteration count as 32-bit
+//! big-endian integer, a 16 byte salt, a 12 byte nonce and the AES-256-GCM encrypted answer file,
+//! with all fields before the encrypted data used as additional authenticated data.
+
+use anyhow::{bail, format_err, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    num::NonZeroU32,
+    process::Command,
+};
+
+const ARMOR_BEGIN: &str = "-----BEGIN PROXMOX ENCRYPTED ANSWER-----";
+const ARMOR_END: &str = "-----END PROXMOX ENCRYPTED ANSWER-----";
+
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 1 + 4 + SALT_LEN + NONCE_LEN;
+
+/// PBKDF2 iterations for new encryptions, as recommended by OWASP for HMAC-SHA256.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+/// Upper limit for the iterations of an encrypted answer file, to not wait forever on broken ones.
+const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+
+/// Returns whether the content is an encrypted answer file.
+pub fn is_encrypted(content: &str) -> bool {
+    content.trim_start().starts_with(ARMOR_BEGIN)
+}
+
+/// Encrypts the answer file with a key derived from the passphrase, returning the armored result.
+pub fn encrypt(answer: &str, passphrase: &str) -> Result<String> {
+    encrypt_with_iterations(answer, passphrase, PBKDF2_ITERATIONS)
+}
+
+fn encrypt_with_iterations(answer: &str, passphrase: &str, iterations: u32) -> Result<String> {
+    if passphrase.is_empty() {
+        bail!("passphrase must not be empty");
+    }
+
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| format_err!("failed to generate random salt and nonce"))?;
+
+    let mut data = Vec::with_capacity(HEADER_LEN + answer.len() + AES_256_GCM.tag_len());
+    data.push(VERSION);
+    data.extend_from_slice(&iterations.to_be_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, iterations)?;
+    let mut encrypted = answer.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(&data[..]),
+        &mut encrypted,
+    )
+    .map_err(|_| format_err!("failed to encrypt answer file"))?;
+    data.extend_from_slice(&encrypted);
+
+    let encoded = BASE64.encode(data);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(64)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect();
+
+    Ok(format!(
+        "{ARMOR_BEGIN}\n{}\n{ARMOR_END}\n",
+        lines.join("\n")
+    ))
+}
+
+/// Decrypts an armored answer file with the passphrase.
+pub fn decrypt(content: &str, passphrase: &str) -> Result<String> {
+    let mut lines = content.lines().map(str::trim);
+    if !lines.any(|line| line == ARMOR_BEGIN) {
+        bail!("not an encrypted answer file");
+    }
+    let encoded: String = lines.take_while(|line| *line != ARMOR_END).collect();
+    let data = BASE64
+        .decode(encoded)
+        .map_err(|err| format_err!("failed to decode encrypted answer file - {err}"))?;
+
+    if data.len() < HEADER_LEN + AES_256_GCM.tag_len() {
+        bail!("encrypted answer file is truncated");
+    }
+    let (header, encrypted) = data.split_at(HEADER_LEN);
+    if header[0] != VERSION {
+        bail!("unsupported encrypted answer file version {}", header[0]);
+    }
+    let iterations = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    if iterations > MAX_PBKDF2_ITERATIONS {
+        bail!("too many key derivation iterations: {iterations}");
+    }
+    let salt = &header[5..5 + SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&header[5 + SALT_LEN..]);
+
+    let key = derive_key(passphrase, salt, iterations)?;
+    let mut encrypted = encrypted.to_vec();
+    let answer = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(header),
+            &mut encrypted,
+        )
+        .map_err(|_| format_err!("wrong passphrase or corrupted answer file"))?;
+
+    Ok(String::from_utf8(answer.to_vec())?)
+}
+
+/// Asks for a passphrase on the controlling terminal, without echoing it. Works even if stdout is
+/// redirected, as for `proxmox-fetch-answer`.
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|err| format_err!("no terminal to ask for the passphrase - {err}"))?;
+    write!(tty, "{prompt}")?;
+
+    let stty = |arg: &str, tty: &std::fs::File| {
+        tty.try_clone()
+            .and_then(|stdin| Command::new("stty").arg(arg).stdin(stdin).status())
+            .is_ok_and(|status| status.success())
+    };
+    let echo_disabled = stty("-echo", &tty);
+
+    let mut passphrase = String::new();
+    let result = BufReader::new(&tty).read_line(&mut passphrase);
+    if echo_disabled {
+        stty("echo", &tty);
+    }
+    writeln!(tty)?;
+    result?;
+
+    Ok(passphrase.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| format_err!("invalid iteration count 0"))?;
+
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| format_err!("invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANSWER: &str = "[global]\nroot_password = \"123456\"\n";
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let encrypted = encrypt_with_iterations(ANSWER, "correct horse", 1000).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(ANSWER));
+        assert!(!encrypted.contains("123456"));
+        assert!(encrypted.lines().all(|line| line.len() <= 64));
+
+        assert_eq!(decrypt(&encrypted, "correct horse").unwrap(), ANSWER);
+        assert!(decrypt(&encrypted, "wrong horse").is_err());
+
+        // the header is authenticated too
+        let lines: Vec<&str> = encrypted.lines().collect();
+        let mut data = BASE64.decode(lines[1..lines.len() - 1].concat()).unwrap();
+        data[1..5].copy_from_slice(&1001u32.to_be_bytes());
+        let tampered = format!("{ARMOR_BEGIN}\n{}\n{ARMOR_END}\n", BASE64.encode(data));
+        assert!(decrypt(&tampered, "correct horse").is_err());
+
+        assert!(encrypt(ANSWER, "").is_err());
+    }
+}
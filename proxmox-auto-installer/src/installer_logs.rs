@@ -0,0 +1,224 @@
+//! Collection of the installer logs, to be able to diagnose failed installations.
+//!
+//! On a successful installation, the low-level installer copies the logs into
+//! `/var/log/proxmox-installer/` on the target. If the installation fails, they are uploaded to the
+//! endpoint configured in the `log-upload` section of the answer file, or written to the
+//! 'proxmox-ais' partition if it is writable. The latter is skipped for encrypted answer files, as
+//! the logs would disclose the answer file on the unencrypted partition.
+
+use anyhow::{bail, format_err, Result};
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use proxmox_installer_common::{answer::LogUploadInfo, http};
+
+use crate::sysinfo::SysInfo;
+
+/// File name of the redacted answer file in the runtime directory, from where the low-level
+/// installer copies it into the installed system.
+pub const ANSWER_FILE: &str = "answer.toml";
+
+/// Logs of the live environment, as written by the individual installer components.
+const LOG_FILES: &[&str] = &[
+    "/tmp/fetch_answer.log",
+    "/tmp/auto_installer.log",
+    "/tmp/install-low-level-start-session.log",
+];
+/// Marker file created by `proxmox-fetch-answer` if the answer file or lookup table was encrypted.
+pub const ENCRYPTED_ANSWER_MARKER: &str = "/tmp/answer_encrypted";
+
+/// Config as passed to the low-level installer, contains the root password.
+const LOW_LEVEL_CONFIG: &str = "/tmp/low-level-config.json";
+
+/// Mount point of the 'proxmox-ais' partition, as used by `proxmox-fetch-answer`
+pub const ANSWER_PARTITION_MP: &str = "/mnt/answer";
+/// Directory on the 'proxmox-ais' partition to store the logs of failed installations in
+const PARTITION_LOG_DIR: &str = "proxmox-installer-logs";
+
+const REDACTED: &str = "<redacted>";
+/// Keys of the `global` section of the answer file containing secrets.
+const SECRET_ANSWER_KEYS: &[&str] = &["root_password", "root_password_hashed"];
+
+/// Removes all secrets from an answer file, so that it can be stored alongside the logs.
+pub fn redact_answer(answer: &str) -> Result<String> {
+    let mut answer: toml::Value = toml::from_str(answer)?;
+
+    if let Some(global) = answer.get_mut("global").and_then(|g| g.as_table_mut()) {
+        for key in SECRET_ANSWER_KEYS {
+            if let Some(secret) = global.get_mut(*key) {
+                *secret = toml::Value::String(REDACTED.to_owned());
+            }
+        }
+    }
+
+    Ok(toml::to_string(&answer)?)
+}
+
+/// Returns whether the answer file was encrypted, see [`ENCRYPTED_ANSWER_MARKER`].
+pub fn answer_was_encrypted() -> bool {
+    Path::new(ENCRYPTED_ANSWER_MARKER).exists()
+}
+
+/// Removes all secrets from the low-level installer config, given as JSON.
+pub fn redact_config(config: &str) -> Result<String> {
+    let mut config: Value = serde_json::from_str(config)?;
+
+    if let Some(Value::Object(password)) = config.get_mut("root_password") {
+        for value in password.values_mut() {
+            *value = Value::String(REDACTED.to_owned());
+        }
+    }
+
+    Ok(serde_json::to_string_pretty(&config)?)
+}
+
+/// The installer logs of a failed installation, by file name.
+#[derive(Serialize)]
+pub struct InstallerLogs {
+    uuid: Option<String>,
+    logs: BTreeMap<String, String>,
+}
+
+impl InstallerLogs {
+    /// Collects all logs available in the live environment, together with the redacted answer
+    /// file and low-level installer config.
+    pub fn collect(runtime_dir: &Path) -> Self {
+        let mut logs = BTreeMap::new();
+
+        let answer_path = runtime_dir.join(ANSWER_FILE);
+        for path in LOG_FILES
+            .iter()
+            .map(Path::new)
+            .chain([answer_path.as_path()])
+        {
+            if let (Some(name), Ok(content)) = (path.file_name(), fs::read_to_string(path)) {
+                logs.insert(name.to_string_lossy().into_owned(), content);
+            }
+        }
+
+        if let Ok(config) = fs::read_to_string(LOW_LEVEL_CONFIG) {
+            let config = redact_config(&config)
+                .unwrap_or_else(|err| format!("failed to redact config: {err}"));
+            logs.insert("low-level-config.json".to_owned(), config);
+        }
+
+        let uuid = SysInfo::get()
+            .ok()
+            .and_then(|sysinfo| sysinfo.system_uuid().map(str::to_owned));
+
+        Self { uuid, logs }
+    }
+
+    /// Uploads the logs as JSON object via a POST request.
+    pub fn upload(&self, info: &LogUploadInfo) -> Result<()> {
+        info!("Uploading installer logs to '{}'.", info.url);
+        let payload = serde_json::to_string(self)?;
+        http::post(&info.url, info.cert_fingerprint.as_deref(), payload)?;
+        Ok(())
+    }
+
+    /// Writes the logs to a new directory on the 'proxmox-ais' partition, returning its path.
+    pub fn store_on_partition(&self) -> Result<PathBuf> {
+        mount_answer_partition(true)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let name = match &self.uuid {
+            Some(uuid) => format!("{uuid}-{timestamp}"),
+            None => timestamp.to_string(),
+        };
+        let dir = Path::new(ANSWER_PARTITION_MP)
+            .join(PARTITION_LOG_DIR)
+            .join(name);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format_err!("failed to create directory {dir:?}: {err}"))?;
+
+        for (name, content) in &self.logs {
+            let path = dir.join(name);
+            fs::write(&path, content)
+                .map_err(|err| format_err!("failed to write {path:?}: {err}"))?;
+        }
+        run_cmd("sync", &[])?;
+
+        Ok(dir)
+    }
+}
+
+/// Mounts the 'proxmox-ais' partition, unless `proxmox-fetch-answer` already left it mounted
+/// while fetching the answer file.
+///
+/// With `writable`, the partition is mounted read-write, and an existing read-only mount is
+/// remounted accordingly.
+pub fn mount_answer_partition(writable: bool) -> Result<()> {
+    let mounts = fs::read_to_string("/proc/mounts")?;
+    let mounted = mounts
+        .lines()
+        .any(|line| line.split(' ').nth(1) == Some(ANSWER_PARTITION_MP));
+
+    if mounted {
+        if writable {
+            return run_cmd("mount", &["-o", "remount,rw", ANSWER_PARTITION_MP]);
+        }
+        return Ok(());
+    }
+
+    // FAT labels are usually upper case, so check both variants
+    let device = ["PROXMOX-AIS", "proxmox-ais"]
+        .iter()
+        .map(|label| Path::new("/dev/disk/by-label").join(label))
+        .find(|path| path.exists())
+        .ok_or_else(|| format_err!("no 'proxmox-ais' partition found"))?;
+
+    fs::create_dir_all(ANSWER_PARTITION_MP)?;
+    let options = if writable { "rw" } else { "ro" };
+    run_cmd(
+        "mount",
+        &[
+            "-o",
+            options,
+            &device.to_string_lossy(),
+            ANSWER_PARTITION_MP,
+        ],
+    )
+}
+
+fn run_cmd(cmd: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(cmd).args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "{cmd} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_secrets() {
+        let answer = "[global]\nfqdn = \"pve.example.com\"\nroot_password = \"123456\"\n";
+        let redacted = redact_answer(answer).unwrap();
+        assert!(!redacted.contains("123456"));
+        assert!(redacted.contains("pve.example.com"));
+
+        let answer = "[global]\nroot_password_hashed = \"$6$salt$hash\"\n";
+        let redacted = redact_answer(answer).unwrap();
+        assert!(!redacted.contains("$6$salt$hash"));
+        assert!(redacted.contains("root_password_hashed = \"<redacted>\""));
+
+        let config = r#"{"hostname": "pve", "root_password": {"plain": "123456"}}"#;
+        let redacted = redact_config(config).unwrap();
+        assert!(!redacted.contains("123456"));
+        assert!(redacted.contains("\"plain\": \"<redacted>\""));
+    }
+}
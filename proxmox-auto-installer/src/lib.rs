@@ -1,5 +1,11 @@
-pub mod answer;
+pub mod encryption;
+pub mod installer_logs;
 pub mod log;
+pub mod post_hook;
+pub mod progress;
+pub mod signature;
+pub mod summary;
 pub mod sysinfo;
-pub mod udevinfo;
+pub mod template;
 pub mod utils;
+pub mod validate;
@@ -1,46 +1,187 @@
+//! Logger used by the auto-installer and `proxmox-fetch-answer`, writing to both stderr and a log
+//! file.
+//!
+//! The output can be switched to JSON lines, each with a timestamp, the level, the module, the
+//! current install phase and the message, e.g. for ingesting them with a log shipper. Both format
+//! and level can be set on the kernel command line:
+//!
+//! ```text
+//! proxmox-auto-installer.log-format=json proxmox-auto-installer.log-level=debug
+//! ```
+//!
+//! The format must be known before the first line is written. If no level is set on the kernel
+//! command line, it can be set later on, e.g. from the `log_level` answer file option.
+
 use anyhow::{bail, Result};
-use log::{Level, Metadata, Record};
-use std::{fs::File, io::Write, sync::Mutex, sync::OnceLock};
+use log::{Level, LevelFilter, Metadata, Record};
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    io::Write,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CMDLINE_LOG_FORMAT_PARAM: &str = "proxmox-auto-installer.log-format=";
+const CMDLINE_LOG_LEVEL_PARAM: &str = "proxmox-auto-installer.log-level=";
 
 pub struct AutoInstLogger;
 static LOGFILE: OnceLock<Mutex<File>> = OnceLock::new();
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+/// Set if the level was given on the kernel command line, which takes precedence
+static CMDLINE_LEVEL: AtomicBool = AtomicBool::new(false);
+static PHASE: Mutex<&str> = Mutex::new("setup");
+/// Set once writing to the log file failed, to only report it once on stderr
+static LOGFILE_FAILED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: &'a str,
+    module: &'a str,
+    phase: &'a str,
+    message: String,
+}
 
 impl AutoInstLogger {
-    pub fn init(path: &str) -> Result<()> {
-        let f = File::create(path)?;
-        if LOGFILE.set(Mutex::new(f)).is_err() {
-            bail!("Cannot set LOGFILE")
+    /// Opens the log file and applies the format and level from the kernel command line,
+    /// returning the level to pass to [`log::set_max_level`].
+    ///
+    /// If the log file cannot be created, only logs to stderr.
+    pub fn init(path: &str) -> Result<LevelFilter> {
+        match File::create(path) {
+            Ok(f) => {
+                if LOGFILE.set(Mutex::new(f)).is_err() {
+                    bail!("Cannot set LOGFILE")
+                }
+            }
+            Err(err) => eprintln!("{}: could not create log file '{path}': {err}", Level::Warn),
+        }
+
+        let cmdline = fs::read_to_string("/proc/cmdline").unwrap_or_default();
+        let param = |prefix: &str| {
+            cmdline
+                .split_whitespace()
+                .find_map(|param| param.strip_prefix(prefix))
+        };
+
+        if param(CMDLINE_LOG_FORMAT_PARAM) == Some("json") {
+            JSON_FORMAT.store(true, Ordering::Relaxed);
+        }
+        let level =
+            param(CMDLINE_LOG_LEVEL_PARAM).and_then(|level| LevelFilter::from_str(level).ok());
+        CMDLINE_LEVEL.store(level.is_some(), Ordering::Relaxed);
+
+        Ok(level.unwrap_or(LevelFilter::Info))
+    }
+
+    /// Sets the level, unless it was given explicitly on the kernel command line.
+    pub fn set_fallback_level(level: LevelFilter) {
+        if !CMDLINE_LEVEL.load(Ordering::Relaxed) {
+            log::set_max_level(level);
+        }
+    }
+
+    /// Sets the install phase included in each JSON log line, e.g. `fetch-answer` or
+    /// `installation`.
+    pub fn set_phase(phase: &'static str) {
+        if let Ok(mut current) = PHASE.lock() {
+            *current = phase;
+        }
+    }
+
+    fn format(record: &Record) -> String {
+        if !JSON_FORMAT.load(Ordering::Relaxed) {
+            return format!("{}: {}", record.level(), record.args());
         }
-        Ok(())
+
+        let phase = PHASE.lock().map(|phase| *phase).unwrap_or("unknown");
+        let json = JsonRecord {
+            timestamp: rfc3339_timestamp(SystemTime::now()),
+            level: record.level().as_str(),
+            module: record.module_path().unwrap_or_else(|| record.target()),
+            phase,
+            message: record.args().to_string(),
+        };
+        serde_json::to_string(&json)
+            .unwrap_or_else(|err| format!("{}: {} ({err})", record.level(), record.args()))
     }
 }
 
 impl log::Log for AutoInstLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
+        metadata.level() <= log::max_level()
     }
 
     /// Logs to both, stderr and into a log file
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            eprintln!("{}: {}", record.level(), record.args());
-            let mut file = LOGFILE
-                .get()
-                .expect("could not get LOGFILE")
-                .lock()
-                .expect("could not get mutex for LOGFILE");
-            writeln!(file, "{}: {}", record.level(), record.args())
-                .expect("could not write to LOGFILE");
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = Self::format(record);
+        eprintln!("{line}");
+
+        let result = match LOGFILE.get().map(|file| file.lock()) {
+            Some(Ok(mut file)) => writeln!(file, "{line}"),
+            _ => return,
+        };
+        // keep logging to stderr, losing the log file must not abort the installation
+        if let Err(err) = result {
+            if !LOGFILE_FAILED.swap(true, Ordering::Relaxed) {
+                eprintln!("{}: could not write to log file: {err}", Level::Warn);
+            }
         }
     }
 
     fn flush(&self) {
-        LOGFILE
-            .get()
-            .expect("could not get LOGFILE")
-            .lock()
-            .expect("could not get mutex for LOGFILE")
-            .flush()
-            .expect("could not flush LOGFILE");
+        if let Some(Ok(mut file)) = LOGFILE.get().map(|file| file.lock()) {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Formats the time as RFC 3339 timestamp in UTC, with millisecond precision.
+fn rfc3339_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (hour, min, sec) = ((secs / 3600) % 24, (secs / 60) % 60, secs % 60);
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}.{:03}Z",
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamp_format() {
+        assert_eq!(rfc3339_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rfc3339_timestamp(UNIX_EPOCH + Duration::from_millis(1_709_251_199_250)),
+            "2024-02-29T23:59:59.250Z"
+        );
+        assert_eq!(
+            rfc3339_timestamp(UNIX_EPOCH + Duration::from_secs(951_868_800)),
+            "2000-03-01T00:00:00.000Z"
+        );
     }
 }
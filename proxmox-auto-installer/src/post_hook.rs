@@ -0,0 +1,194 @@
+use anyhow::{bail, format_err, Result};
+use base64::{
+    engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD},
+    Engine as _,
+};
+use log::warn;
+use proxmox_installer_common::{
+    setup::{InstallConfig, RuntimeInfo},
+    RUNTIME_DIR,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use crate::sysinfo::SysInfo;
+
+/// File the low-level installer places the public SSH host keys of the installed system in.
+static SSH_HOST_KEYS_FILE: &str = "ssh-host-keys.json";
+
+/// Information about the finished installation, sent as JSON body to the post-installation
+/// webhook defined in the answer file.
+#[derive(Debug, Serialize)]
+pub struct PostHookInfo {
+    /// Same information as sent when fetching the answer file via HTTP
+    system_info: SysInfo,
+    /// Public SSH host keys of the installed system, along with their fingerprints
+    ssh_host_keys: Vec<SshHostKey>,
+    /// The file system used for the root file system
+    filesystem: String,
+    /// Disks the system was installed on
+    disks: Vec<PostHookDisk>,
+    /// Network configuration of the management interface
+    network: PostHookNetwork,
+}
+
+#[derive(Debug, Serialize)]
+struct SshHostKey {
+    /// Key type, e.g. `ssh-ed25519`
+    key_type: String,
+    /// Public key in the OpenSSH format
+    public_key: String,
+    /// SHA256 fingerprint, in the same format as printed by `ssh-keygen -l`
+    fingerprint: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PostHookDisk {
+    path: String,
+    model: Option<String>,
+    /// Size in GiB
+    size: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct PostHookNetwork {
+    interface: String,
+    mac: Option<String>,
+    hostname: String,
+    domain: String,
+    cidr: String,
+    gateway: String,
+    cidr6: Option<String>,
+    gateway6: Option<String>,
+    dns: Vec<String>,
+}
+
+impl PostHookInfo {
+    /// Gathers all information about the installed system, based on the configuration that was
+    /// passed to the low-level installer.
+    pub fn gather(config: &InstallConfig, runtime_info: &RuntimeInfo) -> Result<Self> {
+        let disks = if let Some(disk) = &config.target_hd {
+            vec![disk]
+        } else {
+            config
+                .disk_selection
+                .values()
+                .filter_map(|index| runtime_info.disks.iter().find(|d| &d.index == index))
+                .collect()
+        };
+
+        let mac = runtime_info
+            .network
+            .interfaces
+            .get(&config.mngmt_nic)
+            .map(|iface| iface.mac.clone());
+
+        Ok(Self {
+            system_info: SysInfo::get()?,
+            ssh_host_keys: Self::gather_ssh_host_keys(),
+            filesystem: config.filesys.to_string(),
+            disks: disks
+                .into_iter()
+                .map(|disk| PostHookDisk {
+                    path: disk.path.clone(),
+                    model: disk.model.clone(),
+                    size: disk.size,
+                })
+                .collect(),
+            network: PostHookNetwork {
+                interface: config.mngmt_nic.clone(),
+                mac,
+                hostname: config.hostname.clone(),
+                domain: config.domain.clone(),
+                cidr: config.cidr.to_string(),
+                gateway: config.gateway.to_string(),
+                cidr6: config.cidr6.as_ref().map(|cidr| cidr.to_string()),
+                gateway6: config.gateway6.map(|gateway| gateway.to_string()),
+                dns: std::iter::once(&config.dns)
+                    .chain(&config.dns_additional)
+                    .map(|dns| dns.to_string())
+                    .collect(),
+            },
+        })
+    }
+
+    /// Reads the public SSH host keys as saved by the low-level installer. Missing keys are not
+    /// fatal, as the rest of the information is still useful.
+    fn gather_ssh_host_keys() -> Vec<SshHostKey> {
+        let path = PathBuf::from(RUNTIME_DIR).join(SSH_HOST_KEYS_FILE);
+        let keys: BTreeMap<String, String> = match fs::read_to_string(&path) {
+            Ok(raw) => match serde_json::from_str(&raw) {
+                Ok(keys) => keys,
+                Err(err) => {
+                    warn!("Failed to parse {path:?}: {err}");
+                    return vec![];
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                warn!("No SSH host keys found at {path:?}");
+                return vec![];
+            }
+            Err(err) => {
+                warn!("Failed to read {path:?}: {err}");
+                return vec![];
+            }
+        };
+
+        keys.values()
+            .filter_map(|public_key| match ssh_key_fingerprint(public_key) {
+                Ok((key_type, fingerprint)) => Some(SshHostKey {
+                    key_type,
+                    public_key: public_key.clone(),
+                    fingerprint,
+                }),
+                Err(err) => {
+                    warn!("Skipping SSH host key '{public_key}': {err}");
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Calculates the SHA256 fingerprint of a public SSH key in the OpenSSH format, e.g.
+/// `ssh-ed25519 AAAA... root@host`.
+///
+/// Returns the key type along with the fingerprint.
+fn ssh_key_fingerprint(public_key: &str) -> Result<(String, String)> {
+    let mut parts = public_key.split_ascii_whitespace();
+    let (key_type, key) = match (parts.next(), parts.next()) {
+        (Some(key_type), Some(key)) => (key_type, key),
+        _ => bail!("not a valid OpenSSH public key"),
+    };
+
+    let decoded = BASE64
+        .decode(key)
+        .map_err(|err| format_err!("failed to decode public key - {err}"))?;
+    let digest = Sha256::digest(decoded);
+
+    Ok((
+        key_type.to_owned(),
+        format!("SHA256:{}", BASE64_NO_PAD.encode(digest)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_fingerprint() {
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIL7F3aDBalBMA8MDM9Kwk2SLACWm2xz7DMSuFh7d9zif root@pveauto";
+        assert_eq!(
+            ssh_key_fingerprint(key).unwrap(),
+            (
+                "ssh-ed25519".to_owned(),
+                "SHA256:hSLlfVpOUS+sVSvGD13r9hFhmWYZqq1jSAALNtSqANE".to_owned()
+            )
+        );
+
+        assert!(ssh_key_fingerprint("ssh-ed25519").is_err());
+        assert!(ssh_key_fingerprint("ssh-ed25519 !invalid!").is_err());
+    }
+}
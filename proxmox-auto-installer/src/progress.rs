@@ -0,0 +1,251 @@
+//! Reporting of the installation progress to a remote endpoint.
+//!
+//! Every message of the low-level installer is sent as JSON event, tagged with the DMI UUID, the
+//! MAC address of the management interface and the hostname, so that the events of many parallel
+//! installations can be told apart. The events are sent from a background thread, a slow or
+//! unreachable endpoint never blocks the installation itself.
+
+use anyhow::{format_err, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    net::{ToSocketAddrs, UdpSocket},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use proxmox_installer_common::{
+    answer::{Answer, ProgressReportTarget},
+    http,
+    setup::{InstallConfig, RuntimeInfo},
+};
+
+use crate::{sysinfo::SysInfo, utils::LowLevelMessage};
+
+/// Default port for syslog via UDP.
+const SYSLOG_PORT: u16 = 514;
+/// Number of attempts for sending the final event, which carries the result of the installation.
+const FINISHED_ATTEMPTS: u32 = 3;
+/// Delay between two attempts of sending the final event.
+const FINISHED_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A single progress event, as sent to the endpoint.
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    uuid: Option<&'a str>,
+    mac: Option<&'a str>,
+    hostname: &'a str,
+    #[serde(flatten)]
+    message: &'a LowLevelMessage,
+}
+
+enum Sink {
+    Http { url: String, client: http::Client },
+    Syslog { socket: UdpSocket, hostname: String },
+}
+
+impl Sink {
+    fn send(&self, message: &LowLevelMessage, event: String) -> Result<()> {
+        match self {
+            Sink::Http { url, client } => {
+                client.post(url, event)?;
+            }
+            Sink::Syslog { socket, hostname } => {
+                // facility 'user', severity 'error' for failures and 'informational' otherwise
+                let priority = match message {
+                    LowLevelMessage::Error { .. } => 11,
+                    LowLevelMessage::Finished { state, .. } if state == "err" => 11,
+                    _ => 14,
+                };
+                let line =
+                    format!("<{priority}>1 - {hostname} proxmox-auto-installer - - - {event}");
+                socket.send(line.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends the messages of the low-level installer to the configured progress-report target.
+pub struct ProgressReporter {
+    uuid: Option<String>,
+    mac: Option<String>,
+    hostname: String,
+    sender: Option<mpsc::Sender<(LowLevelMessage, String)>>,
+    worker: Option<thread::JoinHandle<()>>,
+    finished: bool,
+}
+
+impl ProgressReporter {
+    /// Sets up reporting to the target configured in the answer file, if any.
+    ///
+    /// This happens before the answer is fully parsed, so that failures while parsing it get
+    /// reported too. Until the management interface is known from the final configuration, see
+    /// [`Self::set_config`], the MAC address of the interface with the default route is used.
+    pub fn new(answer: &Answer, runtime_info: &RuntimeInfo) -> Result<Option<Self>> {
+        let info = match &answer.progress_report {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        let hostname = answer.global.fqdn.to_string();
+
+        let sink = match info.target {
+            ProgressReportTarget::Http => Sink::Http {
+                url: info.url.clone().ok_or_else(|| format_err!("no URL set"))?,
+                client: http::Client::new(
+                    info.cert_fingerprint.as_deref(),
+                    http::PostOptions::default(),
+                )?,
+            },
+            ProgressReportTarget::Syslog => {
+                let host = info
+                    .host
+                    .as_deref()
+                    .ok_or_else(|| format_err!("no host set"))?;
+                let addr = host
+                    .to_socket_addrs()
+                    .or_else(|_| (host, SYSLOG_PORT).to_socket_addrs())
+                    .map_err(|err| format_err!("failed to resolve '{host}': {err}"))?
+                    .next()
+                    .ok_or_else(|| format_err!("no address found for '{host}'"))?;
+
+                let bind_addr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind_addr)?;
+                socket.connect(addr)?;
+                Sink::Syslog {
+                    socket,
+                    hostname: hostname.clone(),
+                }
+            }
+        };
+
+        let uuid = SysInfo::get()?.system_uuid().map(str::to_owned);
+        let mac = runtime_info
+            .network
+            .routes
+            .as_ref()
+            .and_then(|routes| routes.gateway4.as_ref().or(routes.gateway6.as_ref()))
+            .and_then(|gateway| runtime_info.network.interfaces.get(&gateway.dev))
+            .map(|iface| iface.mac.clone());
+
+        let (sender, receiver) = mpsc::channel::<(LowLevelMessage, String)>();
+        let worker = thread::spawn(move || {
+            for (message, event) in receiver {
+                // the final event carries the result, so try a bit harder to get it through
+                let attempts = match message {
+                    LowLevelMessage::Finished { .. } => FINISHED_ATTEMPTS,
+                    _ => 1,
+                };
+                for attempt in 1..=attempts {
+                    match sink.send(&message, event.clone()) {
+                        Ok(()) => break,
+                        Err(err) => warn!("Failed to report installation progress: {err}"),
+                    }
+                    if attempt < attempts {
+                        thread::sleep(FINISHED_RETRY_DELAY);
+                    }
+                }
+            }
+        });
+
+        info!("Reporting installation progress of host '{hostname}'");
+        Ok(Some(Self {
+            uuid,
+            mac,
+            hostname,
+            sender: Some(sender),
+            worker: Some(worker),
+            finished: false,
+        }))
+    }
+
+    /// Tags all further events with the MAC address of the management interface from the final
+    /// configuration.
+    pub fn set_config(&mut self, config: &InstallConfig, runtime_info: &RuntimeInfo) {
+        if let Some(iface) = runtime_info.network.interfaces.get(&config.mngmt_nic) {
+            self.mac = Some(iface.mac.clone());
+        }
+    }
+
+    /// Queues a message of the low-level installer for sending.
+    pub fn report(&mut self, message: &LowLevelMessage) {
+        if let LowLevelMessage::Finished { .. } = message {
+            self.finished = true;
+        }
+
+        let event = ProgressEvent {
+            uuid: self.uuid.as_deref(),
+            mac: self.mac.as_deref(),
+            hostname: &self.hostname,
+            message,
+        };
+        let event = match serde_json::to_string(&event) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("Failed to serialize progress event: {err}");
+                return;
+            }
+        };
+
+        if let Some(sender) = &self.sender {
+            // the worker keeps receiving until the sender is dropped in `finish`
+            let _ = sender.send((message.clone(), event));
+        }
+    }
+
+    /// Reports a failed installation, unless the low-level installer already reported its final
+    /// state itself.
+    pub fn report_failure(&mut self, message: &str) {
+        if !self.finished {
+            self.report(&LowLevelMessage::Finished {
+                state: "err".to_owned(),
+                message: message.to_owned(),
+            });
+        }
+    }
+
+    /// Waits until all queued events have been sent.
+    pub fn finish(mut self) {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn event_format() {
+        let message = LowLevelMessage::Progress {
+            ratio: 0.5,
+            text: "extracting base system".to_owned(),
+        };
+        let event = ProgressEvent {
+            uuid: Some("4c4c4544-0042"),
+            mac: Some("aa:bb:cc:dd:ee:ff"),
+            hostname: "pve1.example.com",
+            message: &message,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "uuid": "4c4c4544-0042",
+                "mac": "aa:bb:cc:dd:ee:ff",
+                "hostname": "pve1.example.com",
+                "type": "progress",
+                "ratio": 0.5,
+                "text": "extracting base system",
+            }),
+        );
+    }
+}
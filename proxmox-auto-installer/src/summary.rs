@@ -0,0 +1,80 @@
+//! Human-readable summary of an installation, shown before the disks get wiped.
+
+use proxmox_installer_common::{
+    options::Disk,
+    setup::{InstallConfig, RuntimeInfo},
+    udevinfo::UdevInfo,
+};
+
+/// Summarizes the resolved installation config, listing the target disks with model and serial,
+/// the filesystem and the management network configuration.
+pub fn installation_summary(
+    config: &InstallConfig,
+    runtime_info: &RuntimeInfo,
+    udev_info: &UdevInfo,
+) -> String {
+    let mut entries: Vec<(&str, String)> = vec![("Filesystem", config.filesys.to_string())];
+
+    let disks: Vec<&Disk> = match &config.target_hd {
+        Some(disk) => vec![disk],
+        None => config
+            .disk_selection
+            .values()
+            .filter_map(|index| runtime_info.disks.iter().find(|d| &d.index == index))
+            .collect(),
+    };
+    for disk in disks {
+        let serial = udev_info
+            .disks
+            .get(&disk.index)
+            .and_then(|props| props.get("ID_SERIAL_SHORT").or(props.get("ID_SERIAL")));
+
+        let mut details = vec![];
+        if let Some(model) = &disk.model {
+            details.push(model.trim().to_owned());
+        }
+        if let Some(serial) = serial {
+            details.push(format!("serial {serial}"));
+        }
+        details.push(format!("{:.2} GiB", disk.size));
+
+        entries.push(("Disk", format!("{} ({})", disk.path, details.join(", "))));
+    }
+
+    let nic = match runtime_info.network.interfaces.get(&config.mngmt_nic) {
+        Some(iface) => format!("{} ({})", config.mngmt_nic, iface.mac),
+        None => config.mngmt_nic.clone(),
+    };
+    entries.push(("Interface", nic));
+    if let Some(vlan) = config.mngmt_vlan {
+        entries.push(("VLAN", vlan.to_string()));
+    }
+
+    entries.push(("Hostname", format!("{}.{}", config.hostname, config.domain)));
+    entries.push(("Address", format!("{} via {}", config.cidr, config.gateway)));
+    if let (Some(cidr6), Some(gateway6)) = (&config.cidr6, config.gateway6) {
+        entries.push(("Address", format!("{cidr6} via {gateway6}")));
+    }
+
+    let dns: Vec<String> = std::iter::once(&config.dns)
+        .chain(&config.dns_additional)
+        .map(|ip| ip.to_string())
+        .collect();
+    entries.push(("DNS", dns.join(", ")));
+
+    let width = entries
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    entries
+        .iter()
+        .map(|(label, value)| {
+            format!(
+                "  {:width$}  {value}\n",
+                format!("{label}:"),
+                width = width + 1
+            )
+        })
+        .collect()
+}
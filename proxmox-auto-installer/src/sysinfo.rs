@@ -38,6 +38,16 @@ impl SysInfo {
         })
     }
 
+    /// The DMI system UUID, if available.
+    pub fn system_uuid(&self) -> Option<&str> {
+        self.dmi.system.get("uuid").map(String::as_str)
+    }
+
+    /// The MAC addresses of all network interfaces.
+    pub fn mac_addresses(&self) -> impl Iterator<Item = &str> {
+        self.network_interfaces.iter().map(|nic| nic.mac.as_str())
+    }
+
     pub fn as_json_pretty() -> Result<String> {
         let info = Self::get()?;
         Ok(serde_json::to_string_pretty(&info)?)
@@ -0,0 +1,264 @@
+//! Rendering of answer file templates.
+//!
+//! An answer file marked as template by a top-level `template = true`, before the first table,
+//! can contain placeholders in the form of `{{ name }}`, which are replaced before the answer file
+//! is parsed. The marker itself is removed on rendering. Available are:
+//!
+//! * all fields of the system information, see [`SysInfo`], e.g. `dmi.system.serial` or
+//!   `dmi.system.uuid`, with the MAC addresses of all network interfaces as `mac.<link>`
+//! * the columns of the row in a lookup table matching this host, as `host.<column>`
+//!
+//! A lookup table is a CSV file with a header row. The first column names the variable to match
+//! the hosts by, e.g. `dmi.system.serial`, the other columns can be freely chosen:
+//!
+//! ```text
+//! dmi.system.serial,hostname,cidr
+//! CZ1234,pve1.example.com,192.168.1.11/24
+//! CZ1235,pve2.example.com,192.168.1.12/24
+//! ```
+//!
+//! Values are escaped for TOML basic strings, so placeholders are meant to be used within
+//! double-quoted strings, e.g. `fqdn = "{{ host.hostname }}"`.
+
+use anyhow::{bail, format_err, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::sysinfo::SysInfo;
+
+/// Variables available for rendering a template, mapping the name to the value.
+pub type Variables = BTreeMap<String, String>;
+
+/// Top-level key marking an answer file as template.
+const TEMPLATE_KEY: &str = "template";
+
+/// Returns whether the answer file is marked as template, by a `template = true` before the first
+/// table. Other answer files are used as is, even if they contain `{{`.
+pub fn is_template(content: &str) -> bool {
+    content
+        .lines()
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .any(is_template_marker)
+}
+
+fn is_template_marker(line: &str) -> bool {
+    let line = match line.split_once('#') {
+        Some((line, _comment)) => line,
+        None => line,
+    };
+    match line.split_once('=') {
+        Some((key, value)) => key.trim() == TEMPLATE_KEY && value.trim() == "true",
+        None => false,
+    }
+}
+
+/// Gathers the variables from the system information of this host.
+pub fn system_variables() -> Result<Variables> {
+    let sysinfo = serde_json::to_value(SysInfo::get()?)?;
+    Ok(variables_from_sysinfo(&sysinfo))
+}
+
+/// Gathers the variables from the system information, in the format as sent via HTTP when
+/// fetching an answer file.
+pub fn variables_from_sysinfo(sysinfo: &Value) -> Variables {
+    let mut variables = Variables::new();
+    flatten_object("", sysinfo, &mut variables);
+
+    if let Some(Value::Array(interfaces)) = sysinfo.get("network_interfaces") {
+        for interface in interfaces {
+            if let (Some(link), Some(mac)) = (interface["link"].as_str(), interface["mac"].as_str())
+            {
+                variables.insert(format!("mac.{link}"), mac.to_owned());
+            }
+        }
+    }
+
+    variables
+}
+
+fn flatten_object(prefix: &str, value: &Value, variables: &mut Variables) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_object(&key, value, variables);
+            }
+        }
+        Value::String(s) => {
+            variables.insert(prefix.to_owned(), s.clone());
+        }
+        Value::Bool(_) | Value::Number(_) => {
+            variables.insert(prefix.to_owned(), value.to_string());
+        }
+        // lists have no stable order to address their entries by
+        Value::Array(_) | Value::Null => {}
+    }
+}
+
+/// Adds the columns of the row matching this host in the lookup table as `host.<column>`.
+pub fn apply_lookup_table(contents: &str, variables: &mut Variables) -> Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|err| format_err!("failed to parse lookup table header - {err}"))?
+        .clone();
+    let key = match headers.get(0) {
+        Some(key) if !key.is_empty() => key,
+        _ => bail!("lookup table has no header row"),
+    };
+    let value = match variables.get(key) {
+        Some(value) => value.clone(),
+        None => bail!("lookup table key '{key}' is not a known variable"),
+    };
+
+    for record in reader.records() {
+        let record = record.map_err(|err| format_err!("failed to parse lookup table - {err}"))?;
+        if record.get(0) != Some(value.as_str()) {
+            continue;
+        }
+        for (column, field) in headers.iter().zip(record.iter()).skip(1) {
+            variables.insert(format!("host.{column}"), field.to_owned());
+        }
+        return Ok(());
+    }
+
+    bail!("no entry for {key} '{value}' in lookup table");
+}
+
+/// Replaces all placeholders in the template and removes the template marker.
+pub fn render(template: &str, variables: &Variables) -> Result<String> {
+    let mut in_tables = false;
+    let template: String = template
+        .split_inclusive('\n')
+        .filter(|line| {
+            in_tables |= line.trim_start().starts_with('[');
+            in_tables || !is_template_marker(line)
+        })
+        .collect();
+
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => bail!(
+                "unterminated placeholder '{}'",
+                rest[start..].lines().next().unwrap()
+            ),
+        };
+
+        let name = rest[start + 2..end].trim();
+        match variables.get(name) {
+            Some(value) => escape_value(value, &mut result),
+            None => bail!("unknown variable '{name}' in placeholder"),
+        }
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Escapes the value as content of a TOML basic string, so that values like a DMI serial cannot
+/// end the string or inject keys.
+fn escape_value(value: &str, result: &mut String) {
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variables() -> Variables {
+        variables_from_sysinfo(&json!({
+            "product": { "product": "pve", "enable_btrfs": true },
+            "dmi": { "system": { "serial": "CZ1235", "uuid": "abc" } },
+            "network_interfaces": [
+                { "link": "eno1", "mac": "aa:bb:cc:dd:ee:ff" },
+            ],
+        }))
+    }
+
+    #[test]
+    fn sysinfo_variables() {
+        let variables = variables();
+        assert_eq!(variables["dmi.system.serial"], "CZ1235");
+        assert_eq!(variables["product.enable_btrfs"], "true");
+        assert_eq!(variables["mac.eno1"], "aa:bb:cc:dd:ee:ff");
+    }
+
+    #[test]
+    fn render_with_lookup_table() {
+        let mut variables = variables();
+        let table = "dmi.system.serial, hostname, cidr\n\
+                     CZ1234, pve1.example.com, 192.168.1.11/24\n\
+                     CZ1235, pve2.example.com, 192.168.1.12/24\n";
+        apply_lookup_table(table, &mut variables).unwrap();
+
+        let template = "template = true\n\n[global]\n\
+                        fqdn = \"{{ host.hostname }}\"\ncidr = \"{{host.cidr}}\"\n";
+        assert!(is_template(template));
+        assert_eq!(
+            render(template, &variables).unwrap(),
+            "\n[global]\nfqdn = \"pve2.example.com\"\ncidr = \"192.168.1.12/24\"\n",
+        );
+    }
+
+    #[test]
+    fn template_marker() {
+        assert!(is_template(
+            "# rendered per host\ntemplate = true # marker\n[global]\n"
+        ));
+        assert!(!is_template("template = false\n[global]\n"));
+        assert!(!is_template("[global]\ntemplate = true\n"));
+        // plain answer files can contain braces, e.g. in an inline first-boot script
+        assert!(!is_template(
+            "[first-boot]\nscript = \"echo {{ not a placeholder }}\"\n"
+        ));
+    }
+
+    #[test]
+    fn render_escapes_values() {
+        let mut variables = variables();
+        variables.insert(
+            "dmi.system.serial".to_owned(),
+            "CZ\"\n[injected]\\\u{7}".to_owned(),
+        );
+
+        let rendered = render("serial = \"{{ dmi.system.serial }}\"\n", &variables).unwrap();
+        assert_eq!(rendered, "serial = \"CZ\\\"\\n[injected]\\\\\\u0007\"\n");
+
+        let value: toml::Value = toml::from_str(&rendered).unwrap();
+        assert_eq!(value["serial"].as_str(), Some("CZ\"\n[injected]\\\u{7}"));
+        assert!(value.get("injected").is_none());
+    }
+
+    #[test]
+    fn render_errors() {
+        let mut variables = variables();
+        assert!(render("fqdn = \"{{ host.hostname }}\"", &variables).is_err());
+        assert!(render("fqdn = \"{{ dmi.system.serial \"", &variables).is_err());
+        assert!(apply_lookup_table("dmi.system.serial,hostname\nCZ1,a\n", &mut variables).is_err());
+        assert!(apply_lookup_table("unknown,hostname\nCZ1235,a\n", &mut variables).is_err());
+    }
+}
@@ -1,20 +1,22 @@
 use anyhow::{bail, Context as _, Result};
 use clap::ValueEnum;
-use glob::Pattern;
 use log::info;
-use std::{collections::BTreeMap, process::Command};
+use std::{collections::BTreeMap, net::Ipv4Addr, process::Command};
 
-use crate::{
-    answer::{self, Answer},
-    udevinfo::UdevInfo,
-};
 use proxmox_installer_common::{
-    options::{FsType, NetworkOptions, ZfsChecksumOption, ZfsCompressOption},
+    answer::{self, Answer},
+    disk_checks::get_existing_disk_data,
+    options::{
+        Disk, FsType, NetworkAddressOptions, NetworkOptions, ZfsChecksumOption, ZfsCompressOption,
+    },
     setup::{
-        InstallConfig, InstallRootPassword, InstallZfsOption, LocaleInfo, RuntimeInfo, SetupInfo,
+        InstallBondConfig, InstallConfig, InstallFirstBootSetup, InstallRootPassword,
+        InstallVlanConfig, InstallZfsOption, LocaleInfo, RuntimeInfo, SetupInfo,
     },
+    udevinfo::{get_disks_from_list, get_matched_disks, get_single_udev_index, UdevInfo},
+    utils::CidrAddress,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 pub fn get_network_settings(
     answer: &Answer,
@@ -30,40 +32,180 @@ pub fn get_network_settings(
     network_options.fqdn = answer.global.fqdn.clone();
 
     if let answer::NetworkSettings::Manual(settings) = &answer.network.network_settings {
-        network_options.address = settings.cidr.clone();
-        network_options.dns_server = settings.dns;
-        network_options.gateway = settings.gateway;
-        network_options.ifname = get_single_udev_index(&settings.filter, &udev_info.nics)?;
+        network_options.ipv4.clone_from(&settings.ipv4);
+        network_options.ipv6.clone_from(&settings.ipv6);
+        network_options.dns_servers.clone_from(&settings.dns);
+        network_options.ifname = get_network_interface(&settings.interface, udev_info)?;
     }
     info!("Network interface used is '{}'", &network_options.ifname);
     Ok(network_options)
 }
 
-pub fn get_single_udev_index(
-    filter: &BTreeMap<String, String>,
-    udev_list: &BTreeMap<String, BTreeMap<String, String>>,
+/// Resolves an interface referenced in the answer file to its name.
+fn get_network_interface(
+    interface: &answer::NetworkInterface,
+    udev_info: &UdevInfo,
 ) -> Result<String> {
-    if filter.is_empty() {
-        bail!("no filter defined");
+    match interface {
+        answer::NetworkInterface::Filter(filter) => get_single_udev_index(filter, &udev_info.nics),
+        answer::NetworkInterface::Bond(name) => Ok(name.clone()),
     }
-    let mut dev_index: Option<String> = None;
-    'outer: for (dev, dev_values) in udev_list {
-        for (filter_key, filter_value) in filter {
-            let filter_pattern =
-                Pattern::new(filter_value).context("invalid glob in disk selection")?;
-            for (udev_key, udev_value) in dev_values {
-                if udev_key == filter_key && filter_pattern.matches(udev_value) {
-                    dev_index = Some(dev.clone());
-                    break 'outer; // take first match
-                }
+}
+
+/// Resolves the members of all bonds defined in the answer file to the physical network
+/// interfaces. Each interface can only be used once, either as bond member or as management
+/// interface.
+pub fn get_network_bonds(
+    answer: &Answer,
+    udev_info: &UdevInfo,
+    mngmt_nic: &str,
+) -> Result<Vec<InstallBondConfig>> {
+    let settings = match &answer.network.network_settings {
+        answer::NetworkSettings::Manual(settings) => settings,
+        answer::NetworkSettings::FromDhcp => return Ok(vec![]),
+    };
+
+    let mut used_nics = vec![];
+    let mut bonds = vec![];
+    for bond in &settings.bonds {
+        let mut members = vec![];
+        for filter in &bond.members {
+            let nic = get_single_udev_index(filter, &udev_info.nics)
+                .with_context(|| format!("bond '{}': no member found for {filter:?}", bond.name))?;
+            if nic == mngmt_nic {
+                bail!(
+                    "bond '{}': interface '{nic}' is already used as management interface",
+                    bond.name
+                );
+            }
+            if used_nics.contains(&nic) {
+                bail!(
+                    "bond '{}': interface '{nic}' is already used as bond member",
+                    bond.name
+                );
             }
+            used_nics.push(nic.clone());
+            members.push(nic);
         }
+        info!(
+            "Bond '{}' uses interfaces: {}",
+            bond.name,
+            members.join(", ")
+        );
+
+        bonds.push(InstallBondConfig {
+            name: bond.name.clone(),
+            mode: bond.mode,
+            members,
+            xmit_hash_policy: bond.xmit_hash_policy,
+        });
+    }
+
+    Ok(bonds)
+}
+
+/// Resolves the additional ports of the management bridge. Ports must neither be the management
+/// interface nor a bond member.
+fn set_network_bridge_ports(
+    answer: &Answer,
+    udev_info: &UdevInfo,
+    setup_info: &SetupInfo,
+    config: &mut InstallConfig,
+) -> Result<()> {
+    let settings = match &answer.network.network_settings {
+        answer::NetworkSettings::Manual(settings) if !settings.bridge_ports.is_empty() => settings,
+        _ => return Ok(()),
+    };
+    if !setup_info.config.bridged_network {
+        bail!(
+            "bridge ports are not supported for {}, it does not use a bridge for the management interface",
+            setup_info.config.fullname
+        );
     }
-    if dev_index.is_none() {
-        bail!("filter did not match any device");
+
+    for port in &settings.bridge_ports {
+        let name = get_network_interface(port, udev_info)
+            .with_context(|| format!("no bridge port found for {port:?}"))?;
+        if name == config.mngmt_nic {
+            bail!("bridge port '{name}' is already used as management interface");
+        }
+        if is_bond_member(&config.bonds, &name) {
+            bail!("bridge port '{name}' is already used as bond member");
+        }
+        if config.bridge_ports.contains(&name) {
+            bail!("bridge port '{name}' is listed more than once");
+        }
+        config.bridge_ports.push(name);
     }
+    info!(
+        "Bridge uses additional ports: {}",
+        config.bridge_ports.join(", ")
+    );
 
-    Ok(dev_index.unwrap())
+    Ok(())
+}
+
+/// Resolves the parent interfaces of all VLAN sub-interfaces. On products with a management
+/// bridge, VLANs cannot be created on the bridge ports, as their traffic would bypass the
+/// bridge.
+fn set_network_vlans(
+    answer: &Answer,
+    udev_info: &UdevInfo,
+    setup_info: &SetupInfo,
+    config: &mut InstallConfig,
+) -> Result<()> {
+    let settings = match &answer.network.network_settings {
+        answer::NetworkSettings::Manual(settings) => settings,
+        answer::NetworkSettings::FromDhcp => return Ok(()),
+    };
+
+    for vlan in &settings.vlans {
+        let parent = get_network_interface(&vlan.interface, udev_info).with_context(|| {
+            format!(
+                "VLAN {}: no interface found for {:?}",
+                vlan.id, vlan.interface
+            )
+        })?;
+        if is_bond_member(&config.bonds, &parent) {
+            bail!(
+                "VLAN {}: interface '{parent}' is already used as bond member",
+                vlan.id
+            );
+        }
+        if setup_info.config.bridged_network
+            && (parent == config.mngmt_nic || config.bridge_ports.contains(&parent))
+        {
+            bail!(
+                "VLAN {}: interface '{parent}' is a bridge port, use the VLAN-aware bridge instead",
+                vlan.id
+            );
+        }
+        if parent == config.mngmt_nic && config.mngmt_vlan == Some(vlan.id) {
+            bail!("VLAN {}: already used as management VLAN", vlan.id);
+        }
+        if config
+            .vlans
+            .iter()
+            .any(|v| v.parent == parent && v.id == vlan.id)
+        {
+            bail!("VLAN {}: defined more than once on '{parent}'", vlan.id);
+        }
+        info!("VLAN {} is created on interface '{parent}'", vlan.id);
+
+        config.vlans.push(InstallVlanConfig {
+            parent,
+            id: vlan.id,
+            cidr: vlan.cidr.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+fn is_bond_member(bonds: &[InstallBondConfig], nic: &str) -> bool {
+    bonds
+        .iter()
+        .any(|bond| bond.members.iter().any(|member| member == nic))
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, ValueEnum, PartialEq)]
@@ -72,6 +214,8 @@ pub enum FetchAnswerFrom {
     Iso,
     Http,
     Partition,
+    Tftp,
+    Nfs,
 }
 
 #[derive(Deserialize, Serialize, Clone, Default, PartialEq, Debug)]
@@ -80,14 +224,87 @@ pub struct HttpOptions {
     pub url: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cert_fingerprint: Option<String>,
+    /// Number of retries after a failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Delay before the first retry in seconds, doubled for each following retry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u64>,
+    /// Timeout for a single attempt in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Time limit for fetching the answer file in seconds, including all retries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+    /// Time to wait for a network link and a DHCP lease in seconds before the first attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_wait: Option<u64>,
+    /// Path to the PEM-encoded client certificate for mutual TLS, in the live environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    /// Path to the PEM-encoded private key of the client certificate, in the live environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    /// Path to the shared secret for signing requests with HMAC-SHA256, in the live environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac_secret: Option<String>,
+    /// Path to the PEM-encoded CA certificates to verify the server against, in the live
+    /// environment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_certs: Option<String>,
+    /// Name the server certificate must be valid for, instead of the host of the URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Debug)]
+pub struct TftpOptions {
+    /// TFTP server, optionally with port, defaults to the boot server provided via DHCP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Directory on the TFTP server containing the answer files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Debug)]
+pub struct NfsOptions {
+    /// NFS server, defaults to the boot server provided via DHCP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Exported directory containing the answer files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "lowercase", deny_unknown_fields)]
 pub struct AutoInstSettings {
-    pub mode: FetchAnswerFrom,
+    /// Sources to fetch the answer file from, tried in order until one returns a valid answer
+    /// file. A single source is accepted too, as written by older versions.
+    #[serde(deserialize_with = "deserialize_fetch_modes")]
+    pub mode: Vec<FetchAnswerFrom>,
     #[serde(default)]
     pub http: HttpOptions,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub tftp: TftpOptions,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub nfs: NfsOptions,
+    /// Path to the public keys trusted to sign answer files, in the live environment. If set,
+    /// only signed answer files are accepted, see the `signature` module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_keys: Option<String>,
+}
+
+fn deserialize_fetch_modes<'de, D>(deserializer: D) -> Result<Vec<FetchAnswerFrom>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    answer::OneOrMany::deserialize(deserializer).map(Vec::from)
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 #[derive(Deserialize, Debug)]
@@ -114,49 +331,61 @@ pub fn get_nic_list() -> Result<Vec<String>> {
     Ok(links)
 }
 
-pub fn get_matched_udev_indexes(
-    filter: &BTreeMap<String, String>,
-    udev_list: &BTreeMap<String, BTreeMap<String, String>>,
-    match_all: bool,
-) -> Result<Vec<String>> {
-    let mut matches = vec![];
-    for (dev, dev_values) in udev_list {
-        let mut did_match_once = false;
-        let mut did_match_all = true;
-        for (filter_key, filter_value) in filter {
-            let filter_pattern =
-                Pattern::new(filter_value).context("invalid glob in disk selection")?;
-            for (udev_key, udev_value) in dev_values {
-                if udev_key == filter_key && filter_pattern.matches(udev_value) {
-                    did_match_once = true;
-                } else if udev_key == filter_key {
-                    did_match_all = false;
-                }
-            }
-        }
-        if (match_all && did_match_all) || (!match_all && did_match_once) {
-            matches.push(dev.clone());
-        }
-    }
-    if matches.is_empty() {
-        bail!("filter did not match any devices");
-    }
-    matches.sort();
-    Ok(matches)
-}
-
 pub fn set_disks(
     answer: &Answer,
     udev_info: &UdevInfo,
     runtime_info: &RuntimeInfo,
     config: &mut InstallConfig,
 ) -> Result<()> {
+    let policy = answer.disks.allow_overwrite;
+
+    // disks holding data are not even considered as candidates for filters
+    let mut candidates = runtime_info.clone();
+    if policy == answer::OverwritePolicy::EmptyOnly {
+        candidates
+            .disks
+            .retain(|disk| get_existing_disk_data(udev_info.disks.get(&disk.index)).is_empty());
+    }
+    let runtime_info = match answer.disks.disk_selection {
+        answer::DiskSelection::Filter(_) => &candidates,
+        answer::DiskSelection::Selection(_) => runtime_info,
+    };
+
     match config.filesys {
-        FsType::Ext4 | FsType::Xfs => set_single_disk(answer, udev_info, runtime_info, config),
+        FsType::Ext4 | FsType::Xfs => set_single_disk(answer, udev_info, runtime_info, config)?,
         FsType::Zfs(_) | FsType::Btrfs(_) => {
-            set_selected_disks(answer, udev_info, runtime_info, config)
+            set_selected_disks(answer, udev_info, runtime_info, config)?
         }
     }
+
+    let selected: Vec<&Disk> = match &config.target_hd {
+        Some(disk) => vec![disk],
+        None => config
+            .disk_selection
+            .values()
+            .filter_map(|index| runtime_info.disks.iter().find(|d| &d.index == index))
+            .collect(),
+    };
+    for disk in selected {
+        let data = get_existing_disk_data(udev_info.disks.get(&disk.index));
+        if data.is_empty() {
+            continue;
+        }
+        if policy != answer::OverwritePolicy::Always {
+            bail!(
+                "refusing to overwrite disk {} holding existing data ({}), see 'allow_overwrite'",
+                disk.path,
+                data.join(", ")
+            );
+        }
+        info!(
+            "Overwriting existing data on {}: {}",
+            disk.path,
+            data.join(", ")
+        );
+    }
+
+    Ok(())
 }
 
 fn set_single_disk(
@@ -167,18 +396,12 @@ fn set_single_disk(
 ) -> Result<()> {
     match &answer.disks.disk_selection {
         answer::DiskSelection::Selection(disk_list) => {
-            let disk_name = disk_list[0].clone();
-            let disk = runtime_info
-                .disks
-                .iter()
-                .find(|item| item.path.ends_with(disk_name.as_str()));
-            match disk {
-                Some(disk) => config.target_hd = Some(disk.clone()),
-                None => bail!("disk in 'disk_selection' not found"),
-            }
+            let disks = get_disks_from_list(disk_list, udev_info, runtime_info)?;
+            config.target_hd = disks.first().cloned();
         }
         answer::DiskSelection::Filter(filter) => {
-            let disk_index = get_single_udev_index(filter, &udev_info.disks)?;
+            let disk_index =
+                get_matched_disks(filter, &udev_info.disks, &runtime_info.disks)?.remove(0);
             let disk = runtime_info
                 .disks
                 .iter()
@@ -199,30 +422,16 @@ fn set_selected_disks(
     match &answer.disks.disk_selection {
         answer::DiskSelection::Selection(disk_list) => {
             info!("Disk selection found");
-            for disk_name in disk_list.clone() {
-                let disk = runtime_info
-                    .disks
-                    .iter()
-                    .find(|item| item.path.ends_with(disk_name.as_str()));
-                if let Some(disk) = disk {
-                    config
-                        .disk_selection
-                        .insert(disk.index.clone(), disk.index.clone());
-                }
+            for disk in get_disks_from_list(disk_list, udev_info, runtime_info)? {
+                config
+                    .disk_selection
+                    .insert(disk.index.clone(), disk.index.clone());
             }
         }
         answer::DiskSelection::Filter(filter) => {
             info!("No disk list found, looking for disk filters");
-            let filter_match = answer
-                .disks
-                .filter_match
-                .clone()
-                .unwrap_or(answer::FilterMatch::Any);
-            let selected_disk_indexes = get_matched_udev_indexes(
-                filter,
-                &udev_info.disks,
-                filter_match == answer::FilterMatch::All,
-            )?;
+            let selected_disk_indexes =
+                get_matched_disks(filter, &udev_info.disks, &runtime_info.disks)?;
 
             for i in selected_disk_indexes.into_iter() {
                 let disk = runtime_info
@@ -276,34 +485,40 @@ pub fn get_first_selected_disk(config: &InstallConfig) -> usize {
 
 pub fn verify_locale_settings(answer: &Answer, locales: &LocaleInfo) -> Result<()> {
     info!("Verifying locale settings");
-    if !locales
-        .countries
-        .keys()
-        .any(|i| i == &answer.global.country)
-    {
-        bail!("country code '{}' is not valid", &answer.global.country);
+    verify_country(&answer.global.country, locales)?;
+    verify_keyboard(&answer.global.keyboard, locales)?;
+    verify_timezone(&answer.global.timezone, locales)?;
+
+    Ok(())
+}
+
+pub fn verify_country(country: &str, locales: &LocaleInfo) -> Result<()> {
+    if !locales.countries.keys().any(|i| i == country) {
+        bail!("country code '{country}' is not valid");
     }
-    if !locales
-        .kmap
-        .keys()
-        .any(|i| i == &answer.global.keyboard.to_string())
-    {
-        bail!("keyboard layout '{}' is not valid", &answer.global.keyboard);
+    Ok(())
+}
+
+pub fn verify_keyboard(keyboard: &answer::KeyboardLayout, locales: &LocaleInfo) -> Result<()> {
+    if !locales.kmap.keys().any(|i| i == &keyboard.to_string()) {
+        bail!("keyboard layout '{keyboard}' is not valid");
     }
+    Ok(())
+}
 
+pub fn verify_timezone(timezone: &str, locales: &LocaleInfo) -> Result<()> {
     if !locales
         .cczones
         .iter()
-        .any(|(_, zones)| zones.contains(&answer.global.timezone))
-        && answer.global.timezone != "UTC"
+        .any(|(_, zones)| zones.iter().any(|zone| zone == timezone))
+        && timezone != "UTC"
     {
-        bail!("timezone '{}' is not valid", &answer.global.timezone);
+        bail!("timezone '{timezone}' is not valid");
     }
-
     Ok(())
 }
 
-fn verify_root_password_settings(answer: &Answer) -> Result<()> {
+pub fn verify_root_password_settings(answer: &Answer) -> Result<()> {
     if answer.global.root_password.is_some() && answer.global.root_password_hashed.is_some() {
         bail!("`global.root_password` and `global.root_password_hashed` cannot be set at the same time");
     } else if answer.global.root_password.is_none() && answer.global.root_password_hashed.is_none()
@@ -314,6 +529,54 @@ fn verify_root_password_settings(answer: &Answer) -> Result<()> {
     }
 }
 
+pub fn verify_first_boot_settings(answer: &Answer) -> Result<()> {
+    use answer::FirstBootHookSourceMode::*;
+
+    if let Some(first_boot) = &answer.first_boot {
+        if first_boot.source == FromAnswer && first_boot.script.is_none() {
+            bail!("`first-boot.script` must be set for the 'from-answer' source");
+        }
+        if first_boot.source == FromUrl && first_boot.url.is_none() {
+            bail!("`first-boot.url` must be set for the 'from-url' source");
+        }
+        if first_boot.script.is_some() && first_boot.source != FromAnswer {
+            bail!("`first-boot.script` is only supported for the 'from-answer' source");
+        }
+        if first_boot.url.is_some() && first_boot.source != FromUrl {
+            bail!("`first-boot.url` is only supported for the 'from-url' source");
+        }
+        if first_boot.cert_fingerprint.is_some() && first_boot.source != FromUrl {
+            bail!("`first-boot.cert-fingerprint` is only supported for the 'from-url' source");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn verify_progress_report_settings(answer: &Answer) -> Result<()> {
+    use answer::ProgressReportTarget::*;
+
+    if let Some(report) = &answer.progress_report {
+        if report.target == Http && report.url.is_none() {
+            bail!("`progress-report.url` must be set for the 'http' target");
+        }
+        if report.target == Syslog && report.host.is_none() {
+            bail!("`progress-report.host` must be set for the 'syslog' target");
+        }
+        if report.url.is_some() && report.target != Http {
+            bail!("`progress-report.url` is only supported for the 'http' target");
+        }
+        if report.cert_fingerprint.is_some() && report.target != Http {
+            bail!("`progress-report.cert-fingerprint` is only supported for the 'http' target");
+        }
+        if report.host.is_some() && report.target != Syslog {
+            bail!("`progress-report.host` is only supported for the 'syslog' target");
+        }
+    }
+
+    Ok(())
+}
+
 pub fn parse_answer(
     answer: &Answer,
     udev_info: &UdevInfo,
@@ -327,9 +590,31 @@ pub fn parse_answer(
     info!("File system selected: {}", filesystem);
 
     let network_settings = get_network_settings(answer, udev_info, runtime_info, setup_info)?;
+    let bonds = get_network_bonds(answer, udev_info, &network_settings.ifname)?;
+    // without a DHCP lease, fall back to the unspecified address, so that the installation does
+    // not fail and the network can be configured afterwards
+    let primary_address = match network_settings.primary() {
+        Some(primary) => primary.clone(),
+        None => NetworkAddressOptions {
+            // Safety: The provided mask will always be valid.
+            address: CidrAddress::new(Ipv4Addr::UNSPECIFIED, 0).unwrap(),
+            gateway: Ipv4Addr::UNSPECIFIED.into(),
+        },
+    };
+    let ipv6_address = network_settings.secondary().cloned();
+    let (dns, dns_additional) = match network_settings.dns_servers.split_first() {
+        Some((dns, additional)) => (*dns, additional.to_vec()),
+        None => (Ipv4Addr::UNSPECIFIED.into(), vec![]),
+    };
+    let mngmt_vlan = match &answer.network.network_settings {
+        answer::NetworkSettings::Manual(settings) => settings.vlan,
+        answer::NetworkSettings::FromDhcp => None,
+    };
 
     verify_locale_settings(answer, locales)?;
     verify_root_password_settings(answer)?;
+    verify_first_boot_settings(answer)?;
+    verify_progress_report_settings(answer)?;
 
     let mut config = InstallConfig {
         autoreboot: 1_usize,
@@ -356,14 +641,30 @@ pub fn parse_answer(
         root_ssh_keys: answer.global.root_ssh_keys.clone(),
 
         mngmt_nic: network_settings.ifname,
+        mngmt_vlan,
+        bonds,
+        bridge_ports: vec![],
+        vlans: vec![],
 
         hostname: network_settings.fqdn.host().unwrap().to_string(),
         domain: network_settings.fqdn.domain(),
-        cidr: network_settings.address,
-        gateway: network_settings.gateway,
-        dns: network_settings.dns_server,
+        cidr: primary_address.address,
+        gateway: primary_address.gateway,
+        dns,
+        cidr6: ipv6_address.as_ref().map(|ipv6| ipv6.address.clone()),
+        gateway6: ipv6_address.map(|ipv6| ipv6.gateway),
+        dns_additional,
+
+        first_boot: answer
+            .first_boot
+            .as_ref()
+            .map(|first_boot| InstallFirstBootSetup {
+                ordering: first_boot.ordering,
+            }),
     };
 
+    set_network_bridge_ports(answer, udev_info, setup_info, &mut config)?;
+    set_network_vlans(answer, udev_info, setup_info, &mut config)?;
     set_disks(answer, udev_info, runtime_info, &mut config)?;
     match &answer.disks.fs_options {
         answer::FsOptions::LVM(lvm) => {
@@ -398,7 +699,7 @@ pub fn parse_answer(
     Ok(config)
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum LowLevelMessage {
     #[serde(rename = "message")]
@@ -0,0 +1,864 @@
+//! Validation of answer files, collecting all problems found instead of stopping at the first
+//! one. Each problem is reported with the path of the offending key and its location in the
+//! file, so that it can be consumed by other tools, e.g. for linting generated answer files.
+
+use serde::{de, Deserialize, Serialize};
+use std::{fmt, ops::Range};
+
+use crate::utils::{
+    verify_country, verify_first_boot_settings, verify_keyboard, verify_progress_report_settings,
+    verify_root_password_settings, verify_timezone,
+};
+use proxmox_installer_common::{
+    answer::{
+        Answer, DiskFilter, DiskSelection, DiskTake, Disks, FirstBootHookInfo, FsOptions, Global,
+        LogUploadInfo, Network, NetworkInterface, NetworkSettings, PostNotificationHookInfo,
+        ProgressReportInfo,
+    },
+    disk_checks::{check_btrfs_raid_config, check_zfs_raid_config},
+    options::{Disk, FsType},
+    setup::LocaleInfo,
+};
+
+/// Limits how often a section is deserialized again after an error, see `check_section`.
+const MAX_PROBLEMS_PER_SECTION: usize = 32;
+
+/// Top-level sections of the answer file, along with whether they are required.
+const SECTIONS: &[(&str, bool)] = &[
+    ("global", true),
+    ("network", true),
+    ("disk-setup", true),
+    ("post-installation-webhook", false),
+    ("first-boot", false),
+    ("progress-report", false),
+    ("log-upload", false),
+];
+
+/// udev properties commonly used in filters. Properties from the same families, e.g. `ID_ATA_*`,
+/// are accepted as well, anything else is reported as warning as it most likely is a typo.
+const KNOWN_UDEV_PROPERTIES: &[&str] = &[
+    "DEVLINKS",
+    "DEVNAME",
+    "DEVPATH",
+    "DEVTYPE",
+    "DISKSEQ",
+    "IFINDEX",
+    "INTERFACE",
+    "MAJOR",
+    "MINOR",
+    "SUBSYSTEM",
+    "ID_BUS",
+    "ID_MODEL",
+    "ID_MODEL_ENC",
+    "ID_MODEL_ID",
+    "ID_PATH",
+    "ID_PATH_TAG",
+    "ID_REVISION",
+    "ID_SERIAL",
+    "ID_SERIAL_SHORT",
+    "ID_TYPE",
+    "ID_VENDOR",
+    "ID_VENDOR_ENC",
+    "ID_VENDOR_ID",
+    "ID_WWN",
+    "ID_WWN_WITH_EXTENSION",
+];
+
+const KNOWN_UDEV_PROPERTY_PREFIXES: &[&str] = &[
+    "ID_ATA_", "ID_FS_", "ID_NET_", "ID_PART_", "ID_PCI_", "ID_SCSI_", "ID_USB_",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// 1-based position in the answer file, the column is counted in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    /// Path of the offending key, e.g. `disk-setup.zfs.raid`. Empty for the whole file.
+    pub path: String,
+    pub span: Option<Span>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(span) = &self.span {
+            write!(
+                f,
+                " (line {}, column {})",
+                span.start.line, span.start.column
+            )?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at '{}'", self.path)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub problems: Vec<Problem>,
+}
+
+/// Validates the raw contents of an answer file.
+///
+/// The answer is returned along with the report if it could be parsed, even if semantic checks
+/// failed. Locale checks are only done if `locales` is given.
+pub fn validate_answer(
+    contents: &str,
+    locales: Option<&LocaleInfo>,
+) -> (Option<Answer>, ValidationReport) {
+    let mut validator = Validator {
+        contents,
+        tree: Node::default(),
+        problems: vec![],
+    };
+
+    let answer = validator.run(locales);
+
+    let valid = !validator
+        .problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error);
+
+    (
+        answer,
+        ValidationReport {
+            valid,
+            problems: validator.problems,
+        },
+    )
+}
+
+struct Validator<'a> {
+    contents: &'a str,
+    tree: Node,
+    problems: Vec<Problem>,
+}
+
+impl Validator<'_> {
+    fn run(&mut self, locales: Option<&LocaleInfo>) -> Option<Answer> {
+        match Node::parse(self.contents) {
+            Ok(tree) => self.tree = tree,
+            Err(err) => {
+                self.push_toml_error(&err);
+                return None;
+            }
+        }
+
+        self.check_sections();
+        if !self.problems.is_empty() {
+            return None;
+        }
+
+        let answer = match toml::from_str::<Answer>(self.contents) {
+            Ok(answer) => answer,
+            Err(err) => {
+                self.push_toml_error(&err);
+                return None;
+            }
+        };
+
+        if let Some(locales) = locales {
+            self.check_locales(&answer, locales);
+        }
+        if let Err(err) = verify_root_password_settings(&answer) {
+            self.push_path(&["global", "root_password"], Severity::Error, err);
+        }
+        if let Err(err) = verify_first_boot_settings(&answer) {
+            self.push_path(&["first-boot"], Severity::Error, err);
+        }
+        if let Err(err) = verify_progress_report_settings(&answer) {
+            self.push_path(&["progress-report"], Severity::Error, err);
+        }
+        self.check_raid(&answer.disks);
+        self.check_udev_filters(&answer);
+
+        Some(answer)
+    }
+
+    /// Deserializes every section on its own, so that errors in all of them are reported.
+    fn check_sections(&mut self) {
+        if let NodeKind::Table(entries) = &self.tree.kind {
+            let unknown: Vec<String> = entries
+                .iter()
+                .map(|(key, _)| key.clone())
+                .filter(|key| !SECTIONS.iter().any(|(name, _)| name == key))
+                .collect();
+            for key in unknown {
+                let message = format!("unknown section '{key}'");
+                self.push_path(&[&key], Severity::Error, message);
+            }
+        }
+
+        for (name, required) in SECTIONS {
+            if *required && self.tree.get(&[name]).is_none() {
+                self.push_path(&[], Severity::Error, format!("missing section '{name}'"));
+            }
+        }
+
+        self.check_section::<GlobalSection>();
+        self.check_section::<NetworkSection>();
+        self.check_section::<DiskSetupSection>();
+        self.check_section::<PostInstallationWebhookSection>();
+        self.check_section::<FirstBootSection>();
+        self.check_section::<ProgressReportSection>();
+        self.check_section::<LogUploadSection>();
+    }
+
+    /// As deserialization stops at the first error, the offending key is removed after each
+    /// error and the section deserialized again, to find all problems within the section.
+    fn check_section<T: de::DeserializeOwned>(&mut self) {
+        let mut document: toml::Table = match toml::from_str(self.contents) {
+            Ok(document) => document,
+            Err(_) => return,
+        };
+        let mut contents = self.contents.to_owned();
+        let mut removed = vec![];
+
+        for _ in 0..MAX_PROBLEMS_PER_SECTION {
+            let err = match toml::from_str::<T>(&contents) {
+                Ok(_) => return,
+                Err(err) => err,
+            };
+            let tree = match Node::parse(&contents) {
+                Ok(tree) => tree,
+                Err(_) => return,
+            };
+
+            let mut path = vec![];
+            if let Some(range) = err.span() {
+                tree.find_path(&range, &mut path);
+            }
+            if let Some(field) = quoted_field(err.message(), "unknown field `") {
+                // dotted keys have no span of their own, so the error points at the parent
+                let parent: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+                if let Some(node) = tree.get(&parent) {
+                    node.find_key(&field, &mut path);
+                }
+            }
+            let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+
+            // a key removed below might be required, which is no problem of its own
+            if let Some(field) = quoted_field(err.message(), "missing field `") {
+                let mut missing = path.clone();
+                missing.push(&field);
+                if removed.contains(&format_path(&missing)) {
+                    return;
+                }
+            }
+
+            self.push_path(&path, Severity::Error, err.message().trim());
+
+            // errors concerning the section as a whole, e.g. missing fields, cannot be skipped
+            if path.len() < 2 || !remove_key(&mut document, &path) {
+                return;
+            }
+            removed.push(format_path(&path));
+            contents = match toml::to_string(&document) {
+                Ok(contents) => contents,
+                Err(_) => return,
+            };
+        }
+    }
+
+    fn check_locales(&mut self, answer: &Answer, locales: &LocaleInfo) {
+        if let Err(err) = verify_country(&answer.global.country, locales) {
+            self.push_path(&["global", "country"], Severity::Error, err);
+        }
+        if let Err(err) = verify_keyboard(&answer.global.keyboard, locales) {
+            self.push_path(&["global", "keyboard"], Severity::Error, err);
+        }
+        if let Err(err) = verify_timezone(&answer.global.timezone, locales) {
+            self.push_path(&["global", "timezone"], Severity::Error, err);
+        }
+    }
+
+    /// The number of disks is only known beforehand if they are listed explicitly, or limited by
+    /// 'take_smallest' or 'take_largest'.
+    fn check_raid(&mut self, disks: &Disks) {
+        let (count, key) = match &disks.disk_selection {
+            DiskSelection::Selection(list) => (list.len(), "disk_list"),
+            DiskSelection::Filter(DiskFilter {
+                take: Some(take), ..
+            }) => match take {
+                DiskTake::Smallest(count) => (*count, "take_smallest"),
+                DiskTake::Largest(count) => (*count, "take_largest"),
+            },
+            DiskSelection::Filter(_) => return,
+        };
+
+        // Only the number of disks is checked, so use dummy disks of the same size, as the
+        // actual sizes are only known on the target machine.
+        let dummy_disks: Vec<Disk> = (0..count)
+            .map(|index| Disk {
+                index: index.to_string(),
+                path: format!("/dev/disk{index}"),
+                model: None,
+                size: 0.,
+                block_size: None,
+            })
+            .collect();
+
+        let result = match (&disks.fs_type, &disks.fs_options) {
+            (FsType::Zfs(level), FsOptions::ZFS(_)) => check_zfs_raid_config(*level, &dummy_disks),
+            (FsType::Btrfs(level), FsOptions::BTRFS(_)) => {
+                check_btrfs_raid_config(*level, &dummy_disks)
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            let level = match disks.fs_type {
+                FsType::Zfs(level) => level.to_string(),
+                FsType::Btrfs(level) => level.to_string(),
+                _ => String::new(),
+            };
+            self.push_path(
+                &["disk-setup", key],
+                Severity::Error,
+                format!("{level}: {}", err.replace('\n', " ")),
+            );
+        }
+    }
+
+    fn check_udev_filters(&mut self, answer: &Answer) {
+        if let DiskSelection::Filter(filter) = &answer.disks.disk_selection {
+            for key in filter.udev.keys() {
+                self.check_udev_key(&["disk-setup", "filter", key], key);
+            }
+        }
+
+        if let NetworkSettings::Manual(settings) = &answer.network.network_settings {
+            if let NetworkInterface::Filter(filter) = &settings.interface {
+                for key in filter.keys() {
+                    self.check_udev_key(&["network", "filter", key], key);
+                }
+            }
+
+            for (i, bond) in settings.bonds.iter().enumerate() {
+                for (j, filter) in bond.members.iter().enumerate() {
+                    for key in filter.keys() {
+                        let (i, j) = (i.to_string(), j.to_string());
+                        self.check_udev_key(&["network", "bond", &i, "members", &j, key], key);
+                    }
+                }
+            }
+
+            for (i, port) in settings.bridge_ports.iter().enumerate() {
+                if let NetworkInterface::Filter(filter) = port {
+                    for key in filter.keys() {
+                        let i = i.to_string();
+                        self.check_udev_key(&["network", "bridge_ports", &i, key], key);
+                    }
+                }
+            }
+
+            for (i, vlan) in settings.vlans.iter().enumerate() {
+                if let NetworkInterface::Filter(filter) = &vlan.interface {
+                    for key in filter.keys() {
+                        let i = i.to_string();
+                        self.check_udev_key(
+                            &["network", "vlan_interface", &i, "interface", key],
+                            key,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_udev_key(&mut self, path: &[&str], key: &str) {
+        if !key
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        {
+            self.push_path(
+                path,
+                Severity::Error,
+                format!("'{key}' is not a valid udev property name"),
+            );
+        } else if !KNOWN_UDEV_PROPERTIES.contains(&key)
+            && !KNOWN_UDEV_PROPERTY_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        {
+            self.push_path(
+                path,
+                Severity::Warning,
+                format!("'{key}' is not a known udev property"),
+            );
+        }
+    }
+
+    fn push_path(&mut self, path: &[&str], severity: Severity, message: impl ToString) {
+        let span = self
+            .tree
+            .get(path)
+            .and_then(|node| node.span.clone())
+            .map(|range| self.span(range));
+        self.problems.push(Problem {
+            path: format_path(path),
+            span,
+            severity,
+            message: message.to_string(),
+        });
+    }
+
+    fn push_toml_error(&mut self, err: &toml::de::Error) {
+        let (path, span) = match err.span() {
+            Some(range) => {
+                let mut path = vec![];
+                self.tree.find_path(&range, &mut path);
+                let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+                (format_path(&path), Some(self.span(range)))
+            }
+            None => (String::new(), None),
+        };
+
+        let problem = Problem {
+            path,
+            span,
+            severity: Severity::Error,
+            message: err.message().trim().to_owned(),
+        };
+
+        // sections are also checked on their own, so the same error might be found twice
+        if !self
+            .problems
+            .iter()
+            .any(|p| p.span == problem.span && p.message == problem.message)
+        {
+            self.problems.push(problem);
+        }
+    }
+
+    fn span(&self, range: Range<usize>) -> Span {
+        Span {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let before = &self.contents[..offset.min(self.contents.len())];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// Extracts the field name from serde's error messages for unknown or missing fields.
+fn quoted_field(message: &str, prefix: &str) -> Option<String> {
+    let rest = message.strip_prefix(prefix)?;
+    rest.split_once('`').map(|(field, _)| field.to_owned())
+}
+
+/// Removes the value at `path` from the document, array elements are not supported.
+fn remove_key(document: &mut toml::Table, path: &[&str]) -> bool {
+    match path {
+        [] => false,
+        [key] => document.remove(*key).is_some(),
+        [key, rest @ ..] => match document.get_mut(*key) {
+            Some(toml::Value::Table(table)) => remove_key(table, rest),
+            _ => false,
+        },
+    }
+}
+
+/// Formats a key path, with array indices in brackets, e.g. `network.bond[0].name`.
+fn format_path(path: &[&str]) -> String {
+    let mut result = String::new();
+    for segment in path {
+        if segment.parse::<usize>().is_ok() {
+            result.push_str(&format!("[{segment}]"));
+        } else {
+            if !result.is_empty() {
+                result.push('.');
+            }
+            result.push_str(segment);
+        }
+    }
+    result
+}
+
+/// Structure of the TOML document, along with the spans of all values.
+///
+/// Values always have a span, tables and arrays only if defined explicitly, e.g. not tables
+/// implicitly defined by dotted keys.
+#[derive(Default)]
+struct Node {
+    span: Option<Range<usize>>,
+    kind: NodeKind,
+}
+
+#[derive(Default)]
+enum NodeKind {
+    Table(Vec<(String, Node)>),
+    Array(Vec<Node>),
+    #[default]
+    Value,
+}
+
+impl Node {
+    /// Parses the structure of the document and looks up the span of each node on its own, as
+    /// `toml::Spanned` fails for the whole document if any requested span is missing.
+    fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        let document: toml::Value = toml::from_str(contents)?;
+        Ok(Self::build(contents, &document, &mut vec![]))
+    }
+
+    fn build(contents: &str, value: &toml::Value, path: &mut Vec<String>) -> Self {
+        let mut child = |key: String, value: &toml::Value| {
+            path.push(key);
+            let node = Self::build(contents, value, path);
+            (path.pop().unwrap_or_default(), node)
+        };
+        let kind = match value {
+            toml::Value::Table(table) => NodeKind::Table(
+                table
+                    .iter()
+                    .map(|(key, value)| child(key.clone(), value))
+                    .collect(),
+            ),
+            toml::Value::Array(items) => NodeKind::Array(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| child(i.to_string(), value).1)
+                    .collect(),
+            ),
+            _ => NodeKind::Value,
+        };
+
+        let span = if path.is_empty() {
+            None
+        } else {
+            de::DeserializeSeed::deserialize(SpanSeed(path), toml::Deserializer::new(contents)).ok()
+        };
+        Self { span, kind }
+    }
+
+    fn get(&self, path: &[&str]) -> Option<&Node> {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+
+        let child = match &self.kind {
+            NodeKind::Table(entries) => entries
+                .iter()
+                .find(|(key, _)| key == first)
+                .map(|(_, node)| node),
+            NodeKind::Array(items) => first.parse::<usize>().ok().and_then(|i| items.get(i)),
+            NodeKind::Value => None,
+        }?;
+
+        child.get(rest)
+    }
+
+    /// Searches this table and its dotted subtables for `key`, appending the path to it.
+    fn find_key(&self, key: &str, path: &mut Vec<String>) -> bool {
+        let entries = match &self.kind {
+            NodeKind::Table(entries) => entries,
+            _ => return false,
+        };
+
+        if entries.iter().any(|(k, _)| k == key) {
+            path.push(key.to_owned());
+            return true;
+        }
+
+        for (k, child) in entries.iter().filter(|(_, child)| child.span.is_none()) {
+            path.push(k.clone());
+            if child.find_key(key, path) {
+                return true;
+            }
+            path.pop();
+        }
+
+        false
+    }
+
+    /// Finds the path of the innermost value containing `range`.
+    fn find_path(&self, range: &Range<usize>, path: &mut Vec<String>) -> bool {
+        let children: Vec<(String, &Node)> = match &self.kind {
+            NodeKind::Table(entries) => entries.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            NodeKind::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v))
+                .collect(),
+            NodeKind::Value => vec![],
+        };
+
+        for (key, child) in children {
+            path.push(key);
+            match &child.span {
+                Some(span) if span.start <= range.start && range.end <= span.end => {
+                    child.find_path(range, path);
+                    return true;
+                }
+                None if child.find_path(range, path) => return true,
+                _ => {}
+            }
+            path.pop();
+        }
+
+        false
+    }
+}
+
+/// Looks up the span of the value at the path, descending into tables and arrays.
+struct SpanSeed<'a>(&'a [String]);
+
+impl<'de> de::DeserializeSeed<'de> for SpanSeed<'_> {
+    type Value = Range<usize>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        match self.0 {
+            [] => Ok(toml::Spanned::<de::IgnoredAny>::deserialize(deserializer)?.span()),
+            _ => deserializer.deserialize_any(self),
+        }
+    }
+}
+
+impl<'de> de::Visitor<'de> for SpanSeed<'_> {
+    type Value = Range<usize>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a TOML table or array")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let (first, rest) = self
+            .0
+            .split_first()
+            .ok_or_else(|| de::Error::custom("empty path"))?;
+        let index: usize = first.parse().map_err(de::Error::custom)?;
+
+        for _ in 0..index {
+            seq.next_element::<de::IgnoredAny>()?;
+        }
+        let span = seq.next_element_seed(SpanSeed(rest))?;
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+
+        span.ok_or_else(|| de::Error::custom(format!("no element {index}")))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (first, rest) = self
+            .0
+            .split_first()
+            .ok_or_else(|| de::Error::custom("empty path"))?;
+
+        let mut span = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if span.is_none() && key == *first {
+                span = Some(map.next_value_seed(SpanSeed(rest))?);
+            } else {
+                map.next_value::<de::IgnoredAny>()?;
+            }
+        }
+
+        span.ok_or_else(|| de::Error::custom(format!("no key '{first}'")))
+    }
+}
+
+// Used to deserialize the sections of the answer file separately. Unknown fields are ignored here
+// on purpose, as they are only checked once for the whole file.
+
+#[derive(Deserialize)]
+struct GlobalSection {
+    #[allow(dead_code)]
+    global: Option<Global>,
+}
+
+#[derive(Deserialize)]
+struct NetworkSection {
+    #[allow(dead_code)]
+    network: Option<Network>,
+}
+
+#[derive(Deserialize)]
+struct DiskSetupSection {
+    #[allow(dead_code)]
+    #[serde(rename = "disk-setup")]
+    disks: Option<Disks>,
+}
+
+#[derive(Deserialize)]
+struct PostInstallationWebhookSection {
+    #[allow(dead_code)]
+    #[serde(rename = "post-installation-webhook")]
+    webhook: Option<PostNotificationHookInfo>,
+}
+
+#[derive(Deserialize)]
+struct FirstBootSection {
+    #[allow(dead_code)]
+    #[serde(rename = "first-boot")]
+    first_boot: Option<FirstBootHookInfo>,
+}
+
+#[derive(Deserialize)]
+struct ProgressReportSection {
+    #[allow(dead_code)]
+    #[serde(rename = "progress-report")]
+    progress_report: Option<ProgressReportInfo>,
+}
+
+#[derive(Deserialize)]
+struct LogUploadSection {
+    #[allow(dead_code)]
+    #[serde(rename = "log-upload")]
+    log_upload: Option<LogUploadInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"
+[global]
+keyboard = "de"
+country = "at"
+fqdn = "pveauto.testinstall"
+mailto = "mail@no.invalid"
+timezone = "Europe/Vienna"
+root_password = "123456"
+
+[network]
+source = "from-dhcp"
+
+[disk-setup]
+filesystem = "zfs"
+zfs.raid = "raid1"
+disk_list = ["sda", "sdb"]
+"#;
+
+    #[test]
+    fn valid_answer() {
+        let (answer, report) = validate_answer(VALID, None);
+        assert!(answer.is_some());
+        assert!(report.valid);
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn collects_all_problems() {
+        let contents = VALID
+            .replace("keyboard = \"de\"", "keyboard = \"xx\"")
+            .replace("source = \"from-dhcp\"", "source = \"from-nowhere\"")
+            .replace("mailto =", "unknown = 1\nmailto =");
+        let (answer, report) = validate_answer(&contents, None);
+
+        assert!(answer.is_none());
+        assert!(!report.valid);
+        assert_eq!(report.problems.len(), 3);
+
+        assert_eq!(report.problems[0].path, "global.keyboard");
+        assert_eq!(
+            report.problems[0].span.unwrap().start,
+            Position {
+                line: 3,
+                column: 12
+            }
+        );
+        assert_eq!(report.problems[1].path, "global.unknown");
+        assert_eq!(report.problems[2].path, "network.source");
+    }
+
+    #[test]
+    fn semantic_checks() {
+        let contents = VALID
+            .replace("root_password = \"123456\"", "")
+            .replace("disk_list = [\"sda\", \"sdb\"]", "disk_list = [\"sda\"]")
+            + "\n[network.filter]\nID_SERIEL = \"1234\"\n";
+        let contents = contents.replace("source = \"from-dhcp\"", "source = \"from-answer\"\ncidr = \"10.0.0.2/24\"\ngateway = \"10.0.0.1\"\ndns = \"10.0.0.1\"");
+        let (answer, report) = validate_answer(&contents, None);
+
+        assert!(answer.is_some());
+        assert!(!report.valid);
+
+        let problems: Vec<(&str, Severity)> = report
+            .problems
+            .iter()
+            .map(|p| (p.path.as_str(), p.severity))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("global.root_password", Severity::Error),
+                ("disk-setup.disk_list", Severity::Error),
+                ("network.filter.ID_SERIEL", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn node_spans() {
+        let tree = Node::parse(VALID).unwrap();
+        let text = |path: &[&str]| {
+            tree.get(path)
+                .and_then(|node| node.span.clone())
+                .map(|range| &VALID[range])
+        };
+
+        assert_eq!(text(&["global", "keyboard"]), Some("\"de\""));
+        assert_eq!(text(&["disk-setup", "disk_list", "1"]), Some("\"sdb\""));
+        assert_eq!(text(&["disk-setup", "zfs", "raid"]), Some("\"raid1\""));
+        assert!(text(&["network"]).unwrap().starts_with("[network]"));
+        // implicitly defined by the dotted key
+        assert!(tree.get(&["disk-setup", "zfs"]).is_some());
+        assert_eq!(text(&["disk-setup", "zfs"]), None);
+
+        let contents = VALID.replace("zfs.raid", "zfs.unknown = 1\nzfs.raid");
+        let (_, report) = validate_answer(&contents, None);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].path, "disk-setup.zfs.unknown");
+    }
+
+    #[test]
+    fn syntax_error() {
+        let (answer, report) = validate_answer("[global\nkeyboard = \"de\"\n", None);
+        assert!(answer.is_none());
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].span.unwrap().start.line, 1);
+    }
+}
@@ -3,12 +3,13 @@ use std::path::PathBuf;
 use serde_json::Value;
 use std::fs;
 
-use proxmox_auto_installer::answer;
-use proxmox_auto_installer::answer::Answer;
-use proxmox_auto_installer::udevinfo::UdevInfo;
+use proxmox_auto_installer::summary::installation_summary;
 use proxmox_auto_installer::utils::parse_answer;
 
+use proxmox_installer_common::answer;
+use proxmox_installer_common::answer::Answer;
 use proxmox_installer_common::setup::{read_json, LocaleInfo, RuntimeInfo, SetupInfo};
+use proxmox_installer_common::udevinfo::UdevInfo;
 
 fn get_test_resource_path() -> Result<PathBuf, String> {
     Ok(std::env::current_dir()
@@ -104,3 +105,126 @@ fn test_parse_answers() {
         }
     }
 }
+
+#[test]
+fn test_installation_summary() {
+    let path = get_test_resource_path().unwrap();
+    let (setup_info, locales, runtime_info, udev_info) = setup_test_basic(&path);
+    let answer = get_answer(path.join("parse_answer/zfs.toml")).unwrap();
+
+    let config = parse_answer(&answer, &udev_info, &runtime_info, &locales, &setup_info).unwrap();
+    let summary = installation_summary(&config, &runtime_info, &udev_info);
+    assert!(summary.contains("ZFS (RAID1)"));
+    assert!(summary.contains("/dev/sda (SAMSUNG MZ7KM240, serial S2HRNX0J403550, 223.57 GiB)"));
+    assert!(summary.contains("/dev/sdb (SAMSUNG MZ7KM240, serial S2HRNX0J403335, 223.57 GiB)"));
+    assert!(summary.contains("eno1 (b4:2e:99:ac:ad:b4)"));
+    assert!(summary.contains("192.168.1.114/24 via 192.168.1.1"));
+}
+
+#[test]
+fn test_disk_overwrite_policy() {
+    let path = get_test_resource_path().unwrap();
+    let (setup_info, locales, runtime_info, udev_info) = setup_test_basic(&path);
+    let answer_raw = fs::read_to_string(path.join("parse_answer/minimal.toml")).unwrap();
+
+    // 'sda' holds a LVM physical volume, 'nvme5n1' a GPT partition table, 'nvme1n1' nothing
+    for (disk, policy, allowed) in [
+        ("sda", "never", false),
+        ("sda", "empty-only", false),
+        ("sda", "always", true),
+        ("nvme5n1", "never", false),
+        ("nvme5n1", "empty-only", false),
+        ("nvme5n1", "always", true),
+        ("nvme1n1", "never", true),
+        ("nvme1n1", "empty-only", true),
+    ] {
+        let answer_raw = answer_raw.replace("\"sda\"", &format!("\"{disk}\""));
+        let answer: Answer =
+            toml::from_str(&format!("{answer_raw}allow_overwrite = \"{policy}\"\n")).unwrap();
+        let result = parse_answer(&answer, &udev_info, &runtime_info, &locales, &setup_info);
+        assert_eq!(result.is_ok(), allowed, "disk '{disk}', policy '{policy}'");
+    }
+}
+
+#[test]
+fn test_progress_report_settings() {
+    let path = get_test_resource_path().unwrap();
+    let (setup_info, locales, runtime_info, udev_info) = setup_test_basic(&path);
+    let answer_raw = fs::read_to_string(path.join("parse_answer/minimal.toml")).unwrap();
+
+    for (section, valid) in [
+        (
+            "target = \"http\"\nurl = \"https://example.com/progress\"",
+            true,
+        ),
+        ("target = \"syslog\"\nhost = \"192.168.1.10:5514\"", true),
+        ("target = \"http\"", false),
+        (
+            "target = \"syslog\"\nurl = \"https://example.com/progress\"",
+            false,
+        ),
+        (
+            "target = \"http\"\nurl = \"https://a.invalid\"\nhost = \"b.invalid\"",
+            false,
+        ),
+    ] {
+        let answer: Answer =
+            toml::from_str(&format!("{answer_raw}\n[progress-report]\n{section}\n")).unwrap();
+        let result = parse_answer(&answer, &udev_info, &runtime_info, &locales, &setup_info);
+        assert_eq!(result.is_ok(), valid, "section '{section}'");
+    }
+}
+
+#[test]
+fn test_network_interface_conflicts() {
+    let path = get_test_resource_path().unwrap();
+    let (setup_info, locales, runtime_info, udev_info) = setup_test_basic(&path);
+    let answer_raw =
+        fs::read_to_string(path.join("parse_answer/network_bridge_ports_vlans.toml")).unwrap();
+
+    for (from, to) in [
+        // bridge port is already a bond member
+        ("*248a071e05bc", "*a0369f0ab382"),
+        // bridge port is the management interface
+        (
+            "bridge_ports = [{ ID_NET_NAME_MAC = \"*248a071e05bc\" }]",
+            "bridge_ports = [\"bond0\"]",
+        ),
+        // VLAN on a bridge port
+        (
+            "interface = \"bond1\"",
+            "interface = { ID_NET_NAME_MAC = \"*248a071e05bc\" }",
+        ),
+        // VLAN on a bond member
+        ("*b42e99acadb5", "*a0369f0ab383"),
+        // VLAN on an undefined bond
+        ("interface = \"bond1\"", "interface = \"bond2\""),
+        // duplicate VLAN
+        (
+            "id = 300\ninterface = { ID_NET_NAME_MAC = \"*b42e99acadb5\" }",
+            "id = 200\ninterface = \"bond1\"",
+        ),
+    ] {
+        assert!(answer_raw.contains(from), "'{from}' not in answer");
+        let invalid = answer_raw.replace(from, to);
+        let result = toml::from_str::<Answer>(&invalid)
+            .map_err(anyhow::Error::from)
+            .and_then(|answer| {
+                parse_answer(&answer, &udev_info, &runtime_info, &locales, &setup_info)
+            });
+        assert!(result.is_err(), "replacing '{from}' with '{to}'");
+    }
+}
+
+#[test]
+fn test_dhcp_without_lease() {
+    let path = get_test_resource_path().unwrap();
+    let (setup_info, locales, mut runtime_info, udev_info) = setup_test_basic(&path);
+    runtime_info.network.routes = None;
+    let answer = get_answer(path.join("parse_answer/minimal.toml")).unwrap();
+
+    let config = parse_answer(&answer, &udev_info, &runtime_info, &locales, &setup_info).unwrap();
+    assert_eq!(config.cidr.to_string(), "0.0.0.0/0");
+    assert_eq!(config.gateway.to_string(), "0.0.0.0");
+    assert!(config.cidr6.is_none());
+}
@@ -0,0 +1,79 @@
+use anyhow::{bail, format_err, Result};
+use log::{info, warn};
+use std::fs;
+
+use proxmox_auto_installer::encryption;
+
+use crate::fetch_plugins::partition::FetchFromPartition;
+
+/// Kernel command line parameter holding the passphrase for encrypted answer files, e.g.
+/// `proxmox-answer-key=secret`
+const CMDLINE_KEY_PARAM: &str = "proxmox-answer-key=";
+const PROMPT_ATTEMPTS: usize = 3;
+
+/// Decrypts encrypted answer files and lookup tables, looking up the passphrase only once.
+///
+/// The passphrase is taken from the kernel command line, from the 'answer.key' file on a
+/// partition labeled 'proxmox-key', or else asked for on the console.
+#[derive(Default)]
+pub struct AnswerDecryptor {
+    passphrase: Option<String>,
+}
+
+impl AnswerDecryptor {
+    /// Returns the content as is, or decrypted if it is encrypted.
+    pub fn decrypt(&mut self, content: String, name: &str) -> Result<String> {
+        if !encryption::is_encrypted(&content) {
+            return Ok(content);
+        }
+        info!("The {name} is encrypted, decrypting it.");
+
+        if let Some(passphrase) = &self.passphrase {
+            return encryption::decrypt(&content, passphrase);
+        }
+
+        if let Some((source, passphrase)) = Self::configured_passphrase()? {
+            let decrypted = encryption::decrypt(&content, &passphrase).map_err(|err| {
+                format_err!("failed to decrypt {name} with key from {source}: {err}")
+            })?;
+            self.passphrase = Some(passphrase);
+            return Ok(decrypted);
+        }
+
+        for attempt in 1..=PROMPT_ATTEMPTS {
+            let passphrase =
+                encryption::read_passphrase(&format!("Passphrase for the encrypted {name}: "))?;
+            match encryption::decrypt(&content, &passphrase) {
+                Ok(decrypted) => {
+                    self.passphrase = Some(passphrase);
+                    return Ok(decrypted);
+                }
+                Err(err) => warn!("Attempt {attempt} of {PROMPT_ATTEMPTS} failed: {err}"),
+            }
+        }
+        bail!("failed to decrypt {name}, no correct passphrase given");
+    }
+
+    /// Returns whether any file was decrypted.
+    pub fn decrypted_any(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// Returns the passphrase from the kernel command line or the key partition, along with its
+    /// source.
+    fn configured_passphrase() -> Result<Option<(&'static str, String)>> {
+        let cmdline = fs::read_to_string("/proc/cmdline")?;
+        let passphrase = cmdline
+            .split_whitespace()
+            .find_map(|param| param.strip_prefix(CMDLINE_KEY_PARAM));
+        if let Some(passphrase) = passphrase {
+            info!("Using key from the kernel command line.");
+            return Ok(Some(("kernel command line", passphrase.to_owned())));
+        }
+
+        match FetchFromPartition::get_answer_key()? {
+            Some(passphrase) => Ok(Some(("key partition", passphrase))),
+            None => Ok(None),
+        }
+    }
+}
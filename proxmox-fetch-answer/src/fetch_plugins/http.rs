@@ -1,14 +1,31 @@
-use anyhow::{bail, Result};
-use log::info;
+use anyhow::{bail, format_err, Result};
+use log::{debug, info, warn};
 use std::{
     fs::{self, read_to_string},
+    path::Path,
     process::Command,
+    thread,
+    time::{Duration, Instant},
 };
 
-use proxmox_auto_installer::{sysinfo::SysInfo, utils::HttpOptions};
+use proxmox_auto_installer::{
+    signature::HTTP_SIGNATURE_HEADER,
+    sysinfo::SysInfo,
+    utils::{get_nic_list, HttpOptions},
+};
+use proxmox_installer_common::http;
+
+use super::{FetchedFile, DHCP_LEASE_FILE};
 
 static ANSWER_URL_SUBDOMAIN: &str = "proxmox-auto-installer";
 static ANSWER_CERT_FP_SUBDOMAIN: &str = "proxmox-auto-installer-cert-fingerprint";
+static ANSWER_CA_FP_SUBDOMAIN: &str = "proxmox-auto-installer-ca-fingerprint";
+
+// defaults for the retry and network-wait policy, see `HttpOptions`
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_DELAY: u64 = 5;
+const DEFAULT_NETWORK_WAIT: u64 = 60;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 // It is possible to set custom DHPC options. Option numbers 224 to 254 [0].
 // To use them with dhclient, we need to configure it to request them and what they should be
@@ -18,7 +35,9 @@ static ANSWER_CERT_FP_SUBDOMAIN: &str = "proxmox-auto-installer-cert-fingerprint
 // ```
 // option proxmox-auto-installer-manifest-url code 250 = text;
 // option proxmox-auto-installer-cert-fingerprint code 251 = text;
-// also request proxmox-auto-installer-manifest-url, proxmox-auto-installer-cert-fingerprint;
+// option proxmox-auto-installer-ca-fingerprint code 253 = text;
+// also request proxmox-auto-installer-manifest-url, proxmox-auto-installer-cert-fingerprint,
+//     proxmox-auto-installer-ca-fingerprint;
 // ```
 //
 // The results will end up in the /var/lib/dhcp/dhclient.leases file from where we can fetch them
@@ -26,7 +45,88 @@ static ANSWER_CERT_FP_SUBDOMAIN: &str = "proxmox-auto-installer-cert-fingerprint
 // [0] https://www.iana.org/assignments/bootp-dhcp-parameters/bootp-dhcp-parameters.xhtml
 static DHCP_URL_OPTION: &str = "proxmox-auto-installer-manifest-url";
 static DHCP_CERT_FP_OPTION: &str = "proxmox-auto-installer-cert-fingerprint";
-static DHCP_LEASE_FILE: &str = "/var/lib/dhcp/dhclient.leases";
+static DHCP_CA_FP_OPTION: &str = "proxmox-auto-installer-ca-fingerprint";
+
+/// Certificate and CA fingerprint to verify the answer server against.
+#[derive(Clone, Default)]
+struct Fingerprints {
+    cert: Option<String>,
+    ca: Option<String>,
+}
+
+/// Retry policy for fetching the answer file, see `HttpOptions`.
+struct RetryPolicy {
+    retries: u32,
+    delay: Duration,
+    timeout: Duration,
+    /// Time limit for all attempts, counted from the start of the fetch
+    deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    fn from_settings(settings: &HttpOptions) -> Self {
+        Self {
+            retries: settings.retries.unwrap_or(DEFAULT_RETRIES),
+            delay: Duration::from_secs(settings.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY)),
+            timeout: settings
+                .timeout
+                .map(Duration::from_secs)
+                .unwrap_or(http::DEFAULT_TIMEOUT),
+            deadline: settings.deadline.map(Duration::from_secs),
+        }
+    }
+
+    /// Calls `fetch` with the timeout for the attempt until it succeeds, sleeping with exponential
+    /// backoff in between. Gives up once the retries are used up or the next attempt would start
+    /// after the deadline. `elapsed` returns the time since the start of the fetch.
+    fn run<T>(
+        &self,
+        elapsed: impl Fn() -> Duration,
+        mut sleep: impl FnMut(Duration),
+        mut fetch: impl FnMut(Duration) -> Result<T>,
+    ) -> Result<T> {
+        let deadline_secs = self.deadline.unwrap_or_default().as_secs();
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let timeout = match self.deadline {
+                Some(deadline) => self.timeout.min(deadline.saturating_sub(elapsed())),
+                None => self.timeout,
+            };
+            if timeout.is_zero() {
+                bail!("deadline of {deadline_secs}s reached");
+            }
+            info!(
+                "Fetching answer file via HTTP, attempt {attempt} of {}.",
+                self.retries + 1
+            );
+
+            let err = match fetch(timeout) {
+                Ok(answer) => return Ok(answer),
+                Err(err) => err,
+            };
+            if attempt > self.retries {
+                bail!("giving up after {attempt} attempts: {err}");
+            }
+            if self
+                .deadline
+                .is_some_and(|deadline| elapsed() + delay >= deadline)
+            {
+                bail!(
+                    "giving up after {attempt} attempts, deadline of {deadline_secs}s reached: {err}"
+                );
+            }
+
+            warn!(
+                "Attempt {attempt} failed: {err} - retrying in {}s.",
+                delay.as_secs()
+            );
+            sleep(delay);
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
 
 pub struct FetchFromHTTP;
 
@@ -37,38 +137,136 @@ impl FetchFromHTTP {
     /// needs to be either trusted by the root certs or a SHA256 fingerprint needs to be provided.
     /// The SHA256 SSL fingerprint can either be defined in the ISO, as DHCP option, or as DNS TXT
     /// record. If provided, the fingerprint provided in the ISO has preference.
-    pub fn get_answer(settings: &HttpOptions) -> Result<String> {
-        let mut fingerprint: Option<String> = match settings.cert_fingerprint.clone() {
-            Some(fp) => {
-                info!("SSL fingerprint provided through ISO.");
-                Some(fp)
+    ///
+    /// Alternatively, the server certificate can be verified against CA certificates embedded in
+    /// the ISO, or against a CA whose SHA256 fingerprint is provided via DHCP or DNS, in the same
+    /// way as the certificate fingerprint. The CA certificate must then be sent by the server as
+    /// part of its certificate chain.
+    /// If CA certificates are embedded in the ISO, any fingerprint provided via DHCP or DNS is
+    /// ignored.
+    ///
+    /// Before the first attempt, it waits for a network link and a DHCP lease. Failed attempts are
+    /// retried with exponential backoff, until either the retries are used up or the deadline is
+    /// reached.
+    pub fn get_answer(settings: &HttpOptions) -> Result<FetchedFile> {
+        let start = Instant::now();
+        let policy = RetryPolicy::from_settings(settings);
+
+        wait_for_network(Duration::from_secs(
+            settings.network_wait.unwrap_or(DEFAULT_NETWORK_WAIT),
+        ));
+
+        let mut options = Self::client_auth(settings)?;
+        policy.run(
+            || start.elapsed(),
+            thread::sleep,
+            |timeout| {
+                options.timeout = timeout;
+                Self::try_get_answer(settings, &options)
+            },
+        )
+    }
+
+    /// Reads the client certificate and HMAC secret embedded in the ISO, if configured.
+    fn client_auth(settings: &HttpOptions) -> Result<http::PostOptions> {
+        let read = |path: &str| {
+            fs::read(path).map_err(|err| format_err!("failed to read '{path}': {err}"))
+        };
+
+        let client_cert = match (&settings.client_cert, &settings.client_key) {
+            (Some(cert), Some(key)) => {
+                info!("Using client certificate for mutual TLS.");
+                Some((read(cert)?, read(key)?))
+            }
+            (None, None) => None,
+            _ => bail!("client certificate and key must be configured together"),
+        };
+
+        let hmac_secret = match &settings.hmac_secret {
+            Some(path) => {
+                info!("Signing requests with HMAC secret.");
+                Some(http::read_hmac_secret(Path::new(path))?)
+            }
+            None => None,
+        };
+
+        let ca_certs = match &settings.ca_certs {
+            Some(path) => {
+                info!("Verifying answer server against CA certificates provided through ISO.");
+                Some(read(path)?)
             }
             None => None,
         };
 
+        Ok(http::PostOptions {
+            client_cert,
+            hmac_secret,
+            ca_certs,
+            server_name: settings.server_name.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn try_get_answer(settings: &HttpOptions, options: &http::PostOptions) -> Result<FetchedFile> {
+        let mut fingerprints = Fingerprints {
+            cert: settings.cert_fingerprint.clone(),
+            ca: None,
+        };
+        if fingerprints.cert.is_some() {
+            info!("SSL fingerprint provided through ISO.");
+        }
+
         let answer_url: String;
         if let Some(url) = settings.url.clone() {
             info!("URL specified in ISO");
             answer_url = url;
         } else {
-            (answer_url, fingerprint) = match Self::fetch_dhcp(fingerprint.clone()) {
-                Ok((url, fp)) => (url, fp),
+            (answer_url, fingerprints) = match Self::fetch_dhcp(fingerprints.clone()) {
+                Ok((url, fps)) => (url, fps),
                 Err(err) => {
                     info!("{err}");
-                    Self::fetch_dns(fingerprint.clone())?
+                    Self::fetch_dns(fingerprints.clone())?
                 }
             };
         }
 
-        if let Some(fingerprint) = &fingerprint {
+        let mut options = options.clone();
+        let cert_fingerprint = Self::apply_fingerprints(settings, fingerprints, &mut options);
+        if let Some(fingerprint) = &cert_fingerprint {
             let _ = fs::write("/tmp/cert_fingerprint", fingerprint);
         }
 
         info!("Gathering system information.");
         let payload = SysInfo::as_json()?;
+        debug!("System information: {payload}");
         info!("Sending POST request to '{answer_url}'.");
-        let answer = http_post::call(answer_url, fingerprint.as_deref(), payload)?;
-        Ok(answer)
+        let response =
+            http::post_with_options(&answer_url, cert_fingerprint.as_deref(), payload, &options)?;
+        Ok(FetchedFile {
+            signature: response.header(HTTP_SIGNATURE_HEADER).map(str::to_owned),
+            content: response.body,
+        })
+    }
+
+    /// Sets the CA fingerprint in the options and returns the certificate fingerprint to verify
+    /// the answer server with. CA certificates provided through the ISO have preference over any
+    /// fingerprint from DHCP or DNS, as those could otherwise be spoofed to bypass the CA.
+    fn apply_fingerprints(
+        settings: &HttpOptions,
+        fingerprints: Fingerprints,
+        options: &mut http::PostOptions,
+    ) -> Option<String> {
+        if options.ca_certs.is_none() {
+            options.ca_fingerprint = fingerprints.ca;
+            return fingerprints.cert;
+        }
+
+        if fingerprints.cert != settings.cert_fingerprint || fingerprints.ca.is_some() {
+            info!(
+                "Ignoring fingerprints from DHCP or DNS, CA certificates are provided through ISO."
+            );
+        }
+        settings.cert_fingerprint.clone()
     }
 
     /// Fetches search domain from resolv.conf file
@@ -115,8 +313,8 @@ impl FetchFromHTTP {
         Ok(url)
     }
 
-    /// Tries to fetch answer URL and SSL fingerprint info from DNS
-    fn fetch_dns(mut fingerprint: Option<String>) -> Result<(String, Option<String>)> {
+    /// Tries to fetch answer URL and SSL and CA fingerprint info from DNS
+    fn fetch_dns(mut fingerprints: Fingerprints) -> Result<(String, Fingerprints)> {
         let search_domain = Self::get_search_domain()?;
 
         let answer_url =
@@ -125,8 +323,8 @@ impl FetchFromHTTP {
                 Err(err) => bail!("{err}"),
             };
 
-        if fingerprint.is_none() {
-            fingerprint =
+        if fingerprints.cert.is_none() {
+            fingerprints.cert =
                 match Self::query_txt_record(format!("{ANSWER_CERT_FP_SUBDOMAIN}.{search_domain}"))
                 {
                     Ok(fp) => Some(fp),
@@ -136,11 +334,21 @@ impl FetchFromHTTP {
                     }
                 };
         }
-        Ok((answer_url, fingerprint))
+        if fingerprints.ca.is_none() {
+            fingerprints.ca =
+                match Self::query_txt_record(format!("{ANSWER_CA_FP_SUBDOMAIN}.{search_domain}")) {
+                    Ok(fp) => Some(fp),
+                    Err(err) => {
+                        info!("{err}");
+                        None
+                    }
+                };
+        }
+        Ok((answer_url, fingerprints))
     }
 
-    /// Tries to fetch answer URL and SSL fingerprint info from DHCP options
-    fn fetch_dhcp(mut fingerprint: Option<String>) -> Result<(String, Option<String>)> {
+    /// Tries to fetch answer URL and SSL and CA fingerprint info from DHCP options
+    fn fetch_dhcp(mut fingerprints: Fingerprints) -> Result<(String, Fingerprints)> {
         info!("Checking DHCP options.");
         let leases = fs::read_to_string(DHCP_LEASE_FILE)?;
 
@@ -148,13 +356,17 @@ impl FetchFromHTTP {
 
         let url_match = format!("option {DHCP_URL_OPTION}");
         let fp_match = format!("option {DHCP_CERT_FP_OPTION}");
+        let ca_fp_match = format!("option {DHCP_CA_FP_OPTION}");
 
         for line in leases.lines() {
             if answer_url.is_none() && line.trim().starts_with(url_match.as_str()) {
                 answer_url = Self::strip_dhcp_option(line.split(' ').nth_back(0));
             }
-            if fingerprint.is_none() && line.trim().starts_with(fp_match.as_str()) {
-                fingerprint = Self::strip_dhcp_option(line.split(' ').nth_back(0));
+            if fingerprints.cert.is_none() && line.trim().starts_with(fp_match.as_str()) {
+                fingerprints.cert = Self::strip_dhcp_option(line.split(' ').nth_back(0));
+            }
+            if fingerprints.ca.is_none() && line.trim().starts_with(ca_fp_match.as_str()) {
+                fingerprints.ca = Self::strip_dhcp_option(line.split(' ').nth_back(0));
             }
         }
 
@@ -166,11 +378,14 @@ impl FetchFromHTTP {
             }
         };
 
-        if let Some(fp) = fingerprint.clone() {
+        if let Some(fp) = fingerprints.cert.clone() {
             info!("Found SSL Fingerprint via DHCP: '{fp}'");
         }
+        if let Some(fp) = fingerprints.ca.clone() {
+            info!("Found CA Fingerprint via DHCP: '{fp}'");
+        }
 
-        Ok((answer_url, fingerprint))
+        Ok((answer_url, fingerprints))
     }
 
     /// Clean DHCP option string
@@ -180,99 +395,187 @@ impl FetchFromHTTP {
     }
 }
 
-mod http_post {
-    use anyhow::Result;
-    use rustls::ClientConfig;
-    use sha2::{Digest, Sha256};
-    use std::sync::Arc;
-    use ureq::{Agent, AgentBuilder};
-
-    /// Issues a POST request with the payload (JSON). Optionally a SHA256 fingerprint can be used to
-    /// check the cert against it, instead of the regular cert validation.
-    /// To gather the sha256 fingerprint you can use the following command:
-    /// ```no_compile
-    /// openssl s_client -connect <host>:443 < /dev/null 2>/dev/null | openssl x509 -fingerprint -sha256  -noout -in /dev/stdin
-    /// ```
-    ///
-    /// # Arguemnts
-    /// * `url` - URL to call
-    /// * `fingerprint` - SHA256 cert fingerprint if certificate pinning should be used. Optional.
-    /// * `payload` - The payload to send to the server. Expected to be a JSON formatted string.
-    pub fn call(url: String, fingerprint: Option<&str>, payload: String) -> Result<String> {
-        let answer;
-
-        if let Some(fingerprint) = fingerprint {
-            let tls_config = ClientConfig::builder()
-                .with_safe_defaults()
-                .with_custom_certificate_verifier(VerifyCertFingerprint::new(fingerprint)?)
-                .with_no_client_auth();
-
-            let agent: Agent = AgentBuilder::new().tls_config(Arc::new(tls_config)).build();
-
-            answer = agent
-                .post(&url)
-                .set("Content-type", "application/json; charset=utf-8")
-                .send_string(&payload)?
-                .into_string()?;
-        } else {
-            let mut roots = rustls::RootCertStore::empty();
-            for cert in rustls_native_certs::load_native_certs()? {
-                roots.add(&rustls::Certificate(cert.0)).unwrap();
-            }
+/// Waits until at least one network interface has a link and a DHCP lease was acquired, or until
+/// the timeout is reached. The answer file is still tried to be fetched in the latter case, e.g.
+/// for a static network configuration.
+fn wait_for_network(timeout: Duration) {
+    if timeout.is_zero() {
+        return;
+    }
+    let start = Instant::now();
+    let mut logged = false;
+
+    loop {
+        let link = match get_nic_list() {
+            Ok(nics) => nics.iter().any(|nic| {
+                fs::read_to_string(format!("/sys/class/net/{nic}/carrier"))
+                    .is_ok_and(|carrier| carrier.trim() == "1")
+            }),
+            Err(_) => false,
+        };
+        let lease = fs::read_to_string(DHCP_LEASE_FILE)
+            .is_ok_and(|leases| leases.contains("fixed-address"));
 
-            let tls_config = rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(roots)
-                .with_no_client_auth();
-
-            let agent = AgentBuilder::new()
-                .tls_connector(Arc::new(native_tls::TlsConnector::new()?))
-                .tls_config(Arc::new(tls_config))
-                .build();
-            answer = agent
-                .post(&url)
-                .set("Content-type", "application/json; charset=utf-8")
-                .timeout(std::time::Duration::from_secs(60))
-                .send_string(&payload)?
-                .into_string()?;
+        if link && lease {
+            if logged {
+                info!("Network is up after {}s.", start.elapsed().as_secs());
+            }
+            return;
+        }
+        if start.elapsed() >= timeout {
+            warn!(
+                "No {} after waiting {}s, trying anyway.",
+                if link { "DHCP lease" } else { "network link" },
+                timeout.as_secs()
+            );
+            return;
+        }
+        if !logged {
+            info!(
+                "Waiting up to {}s for a network link and a DHCP lease.",
+                timeout.as_secs()
+            );
+            logged = true;
         }
-        Ok(answer)
+        thread::sleep(Duration::from_secs(1));
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
 
-    struct VerifyCertFingerprint {
-        cert_fingerprint: Vec<u8>,
+    #[test]
+    fn iso_ca_certs_have_preference() {
+        let discovered = Fingerprints {
+            cert: Some("aa:bb".to_owned()),
+            ca: Some("cc:dd".to_owned()),
+        };
+
+        let mut options = http::PostOptions::default();
+        let cert = FetchFromHTTP::apply_fingerprints(
+            &HttpOptions::default(),
+            discovered.clone(),
+            &mut options,
+        );
+        assert_eq!(cert.as_deref(), Some("aa:bb"));
+        assert_eq!(options.ca_fingerprint.as_deref(), Some("cc:dd"));
+
+        let mut options = http::PostOptions {
+            ca_certs: Some(b"CA".to_vec()),
+            ..Default::default()
+        };
+        let cert = FetchFromHTTP::apply_fingerprints(
+            &HttpOptions::default(),
+            discovered.clone(),
+            &mut options,
+        );
+        assert_eq!(cert, None);
+        assert_eq!(options.ca_fingerprint, None);
+
+        // a fingerprint from the ISO itself is still used
+        let settings = HttpOptions {
+            cert_fingerprint: Some("ee:ff".to_owned()),
+            ..Default::default()
+        };
+        let discovered = Fingerprints {
+            cert: settings.cert_fingerprint.clone(),
+            ..discovered
+        };
+        let cert = FetchFromHTTP::apply_fingerprints(&settings, discovered, &mut options);
+        assert_eq!(cert.as_deref(), Some("ee:ff"));
+        assert_eq!(options.ca_fingerprint, None);
     }
 
-    impl VerifyCertFingerprint {
-        fn new<S: AsRef<str>>(cert_fingerprint: S) -> Result<std::sync::Arc<Self>> {
-            let cert_fingerprint = cert_fingerprint.as_ref();
-            let sanitized = cert_fingerprint.replace(':', "");
-            let decoded = hex::decode(sanitized)?;
-            Ok(std::sync::Arc::new(Self {
-                cert_fingerprint: decoded,
-            }))
-        }
+    /// Runs the policy on a simulated clock, where each attempt takes one second. Returns the
+    /// timeouts of all attempts and the delays slept in between.
+    fn simulate(
+        policy: &RetryPolicy,
+        succeed_at: Option<usize>,
+    ) -> (Result<()>, Vec<u64>, Vec<u64>) {
+        let now = Cell::new(Duration::ZERO);
+        let mut timeouts = vec![];
+        let mut delays = vec![];
+
+        let result = policy.run(
+            || now.get(),
+            |delay| {
+                delays.push(delay.as_secs());
+                now.set(now.get() + delay);
+            },
+            |timeout| {
+                timeouts.push(timeout.as_secs());
+                now.set(now.get() + Duration::from_secs(1));
+                if Some(timeouts.len()) == succeed_at {
+                    Ok(())
+                } else {
+                    bail!("connection refused")
+                }
+            },
+        );
+
+        (result, timeouts, delays)
     }
 
-    impl rustls::client::ServerCertVerifier for VerifyCertFingerprint {
-        fn verify_server_cert(
-            &self,
-            end_entity: &rustls::Certificate,
-            _intermediates: &[rustls::Certificate],
-            _server_name: &rustls::ServerName,
-            _scts: &mut dyn Iterator<Item = &[u8]>,
-            _ocsp_response: &[u8],
-            _now: std::time::SystemTime,
-        ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-            let mut hasher = Sha256::new();
-            hasher.update(end_entity);
-            let result = hasher.finalize();
-
-            if result.as_slice() == self.cert_fingerprint {
-                Ok(rustls::client::ServerCertVerified::assertion())
-            } else {
-                Err(rustls::Error::General("Fingerprint did not match!".into()))
-            }
-        }
+    #[test]
+    fn retry_attempts() {
+        let policy = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(60),
+            deadline: None,
+        };
+
+        let (result, timeouts, delays) = simulate(&policy, None);
+        assert!(result.is_err());
+        assert_eq!(timeouts, [60, 60, 60, 60]);
+        assert_eq!(delays, [5, 10, 20]);
+
+        let (result, timeouts, delays) = simulate(&policy, Some(3));
+        assert!(result.is_ok());
+        assert_eq!(timeouts.len(), 3);
+        assert_eq!(delays, [5, 10]);
+
+        let policy = RetryPolicy {
+            retries: 0,
+            ..policy
+        };
+        let (result, timeouts, delays) = simulate(&policy, None);
+        assert!(result.is_err());
+        assert_eq!(timeouts.len(), 1);
+        assert!(delays.is_empty());
+    }
+
+    #[test]
+    fn retry_backoff_is_capped() {
+        let policy = RetryPolicy {
+            retries: 6,
+            delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(60),
+            deadline: None,
+        };
+
+        let (_, _, delays) = simulate(&policy, None);
+        assert_eq!(delays, [5, 10, 20, 40, 60, 60]);
+    }
+
+    #[test]
+    fn retry_deadline() {
+        let policy = RetryPolicy {
+            retries: 10,
+            delay: Duration::from_secs(5),
+            timeout: Duration::from_secs(60),
+            deadline: Some(Duration::from_secs(30)),
+        };
+
+        // attempts at 0s, 6s and 17s, the next one would start at 38s
+        let (result, timeouts, delays) = simulate(&policy, None);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("deadline of 30s reached"));
+        assert_eq!(timeouts, [30, 24, 13]);
+        assert_eq!(delays, [5, 10]);
     }
 }
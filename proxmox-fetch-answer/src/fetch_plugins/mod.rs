@@ -1,2 +1,131 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::{fs, io::ErrorKind, path::Path};
+
+use proxmox_auto_installer::{signature::SIGNATURE_SUFFIX, sysinfo::SysInfo};
+
 pub(crate) mod http;
+pub(crate) mod nfs;
 pub(crate) mod partition;
+pub(crate) mod tftp;
+
+/// Default answer file, used if there is no host-specific one
+static DEFAULT_ANSWER_FILE: &str = "answer.toml";
+static LOOKUP_TABLE_FILE: &str = "answer-lookup.csv";
+static DHCP_LEASE_FILE: &str = "/var/lib/dhcp/dhclient.leases";
+
+/// A file fetched from an answer source, along with its detached signature, if any.
+pub(crate) struct FetchedFile {
+    pub content: String,
+    pub signature: Option<String>,
+}
+
+impl FetchedFile {
+    /// Reads the file and its signature file next to it, returning `None` if the file does not
+    /// exist.
+    fn read(path: &Path) -> Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("failed to read {path:?} - {err}"),
+        };
+
+        let mut signature_path = path.as_os_str().to_owned();
+        signature_path.push(SIGNATURE_SUFFIX);
+        let signature = match fs::read_to_string(&signature_path) {
+            Ok(signature) => Some(signature),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => {
+                // only relevant if signatures are required, which then fails on its own
+                warn!("Failed to read signature {signature_path:?} - {err}");
+                None
+            }
+        };
+
+        Ok(Some(Self { content, signature }))
+    }
+}
+
+/// Returns the file names to look up the answer file by, in order: by the MAC address of each
+/// network interface, e.g. 'aa-bb-cc-dd-ee-ff.toml', by the DMI system UUID, e.g.
+/// '4c4c4544-0042-3010-8057-b4c04f4e4a32.toml', and finally the default 'answer.toml'.
+fn answer_file_candidates() -> Result<Vec<String>> {
+    let sysinfo = SysInfo::get()?;
+
+    Ok(answer_file_names(
+        sysinfo.mac_addresses(),
+        sysinfo.system_uuid(),
+    ))
+}
+
+fn answer_file_names<'a>(
+    mac_addresses: impl Iterator<Item = &'a str>,
+    system_uuid: Option<&str>,
+) -> Vec<String> {
+    let mut names: Vec<String> = mac_addresses
+        .map(|mac| format!("{}.toml", mac.to_lowercase().replace(':', "-")))
+        .collect();
+    if let Some(uuid) = system_uuid {
+        names.push(format!("{}.toml", uuid.to_lowercase()));
+    }
+    names.push(DEFAULT_ANSWER_FILE.to_owned());
+
+    names
+}
+
+/// Returns the boot server from the DHCP lease, preferring the TFTP server name (option 66) over
+/// the 'next-server' address.
+///
+/// dhclient only requests option 66 if configured to, which `unconfigured.sh` does for the
+/// automated installation, in /etc/dhcp/dhclient.conf:
+/// ```text
+/// also request tftp-server-name;
+/// ```
+fn dhcp_boot_server() -> Result<String> {
+    info!("Checking DHCP lease for boot server.");
+    let leases = fs::read_to_string(DHCP_LEASE_FILE)?;
+
+    // later leases are more recent, so the last value found wins
+    let mut server_name = None;
+    let mut next_server = None;
+    for line in leases.lines() {
+        let line = line.trim().trim_end_matches(';');
+        if let Some(value) = line.strip_prefix("option tftp-server-name ") {
+            server_name = Some(value.trim_matches('"').to_owned());
+        } else if let Some(value) = line.strip_prefix("next-server ") {
+            next_server = Some(value.to_owned());
+        }
+    }
+
+    match server_name.or(next_server) {
+        Some(server) => {
+            info!("Found boot server in DHCP lease: '{server}'");
+            Ok(server)
+        }
+        None => bail!("No boot server found in DHCP lease."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_file_name_order() {
+        let macs = ["AA:BB:CC:DD:EE:FF", "aa:bb:cc:dd:ee:00"];
+        assert_eq!(
+            answer_file_names(
+                macs.into_iter(),
+                Some("4C4C4544-0042-3010-8057-B4C04F4E4A32")
+            ),
+            [
+                "aa-bb-cc-dd-ee-ff.toml",
+                "aa-bb-cc-dd-ee-00.toml",
+                "4c4c4544-0042-3010-8057-b4c04f4e4a32.toml",
+                "answer.toml",
+            ]
+        );
+
+        assert_eq!(answer_file_names(std::iter::empty(), None), ["answer.toml"]);
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{bail, format_err, Result};
+use log::info;
+use std::{fs::create_dir_all, path::Path, process::Command};
+
+use proxmox_auto_installer::utils::NfsOptions;
+
+use super::{answer_file_candidates, dhcp_boot_server, FetchedFile, LOOKUP_TABLE_FILE};
+
+static NFS_MP: &str = "/mnt/answer-nfs";
+
+pub struct FetchFromNfs;
+
+impl FetchFromNfs {
+    /// Mounts the NFS export read-only and returns the contents of the answer file, trying the
+    /// host-specific file names first. The server can be preconfigured in the ISO, otherwise the
+    /// boot server provided via DHCP is used.
+    pub fn get_answer(settings: &NfsOptions) -> Result<FetchedFile> {
+        mount_nfs_export(settings)?;
+
+        for name in answer_file_candidates()? {
+            let path = Path::new(NFS_MP).join(&name);
+            if let Some(answer) = FetchedFile::read(&path)? {
+                info!("Found answer file '{name}' on NFS export.");
+                return Ok(answer);
+            }
+        }
+
+        bail!("No answer file found on NFS export.");
+    }
+
+    /// Returns the contents of the lookup table for answer file templates, if present on the
+    /// export. Must be called after `get_answer`, which mounts the export.
+    pub fn get_lookup_table() -> Result<Option<FetchedFile>> {
+        let path = Path::new(NFS_MP).join(LOOKUP_TABLE_FILE);
+        FetchedFile::read(&path).map_err(|err| format_err!("failed to read lookup table - {err}"))
+    }
+}
+
+fn mount_nfs_export(settings: &NfsOptions) -> Result<()> {
+    let export = settings
+        .export
+        .as_ref()
+        .ok_or_else(|| format_err!("No NFS export configured."))?;
+    let server = match &settings.server {
+        Some(server) => server.clone(),
+        None => dhcp_boot_server()?,
+    };
+
+    // IPv6 addresses need to be enclosed in brackets
+    let source = if server.contains(':') && !server.starts_with('[') {
+        format!("[{server}]:{export}")
+    } else {
+        format!("{server}:{export}")
+    };
+
+    info!("Mounting NFS export '{source}' at {NFS_MP}");
+    create_dir_all(NFS_MP)?;
+    let output = Command::new("mount")
+        .args(["-t", "nfs", "-o", "ro,nolock"])
+        .arg(&source)
+        .arg(NFS_MP)
+        .output()
+        .map_err(|err| format_err!("Error mounting: {err}"))?;
+
+    if !output.status.success() {
+        bail!(
+            "Error mounting '{source}': {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
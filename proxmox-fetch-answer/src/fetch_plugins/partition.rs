@@ -6,28 +6,57 @@ use std::{
     process::Command,
 };
 
-static ANSWER_FILE: &str = "answer.toml";
+use super::{FetchedFile, DEFAULT_ANSWER_FILE, LOOKUP_TABLE_FILE};
+
 static ANSWER_MP: &str = "/mnt/answer";
 // FAT can only handle 11 characters, so shorten Automated Installer Source to AIS
 static PARTLABEL: &str = "proxmox-ais";
+/// Separate partition holding the passphrase for encrypted answer files, e.g. on a second USB
+/// flash drive.
+static KEY_PARTLABEL: &str = "proxmox-key";
+static KEY_MP: &str = "/mnt/answer-key";
+static KEY_FILE: &str = "answer.key";
 static DISK_BY_ID_PATH: &str = "/dev/disk/by-label";
 
 pub struct FetchFromPartition;
 
 impl FetchFromPartition {
-    /// Returns the contents of the answer file
-    pub fn get_answer() -> Result<String> {
+    /// Returns the contents of the answer file, along with its signature if present
+    pub fn get_answer() -> Result<FetchedFile> {
         info!("Checking for answer file on partition.");
 
-        let mut mount_path = PathBuf::from(mount_proxmoxinst_part()?);
-        mount_path.push(ANSWER_FILE);
-        let answer = fs::read_to_string(mount_path)
-            .map_err(|err| format_err!("failed to read answer file - {err}"))?;
+        let mut mount_path = PathBuf::from(mount_part(PARTLABEL, ANSWER_MP)?);
+        mount_path.push(DEFAULT_ANSWER_FILE);
+        let answer = FetchedFile::read(&mount_path)
+            .map_err(|err| format_err!("failed to read answer file - {err}"))?
+            .ok_or_else(|| format_err!("failed to read answer file - {mount_path:?} not found"))?;
 
         info!("Found answer file on partition.");
 
         Ok(answer)
     }
+
+    /// Returns the passphrase for encrypted answer files from the 'proxmox-key' partition, if the
+    /// partition exists.
+    pub fn get_answer_key() -> Result<Option<String>> {
+        if scan_partlabels(KEY_PARTLABEL, DISK_BY_ID_PATH).is_err() {
+            return Ok(None);
+        }
+        let path = Path::new(&mount_part(KEY_PARTLABEL, KEY_MP)?).join(KEY_FILE);
+        let key = fs::read_to_string(&path)
+            .map_err(|err| format_err!("failed to read key file {path:?} - {err}"))?;
+
+        info!("Found key for encrypted answer file on partition.");
+        // allow a trailing newline, as left by most editors
+        Ok(Some(key.trim_end_matches(['\n', '\r']).to_owned()))
+    }
+
+    /// Returns the contents of the lookup table for answer file templates, if present on the
+    /// partition. Must be called after `get_answer`, which mounts the partition.
+    pub fn get_lookup_table() -> Result<Option<FetchedFile>> {
+        let path = Path::new(ANSWER_MP).join(LOOKUP_TABLE_FILE);
+        FetchedFile::read(&path).map_err(|err| format_err!("failed to read lookup table - {err}"))
+    }
 }
 
 fn path_exists_logged(file_name: &str, search_path: &str) -> Option<PathBuf> {
@@ -64,29 +93,29 @@ fn scan_partlabels(partlabel: &str, search_path: &str) -> Result<PathBuf> {
     bail!("Could not detect upper or lower case labels for '{partlabel}'");
 }
 
-/// Will search and mount a partition/FS labeled PARTLABEL (proxmox-ais) in lower or uppercase
-/// to ANSWER_MP
-fn mount_proxmoxinst_part() -> Result<String> {
-    if let Ok(true) = check_if_mounted(ANSWER_MP) {
-        info!("Skipping: '{ANSWER_MP}' is already mounted.");
-        return Ok(ANSWER_MP.into());
+/// Will search and mount a partition/FS labeled `partlabel`, e.g. PARTLABEL (proxmox-ais), in
+/// lower or uppercase to `mount_point`
+fn mount_part(partlabel: &str, mount_point: &str) -> Result<String> {
+    if let Ok(true) = check_if_mounted(mount_point) {
+        info!("Skipping: '{mount_point}' is already mounted.");
+        return Ok(mount_point.into());
     }
-    let part_path = scan_partlabels(PARTLABEL, DISK_BY_ID_PATH)?;
-    info!("Mounting partition at {ANSWER_MP}");
+    let part_path = scan_partlabels(partlabel, DISK_BY_ID_PATH)?;
+    info!("Mounting partition at {mount_point}");
     // create dir for mountpoint
-    create_dir_all(ANSWER_MP)?;
+    create_dir_all(mount_point)?;
     match Command::new("mount")
         .args(["-o", "ro"])
         .arg(part_path)
-        .arg(ANSWER_MP)
+        .arg(mount_point)
         .output()
     {
         Ok(output) => {
             if output.status.success() {
-                Ok(ANSWER_MP.into())
+                Ok(mount_point.into())
             } else {
                 warn!("Error mounting: {}", String::from_utf8(output.stderr)?);
-                Ok(ANSWER_MP.into())
+                Ok(mount_point.into())
             }
         }
         Err(err) => bail!("Error mounting: {err}"),
@@ -0,0 +1,217 @@
+use anyhow::{bail, format_err, Result};
+use log::{info, warn};
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use proxmox_auto_installer::{signature::SIGNATURE_SUFFIX, utils::TftpOptions};
+
+use super::{answer_file_candidates, dhcp_boot_server, FetchedFile, LOOKUP_TABLE_FILE};
+
+const TFTP_PORT: u16 = 69;
+const TFTP_TIMEOUT: Duration = Duration::from_secs(5);
+const TFTP_RETRIES: usize = 3;
+const BLOCK_SIZE: usize = 512;
+
+// opcodes and error codes, see RFC 1350
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+const ERR_FILE_NOT_FOUND: u16 = 1;
+
+pub struct FetchFromTftp;
+
+impl FetchFromTftp {
+    /// Fetches the answer file from a TFTP server, trying the host-specific file names first. The
+    /// server can be preconfigured in the ISO, otherwise the boot server provided via DHCP is used.
+    pub fn get_answer(settings: &TftpOptions) -> Result<FetchedFile> {
+        let server = Self::server_address(settings)?;
+
+        for name in answer_file_candidates()? {
+            let path = Self::remote_path(settings, &name);
+            info!("Trying to fetch '{path}' via TFTP.");
+            if let Some(answer) = Self::get_file(server, &path)? {
+                info!("Found answer file '{path}'.");
+                return Ok(answer);
+            }
+        }
+
+        bail!("No answer file found on TFTP server.");
+    }
+
+    /// Returns the contents of the lookup table for answer file templates, if present next to the
+    /// answer files.
+    pub fn get_lookup_table(settings: &TftpOptions) -> Result<Option<FetchedFile>> {
+        let server = Self::server_address(settings)?;
+        let path = Self::remote_path(settings, LOOKUP_TABLE_FILE);
+
+        Self::get_file(server, &path)
+    }
+
+    /// Fetches the file along with its signature file, returning `None` if it does not exist.
+    fn get_file(server: SocketAddr, path: &str) -> Result<Option<FetchedFile>> {
+        let content = match tftp_get(server, path)? {
+            Some(content) => String::from_utf8(content)?,
+            None => return Ok(None),
+        };
+
+        let signature = match tftp_get(server, &format!("{path}{SIGNATURE_SUFFIX}")) {
+            Ok(Some(signature)) => Some(String::from_utf8_lossy(&signature).into_owned()),
+            Ok(None) => None,
+            Err(err) => {
+                // only relevant if signatures are required, which then fails on its own
+                warn!("Failed to fetch signature of '{path}': {err}");
+                None
+            }
+        };
+
+        Ok(Some(FetchedFile { content, signature }))
+    }
+
+    fn server_address(settings: &TftpOptions) -> Result<SocketAddr> {
+        let server = match &settings.server {
+            Some(server) => server.clone(),
+            None => dhcp_boot_server()?,
+        };
+
+        server
+            .to_socket_addrs()
+            .or_else(|_| (server.as_str(), TFTP_PORT).to_socket_addrs())
+            .map_err(|err| format_err!("failed to resolve TFTP server '{server}': {err}"))?
+            .next()
+            .ok_or_else(|| format_err!("no address found for TFTP server '{server}'"))
+    }
+
+    fn remote_path(settings: &TftpOptions, name: &str) -> String {
+        match &settings.path {
+            Some(path) => format!("{}/{name}", path.trim_end_matches('/')),
+            None => name.to_owned(),
+        }
+    }
+}
+
+/// Reads a file from a TFTP server in octet mode, returning `None` if it does not exist.
+fn tftp_get(server: SocketAddr, filename: &str) -> Result<Option<Vec<u8>>> {
+    let bind_addr = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.set_read_timeout(Some(TFTP_TIMEOUT))?;
+
+    let mut packet = OP_RRQ.to_be_bytes().to_vec();
+    packet.extend_from_slice(filename.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(b"octet");
+    packet.push(0);
+    socket.send_to(&packet, server)?;
+
+    // the server answers from a new port, which is then used for the rest of the transfer
+    let mut peer = None;
+    let mut content = Vec::new();
+    let mut block: u16 = 1;
+    let mut retries = 0;
+    let mut buf = [0u8; 4 + BLOCK_SIZE];
+
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                retries += 1;
+                if retries > TFTP_RETRIES {
+                    bail!("timed out fetching '{filename}' from TFTP server {server}");
+                }
+                socket.send_to(&packet, peer.unwrap_or(server))?;
+                continue;
+            }
+            Err(err) => bail!("failed to receive from TFTP server {server}: {err}"),
+        };
+
+        match peer {
+            Some(peer) if peer != from => continue,
+            None if from.ip() != server.ip() => continue,
+            _ => peer = Some(from),
+        }
+        if len < 4 {
+            bail!("received malformed packet from TFTP server {server}");
+        }
+
+        let opcode = u16::from_be_bytes([buf[0], buf[1]]);
+        let number = u16::from_be_bytes([buf[2], buf[3]]);
+        match opcode {
+            OP_DATA => {
+                // a retransmitted block is acknowledged again, but not added twice
+                if number == block {
+                    content.extend_from_slice(&buf[4..len]);
+                    packet = [OP_ACK.to_be_bytes(), number.to_be_bytes()].concat();
+                    block = block.wrapping_add(1);
+                    retries = 0;
+                }
+                socket.send_to(&packet, from)?;
+
+                if number == block.wrapping_sub(1) && len - 4 < BLOCK_SIZE {
+                    return Ok(Some(content));
+                }
+            }
+            OP_ERROR if number == ERR_FILE_NOT_FOUND => return Ok(None),
+            OP_ERROR => bail!(
+                "TFTP server {server} returned error for '{filename}': {}",
+                String::from_utf8_lossy(&buf[4..len]).trim_end_matches('\0')
+            ),
+            _ => bail!("unexpected packet with opcode {opcode} from TFTP server {server}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Serves a single read request, with the file split into two blocks.
+    fn serve_once(socket: UdpSocket, content: Option<&'static [u8]>) {
+        let mut buf = [0u8; 4 + BLOCK_SIZE];
+        let (_, client) = socket.recv_from(&mut buf).unwrap();
+        assert_eq!(u16::from_be_bytes([buf[0], buf[1]]), OP_RRQ);
+
+        let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let content = match content {
+            Some(content) => content,
+            None => {
+                let error = [&[0, 5, 0, 1][..], b"File not found\0"].concat();
+                transfer.send_to(&error, client).unwrap();
+                return;
+            }
+        };
+
+        for (index, chunk) in content.chunks(BLOCK_SIZE).enumerate() {
+            let number = (index as u16 + 1).to_be_bytes();
+            let data = [&OP_DATA.to_be_bytes()[..], &number, chunk].concat();
+            transfer.send_to(&data, client).unwrap();
+
+            let (len, _) = transfer.recv_from(&mut buf).unwrap();
+            assert_eq!(buf[..len], [0, 4, number[0], number[1]]);
+        }
+    }
+
+    #[test]
+    fn fetch_file() {
+        static CONTENT: [u8; 700] = [b'a'; 700];
+
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || serve_once(server, Some(&CONTENT)));
+        assert_eq!(tftp_get(addr, "answer.toml").unwrap().unwrap(), CONTENT);
+        handle.join().unwrap();
+
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || serve_once(server, None));
+        assert!(tftp_get(addr, "answer.toml").unwrap().is_none());
+        handle.join().unwrap();
+    }
+}
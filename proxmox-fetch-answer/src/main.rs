@@ -1,73 +1,239 @@
+use std::fs;
 use std::process::ExitCode;
-use std::{fs, path::PathBuf};
 
 use anyhow::{bail, format_err, Result};
-use log::{error, info, LevelFilter};
+use log::{error, info, warn};
 
 use proxmox_auto_installer::{
+    installer_logs::ENCRYPTED_ANSWER_MARKER,
     log::AutoInstLogger,
-    utils::{AutoInstSettings, FetchAnswerFrom, HttpOptions},
+    signature::{self, PublicKey},
+    template,
+    utils::{AutoInstSettings, FetchAnswerFrom, HttpOptions, NfsOptions, TftpOptions},
 };
+use proxmox_installer_common::answer::Answer;
 
-use fetch_plugins::{http::FetchFromHTTP, partition::FetchFromPartition};
+use answer_key::AnswerDecryptor;
+use fetch_plugins::{
+    http::FetchFromHTTP, nfs::FetchFromNfs, partition::FetchFromPartition, tftp::FetchFromTftp,
+    FetchedFile,
+};
 
+mod answer_key;
 mod fetch_plugins;
 
 static LOGGER: AutoInstLogger = AutoInstLogger;
 static AUTOINST_MODE_FILE: &str = "/cdrom/auto-installer-mode.toml";
+static ISO_LOOKUP_TABLE_FILE: &str = "/cdrom/answer-lookup.csv";
 
 pub fn init_log() -> Result<()> {
-    AutoInstLogger::init("/tmp/fetch_answer.log")?;
+    let level = AutoInstLogger::init("/tmp/fetch_answer.log")?;
+    AutoInstLogger::set_phase("fetch-answer");
     log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(LevelFilter::Info))
+        .map(|()| log::set_max_level(level))
         .map_err(|err| format_err!(err))
 }
 
+/// Tries the configured sources in order, returning the first valid answer file, rendered for
+/// this host.
 fn fetch_answer(install_settings: &AutoInstSettings) -> Result<String> {
-    info!("Fetching answer file in mode {:?}:", &install_settings.mode);
-    match install_settings.mode {
-        FetchAnswerFrom::Iso => {
-            let answer_path = PathBuf::from("/cdrom/answer.toml");
-            match fs::read_to_string(answer_path) {
-                Ok(answer) => return Ok(answer),
-                Err(err) => info!("Fetching answer file from ISO failed: {err}"),
+    if install_settings.mode.is_empty() {
+        bail!("No source to fetch the answer file from configured!");
+    }
+
+    let keys = match &install_settings.signing_keys {
+        Some(path) => {
+            let keys = fs::read_to_string(path)
+                .map_err(|err| format_err!("failed to read signing keys '{path}' - {err}"))
+                .and_then(|keys| signature::parse_public_keys(&keys))?;
+            info!(
+                "Only accepting answer files signed by one of {} keys.",
+                keys.len()
+            );
+            Some(keys)
+        }
+        None => None,
+    };
+
+    let mut decryptor = AnswerDecryptor::default();
+    for mode in &install_settings.mode {
+        info!("Fetching answer file in mode {mode:?}:");
+        let result = fetch_answer_in_mode(mode, install_settings, keys.as_deref(), &mut decryptor);
+
+        match result {
+            Ok(answer) => {
+                info!("Using answer file fetched in mode {mode:?}.");
+                if decryptor.decrypted_any() {
+                    // keeps the auto-installer from storing its logs on unencrypted media
+                    if let Err(err) = fs::write(ENCRYPTED_ANSWER_MARKER, "") {
+                        warn!("Failed to mark the answer file as encrypted: {err}");
+                    }
+                }
+                return Ok(answer);
             }
+            Err(err) => warn!("Fetching answer file in mode {mode:?} failed: {err}"),
         }
-        FetchAnswerFrom::Partition => match FetchFromPartition::get_answer() {
-            Ok(answer) => return Ok(answer),
-            Err(err) => info!("Fetching answer file from partition failed: {err}"),
+    }
+    bail!("Could not find any answer file!");
+}
+
+/// Fetches the answer file from a single source, then verifies, decrypts and renders it.
+fn fetch_answer_in_mode(
+    mode: &FetchAnswerFrom,
+    install_settings: &AutoInstSettings,
+    keys: Option<&[PublicKey]>,
+    decryptor: &mut AnswerDecryptor,
+) -> Result<String> {
+    let answer = fetch_answer_from(mode, install_settings)?;
+    let answer = check_signature(answer, "answer file", mode, keys)?;
+    let answer = decryptor.decrypt(answer, "answer file")?;
+    let answer = render_answer(answer, mode, install_settings, keys, decryptor)
+        .map_err(|err| format_err!("failed to render answer file template: {err}"))?;
+
+    match toml::from_str::<Answer>(&answer) {
+        Ok(_) => Ok(answer),
+        Err(err) => bail!("invalid answer file: {err}"),
+    }
+}
+
+fn fetch_answer_from(
+    mode: &FetchAnswerFrom,
+    install_settings: &AutoInstSettings,
+) -> Result<FetchedFile> {
+    match mode {
+        FetchAnswerFrom::Iso => match fs::read_to_string("/cdrom/answer.toml") {
+            Ok(content) => Ok(FetchedFile {
+                content,
+                signature: None,
+            }),
+            Err(err) => bail!("failed to read answer file - {err}"),
         },
-        FetchAnswerFrom::Http => match FetchFromHTTP::get_answer(&install_settings.http) {
-            Ok(answer) => return Ok(answer),
-            Err(err) => info!("Fetching answer file via HTTP failed: {err}"),
+        FetchAnswerFrom::Partition => FetchFromPartition::get_answer(),
+        FetchAnswerFrom::Http => FetchFromHTTP::get_answer(&install_settings.http),
+        FetchAnswerFrom::Tftp => FetchFromTftp::get_answer(&install_settings.tftp),
+        FetchAnswerFrom::Nfs => FetchFromNfs::get_answer(&install_settings.nfs),
+    }
+}
+
+/// Verifies the signature of a fetched file, if signing keys are configured. Files embedded in the
+/// ISO are trusted like the keys themselves.
+fn check_signature(
+    file: FetchedFile,
+    name: &str,
+    mode: &FetchAnswerFrom,
+    keys: Option<&[PublicKey]>,
+) -> Result<String> {
+    let keys = match keys {
+        Some(keys) if *mode != FetchAnswerFrom::Iso => keys,
+        _ => return Ok(file.content),
+    };
+
+    let signature = match &file.signature {
+        Some(signature) => signature,
+        None => bail!("{name} is not signed, but signing keys are configured"),
+    };
+    signature::verify(file.content.as_bytes(), signature, keys)
+        .map_err(|err| format_err!("invalid signature of {name}: {err}"))?;
+    info!("Signature of {name} verified.");
+
+    Ok(file.content)
+}
+
+/// Renders the answer file if it is marked as template, see the `template` module.
+fn render_answer(
+    answer: String,
+    mode: &FetchAnswerFrom,
+    install_settings: &AutoInstSettings,
+    keys: Option<&[PublicKey]>,
+    decryptor: &mut AnswerDecryptor,
+) -> Result<String> {
+    if !template::is_template(&answer) {
+        return Ok(answer);
+    }
+    info!("Answer file is a template, rendering it for this host.");
+
+    let lookup_table = match mode {
+        FetchAnswerFrom::Iso => match fs::read_to_string(ISO_LOOKUP_TABLE_FILE) {
+            Ok(content) => Some(FetchedFile {
+                content,
+                signature: None,
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => bail!("failed to read lookup table - {err}"),
         },
+        FetchAnswerFrom::Partition => FetchFromPartition::get_lookup_table()?,
+        // the HTTP server can provide a host-specific answer file directly
+        FetchAnswerFrom::Http => None,
+        FetchAnswerFrom::Tftp => FetchFromTftp::get_lookup_table(&install_settings.tftp)?,
+        FetchAnswerFrom::Nfs => FetchFromNfs::get_lookup_table()?,
+    };
+
+    let mut variables = template::system_variables()?;
+    if let Some(table) = lookup_table {
+        // the lookup table provides values for the answer file, so it must be signed as well
+        let table = check_signature(table, "lookup table", mode, keys)?;
+        let table = decryptor.decrypt(table, "lookup table")?;
+        template::apply_lookup_table(&table, &mut variables)?;
     }
-    bail!("Could not find any answer file!");
+    template::render(&answer, &variables)
 }
 
 fn settings_from_cli_args(args: &[String]) -> Result<AutoInstSettings> {
     // TODO: this was done in a bit of a hurry, needs tidying up
-    let mode = match args[1].to_lowercase().as_str() {
-        "iso" => FetchAnswerFrom::Iso,
-        "http" => FetchAnswerFrom::Http,
-        "partition" => FetchAnswerFrom::Partition,
-        "-h" | "--help" => bail!(
-            "usage: {} <http|iso|partition> [<http-url>] [<tls-cert-fingerprint>]",
+    if matches!(args[1].as_str(), "-h" | "--help") {
+        bail!(
+            "usage: {0} <http|iso|partition> [<http-url>] [<tls-cert-fingerprint>]\n       \
+             {0} tftp [<server>] [<path>]\n       \
+             {0} nfs [<server>] [<export>]\n       \
+             {0} <mode>,<mode>,..",
             args[0]
-        ),
-        _ => bail!("failed to parse fetch-from argument, not one of 'http', 'iso', or 'partition'"),
+        );
+    }
+
+    let modes = args[1]
+        .split(',')
+        .map(|mode| match mode.trim().to_lowercase().as_str() {
+            "iso" => Ok(FetchAnswerFrom::Iso),
+            "http" => Ok(FetchAnswerFrom::Http),
+            "partition" => Ok(FetchAnswerFrom::Partition),
+            "tftp" => Ok(FetchAnswerFrom::Tftp),
+            "nfs" => Ok(FetchAnswerFrom::Nfs),
+            _ => bail!(
+                "failed to parse fetch-from argument '{mode}', not one of 'http', 'iso', \
+                 'partition', 'tftp' or 'nfs'"
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut settings = AutoInstSettings {
+        mode: modes.clone(),
+        http: HttpOptions::default(),
+        tftp: TftpOptions::default(),
+        nfs: NfsOptions::default(),
+        signing_keys: None,
     };
-    if args.len() > 4 {
-    } else if args.len() > 2 && mode != FetchAnswerFrom::Http {
-        bail!("only 'http' fetch-from mode supports additional url and cert-fingerprint mode");
+    if args.len() <= 2 {
+        return Ok(settings);
     }
-    Ok(AutoInstSettings {
-        mode,
-        http: HttpOptions {
-            url: args.get(2).cloned(),
-            cert_fingerprint: args.get(3).cloned(),
-        },
-    })
+
+    match modes.as_slice() {
+        [FetchAnswerFrom::Tftp] => {
+            settings.tftp.server = args.get(2).cloned();
+            settings.tftp.path = args.get(3).cloned();
+        }
+        [FetchAnswerFrom::Nfs] => {
+            settings.nfs.server = args.get(2).cloned();
+            settings.nfs.export = args.get(3).cloned();
+        }
+        [FetchAnswerFrom::Http] => {
+            settings.http.url = args.get(2).cloned();
+            settings.http.cert_fingerprint = args.get(3).cloned();
+        }
+        _ => bail!(
+            "only a single 'http', 'tftp' or 'nfs' fetch-from mode supports additional arguments"
+        ),
+    }
+    Ok(settings)
 }
 
 fn do_main() -> Result<()> {
@@ -106,3 +272,27 @@ fn main() -> ExitCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mode_file() {
+        // as written by older versions of 'prepare-iso'
+        let settings: AutoInstSettings = toml::from_str("mode = \"iso\"\n").unwrap();
+        assert_eq!(settings.mode, [FetchAnswerFrom::Iso]);
+
+        let settings: AutoInstSettings =
+            toml::from_str("mode = [\"partition\", \"http\"]\n[http]\nurl = \"https://a\"\n")
+                .unwrap();
+        assert_eq!(
+            settings.mode,
+            [FetchAnswerFrom::Partition, FetchAnswerFrom::Http]
+        );
+
+        let settings = toml::to_string(&settings).unwrap();
+        let settings: AutoInstSettings = toml::from_str(&settings).unwrap();
+        assert_eq!(settings.http.url.as_deref(), Some("https://a"));
+    }
+}
@@ -0,0 +1,800 @@
+#[cfg(feature = "clap")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::IpAddr};
+
+use crate::{
+    options::{
+        BondMode, BondXmitHashPolicy, BtrfsRaidLevel, FsType, NetworkAddressOptions,
+        ZfsChecksumOption, ZfsCompressOption, ZfsRaidLevel,
+    },
+    setup::FirstBootHookServiceOrdering,
+    utils::{CidrAddress, Fqdn},
+};
+
+// BTreeMap is used to store filters as the order of the filters will be stable, compared to
+// storing them in a HashMap
+
+// ----- Start of `answers.toml` format definition -----
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Answer {
+    pub global: Global,
+    pub network: Network,
+    #[serde(rename = "disk-setup")]
+    pub disks: Disks,
+    pub post_installation_webhook: Option<PostNotificationHookInfo>,
+    pub first_boot: Option<FirstBootHookInfo>,
+    pub progress_report: Option<ProgressReportInfo>,
+    pub log_upload: Option<LogUploadInfo>,
+}
+
+/// An answer file with all sections and all fields of `[global]` optional, as used to pre-seed the
+/// interactive installer. The sections use the same types as [`Answer`], so unknown keys are
+/// rejected the same way.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PartialAnswer {
+    #[serde(default)]
+    pub global: PartialGlobal,
+    #[serde(default)]
+    pub network: NetworkInAnswer,
+    #[serde(rename = "disk-setup")]
+    pub disks: Option<DiskSetup>,
+    pub post_installation_webhook: Option<PostNotificationHookInfo>,
+    pub first_boot: Option<FirstBootHookInfo>,
+    pub progress_report: Option<ProgressReportInfo>,
+    pub log_upload: Option<LogUploadInfo>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Global {
+    pub country: String,
+    pub fqdn: Fqdn,
+    pub keyboard: KeyboardLayout,
+    pub mailto: String,
+    pub timezone: String,
+    pub root_password: Option<String>,
+    pub root_password_hashed: Option<String>,
+    #[serde(default)]
+    pub reboot_on_error: bool,
+    /// Shows a summary and counts down for this many seconds before starting the installation,
+    /// during which it can be aborted by pressing any key on the console.
+    pub confirm_timeout: Option<u64>,
+    #[serde(default)]
+    pub root_ssh_keys: Vec<String>,
+    /// Log level of the auto-installer, unless one is set on the kernel command line.
+    pub log_level: Option<LogLevel>,
+}
+
+/// The `[global]` section of a [`PartialAnswer`], same as [`Global`] but with all fields optional.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PartialGlobal {
+    pub country: Option<String>,
+    pub fqdn: Option<Fqdn>,
+    pub keyboard: Option<KeyboardLayout>,
+    pub mailto: Option<String>,
+    pub timezone: Option<String>,
+    pub root_password: Option<String>,
+    pub root_password_hashed: Option<String>,
+    pub reboot_on_error: Option<bool>,
+    pub confirm_timeout: Option<u64>,
+    pub root_ssh_keys: Option<Vec<String>>,
+    pub log_level: Option<LogLevel>,
+}
+
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum KeyboardLayout {
+    De,
+    DeCh,
+    Dk,
+    EnGb,
+    EnUs,
+    Es,
+    Fi,
+    Fr,
+    FrBe,
+    FrCa,
+    FrCh,
+    Hu,
+    Is,
+    It,
+    Jp,
+    Lt,
+    Mk,
+    Nl,
+    No,
+    Pl,
+    Pt,
+    PtBr,
+    Se,
+    Si,
+    Tr,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PostNotificationHookInfo {
+    /// URL to send a POST request to
+    pub url: String,
+    /// SHA256 cert fingerprint if certificate pinning should be used.
+    pub cert_fingerprint: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ProgressReportTarget {
+    /// Each event is sent as POST request with a JSON body
+    Http,
+    /// Each event is sent as syslog message via UDP
+    Syslog,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProgressReportInfo {
+    /// Where to report the installation progress to
+    pub target: ProgressReportTarget,
+    /// URL to send the events to, only for the 'http' target
+    pub url: Option<String>,
+    /// SHA256 cert fingerprint if certificate pinning should be used, only for the 'http' target
+    pub cert_fingerprint: Option<String>,
+    /// Host to send the syslog messages to, optionally with a port, defaults to 514, only for the
+    /// 'syslog' target
+    pub host: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LogUploadInfo {
+    /// URL to upload the installer logs to if the installation fails
+    pub url: String,
+    /// SHA256 cert fingerprint if certificate pinning should be used.
+    pub cert_fingerprint: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum FirstBootHookSourceMode {
+    /// Script is given inline in the answer file with the `script` option
+    FromAnswer,
+    /// Script is placed in the ISO with `proxmox-auto-install-assistant prepare-iso`
+    FromIso,
+    /// Script is placed next to the answer file on the partition labeled 'proxmox-ais'
+    FromPartition,
+    /// Script is fetched with a HTTP POST request from `url`, like the answer file itself
+    FromUrl,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FirstBootHookInfo {
+    /// Where to get the executable from
+    pub source: FirstBootHookSourceMode,
+    /// When to run the executable on the first boot
+    #[serde(default)]
+    pub ordering: FirstBootHookServiceOrdering,
+    /// Inline script, only for the 'from-answer' source
+    pub script: Option<String>,
+    /// URL to fetch the executable from, only for the 'from-url' source
+    pub url: Option<String>,
+    /// SHA256 cert fingerprint if certificate pinning should be used, only for the 'from-url'
+    /// source
+    pub cert_fingerprint: Option<String>,
+}
+
+// This is the format in `answers.toml` because `Network` is constructed from `NetworkInAnswer`.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkInAnswer {
+    #[serde(default)]
+    pub source: NetworkConfigMode,
+    pub cidr: Option<CidrAddress>,
+    pub gateway: Option<IpAddr>,
+    pub cidr6: Option<CidrAddress>,
+    pub gateway6: Option<IpAddr>,
+    pub dns: Option<OneOrMany<IpAddr>>,
+    pub filter: Option<BTreeMap<String, String>>,
+    pub interface: Option<String>,
+    pub vlan: Option<u16>,
+    #[serde(default)]
+    pub bond: Vec<NetworkBond>,
+    #[serde(default)]
+    pub bridge_ports: Vec<NetworkInterface>,
+    #[serde(default)]
+    pub vlan_interface: Vec<NetworkVlan>,
+}
+
+/// Allows a single value to be given instead of a list with just one entry.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(value: OneOrMany<T>) -> Self {
+        match value {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkBond {
+    pub name: String,
+    pub mode: BondMode,
+    pub xmit_hash_policy: Option<BondXmitHashPolicy>,
+    /// One udev filter per bond member, each selecting a single physical interface
+    pub members: Vec<BTreeMap<String, String>>,
+}
+
+/// An additional VLAN sub-interface, optionally with a static address.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkVlan {
+    pub id: u16,
+    /// The interface the VLAN is created on
+    pub interface: NetworkInterface,
+    pub cidr: Option<CidrAddress>,
+}
+
+#[derive(Clone, Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum NetworkConfigMode {
+    #[default]
+    #[serde(rename = "from-dhcp")]
+    FromDhcp,
+    #[serde(rename = "from-answer")]
+    FromAnswer,
+}
+
+// This is the format in `answers.toml` because `Disks` is constructed from `DiskSetup`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskSetup {
+    pub filesystem: Filesystem,
+    #[serde(default)]
+    pub disk_list: Vec<String>,
+    pub filter: Option<BTreeMap<String, String>>,
+    pub filter_match: Option<FilterMatch>,
+    /// Disk size in GiB, e.g. '>= 800'
+    pub filter_size: Option<NumericFilter>,
+    /// Logical block size in bytes, e.g. '4096'
+    pub filter_block_size: Option<NumericFilter>,
+    pub take_smallest: Option<usize>,
+    pub take_largest: Option<usize>,
+    pub allow_overwrite: Option<OverwritePolicy>,
+    pub btrfs: Option<BtrfsOptions>,
+    pub lvm: Option<LvmOptions>,
+    pub zfs: Option<ZfsOptions>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Filesystem {
+    Btrfs,
+    Ext4,
+    Xfs,
+    Zfs,
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum FilterMatch {
+    Any,
+    All,
+}
+
+/// Whether disks which already hold data may be overwritten by the installation.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum OverwritePolicy {
+    /// Abort the installation if any of the selected disks holds data.
+    Never,
+    /// Like `never`, but disks holding data are skipped when matching filters.
+    EmptyOnly,
+    /// Overwrite the selected disks regardless of their contents.
+    #[default]
+    Always,
+}
+
+/// A numeric comparison like '>= 800', a plain number compares for equality.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct NumericFilter {
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl NumericFilter {
+    pub fn matches(&self, value: f64) -> bool {
+        use Comparison::*;
+        match self.comparison {
+            Less => value < self.value,
+            LessOrEqual => value <= self.value,
+            Equal => value == self.value,
+            GreaterOrEqual => value >= self.value,
+            Greater => value > self.value,
+        }
+    }
+}
+
+impl TryFrom<String> for NumericFilter {
+    type Error = &'static str;
+
+    fn try_from(filter: String) -> Result<Self, Self::Error> {
+        use Comparison::*;
+        let filter = filter.trim();
+
+        // longest operators first, as '<' is a prefix of '<='
+        let operators = [
+            ("<=", LessOrEqual),
+            (">=", GreaterOrEqual),
+            ("==", Equal),
+            ("<", Less),
+            (">", Greater),
+            ("=", Equal),
+        ];
+        let (comparison, value) = operators
+            .iter()
+            .find_map(|(op, comparison)| {
+                filter
+                    .strip_prefix(op)
+                    .map(|value| (*comparison, value.trim()))
+            })
+            .unwrap_or((Equal, filter));
+
+        match value.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(NumericFilter { comparison, value }),
+            _ => Err("Invalid numeric filter, expected a number with an optional comparison like '>= 800'."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BtrfsOptions {
+    pub hdsize: Option<f64>,
+    pub raid: Option<BtrfsRaidLevel>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LvmOptions {
+    pub hdsize: Option<f64>,
+    pub swapsize: Option<f64>,
+    pub maxroot: Option<f64>,
+    pub maxvz: Option<f64>,
+    pub minfree: Option<f64>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ZfsOptions {
+    pub raid: Option<ZfsRaidLevel>,
+    pub ashift: Option<usize>,
+    pub arc_max: Option<usize>,
+    pub checksum: Option<ZfsChecksumOption>,
+    pub compress: Option<ZfsCompressOption>,
+    pub copies: Option<usize>,
+    pub hdsize: Option<f64>,
+}
+
+// ----- End of `answers.toml` format definition -----
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(try_from = "NetworkInAnswer", deny_unknown_fields)]
+pub struct Network {
+    pub network_settings: NetworkSettings,
+}
+
+#[derive(Clone, Debug)]
+pub enum NetworkSettings {
+    FromDhcp,
+    Manual(Box<NetworkManual>),
+}
+
+#[derive(Clone, Debug)]
+pub struct NetworkManual {
+    pub ipv4: Option<NetworkAddressOptions>,
+    pub ipv6: Option<NetworkAddressOptions>,
+    pub dns: Vec<IpAddr>,
+    pub interface: NetworkInterface,
+    pub vlan: Option<u16>,
+    pub bonds: Vec<NetworkBond>,
+    /// Interfaces added to the management bridge in addition to `interface`
+    pub bridge_ports: Vec<NetworkInterface>,
+    pub vlans: Vec<NetworkVlan>,
+}
+
+/// A network interface referenced in the answer file. In lists like `bridge_ports`, either a
+/// udev filter table or the name of a bond can be given.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum NetworkInterface {
+    /// Physical interface, selected by a udev filter
+    Filter(BTreeMap<String, String>),
+    /// Name of a bond defined in the answer file
+    Bond(String),
+}
+
+impl TryFrom<NetworkInAnswer> for Network {
+    type Error = &'static str;
+
+    fn try_from(network: NetworkInAnswer) -> Result<Self, Self::Error> {
+        if network.source == NetworkConfigMode::FromAnswer {
+            if network.cidr.is_none() {
+                return Err("Field 'cidr' must be set.");
+            }
+            if network.dns.is_none() {
+                return Err("Field 'dns' must be set.");
+            }
+            if network.gateway.is_none() {
+                return Err("Field 'gateway' must be set.");
+            }
+            if network.cidr6.is_some() != network.gateway6.is_some() {
+                return Err("Fields 'cidr6' and 'gateway6' must be set together.");
+            }
+
+            let primary = NetworkAddressOptions {
+                address: network.cidr.unwrap(),
+                gateway: network.gateway.unwrap(),
+            };
+            if primary.address.addr().is_ipv4() != primary.gateway.is_ipv4() {
+                return Err("Fields 'cidr' and 'gateway' must be of the same IP version.");
+            }
+
+            let (ipv4, ipv6) = match (network.cidr6, network.gateway6) {
+                (Some(address), Some(gateway)) => {
+                    if !primary.address.addr().is_ipv4() {
+                        return Err("Field 'cidr' must be an IPv4 address if 'cidr6' is set.");
+                    }
+                    if !address.addr().is_ipv6() || !gateway.is_ipv6() {
+                        return Err("Fields 'cidr6' and 'gateway6' must be IPv6 addresses.");
+                    }
+                    (
+                        Some(primary),
+                        Some(NetworkAddressOptions { address, gateway }),
+                    )
+                }
+                _ if primary.address.addr().is_ipv4() => (Some(primary), None),
+                _ => (None, Some(primary)),
+            };
+
+            let dns: Vec<IpAddr> = network.dns.unwrap().into();
+            if dns.is_empty() {
+                return Err("Field 'dns' must contain at least one address.");
+            }
+
+            let interface = match (network.filter, network.interface) {
+                (Some(filter), None) => NetworkInterface::Filter(filter),
+                (None, Some(name)) => {
+                    if !network.bond.iter().any(|bond| bond.name == name) {
+                        return Err("Field 'interface' must name a bond defined in 'bond'.");
+                    }
+                    NetworkInterface::Bond(name)
+                }
+                (None, None) => return Err("Either field 'filter' or 'interface' must be set."),
+                (Some(_), Some(_)) => return Err("Cannot use both, 'filter' and 'interface'."),
+            };
+
+            if let Some(vlan) = network.vlan {
+                if !(1..=4094).contains(&vlan) {
+                    return Err("Field 'vlan' must be between 1 and 4094.");
+                }
+            }
+
+            for (i, bond) in network.bond.iter().enumerate() {
+                verify_bond_name(&bond.name)?;
+                if network.bond[..i].iter().any(|b| b.name == bond.name) {
+                    return Err("Bond names must be unique.");
+                }
+                if bond.members.is_empty() {
+                    return Err("Bonds need at least one entry in 'members'.");
+                }
+                if bond.members.iter().any(|filter| filter.is_empty()) {
+                    return Err("Bond member filters must not be empty.");
+                }
+                if bond.xmit_hash_policy.is_some() && !bond.mode.uses_xmit_hash_policy() {
+                    return Err(
+                        "Field 'xmit_hash_policy' is only supported for the 'balance-xor' and '802.3ad' bond modes.",
+                    );
+                }
+            }
+
+            for port in &network.bridge_ports {
+                verify_interface_ref(port, &network.bond)?;
+            }
+
+            for (i, vlan) in network.vlan_interface.iter().enumerate() {
+                if !(1..=4094).contains(&vlan.id) {
+                    return Err("VLAN interface IDs must be between 1 and 4094.");
+                }
+                verify_interface_ref(&vlan.interface, &network.bond)?;
+                if network.vlan_interface[..i]
+                    .iter()
+                    .any(|v| v.id == vlan.id && v.interface == vlan.interface)
+                {
+                    return Err("VLAN interfaces must be unique.");
+                }
+            }
+
+            Ok(Network {
+                network_settings: NetworkSettings::Manual(Box::new(NetworkManual {
+                    ipv4,
+                    ipv6,
+                    dns,
+                    interface,
+                    vlan: network.vlan,
+                    bonds: network.bond,
+                    bridge_ports: network.bridge_ports,
+                    vlans: network.vlan_interface,
+                })),
+            })
+        } else {
+            if network.cidr.is_some() {
+                return Err("Field 'cidr' not supported for 'from-dhcp' config.");
+            }
+            if network.dns.is_some() {
+                return Err("Field 'dns' not supported for 'from-dhcp' config.");
+            }
+            if network.gateway.is_some() {
+                return Err("Field 'gateway' not supported for 'from-dhcp' config.");
+            }
+            if network.cidr6.is_some() {
+                return Err("Field 'cidr6' not supported for 'from-dhcp' config.");
+            }
+            if network.gateway6.is_some() {
+                return Err("Field 'gateway6' not supported for 'from-dhcp' config.");
+            }
+            if network.filter.is_some() {
+                return Err("Field 'filter' not supported for 'from-dhcp' config.");
+            }
+            if network.interface.is_some() {
+                return Err("Field 'interface' not supported for 'from-dhcp' config.");
+            }
+            if network.vlan.is_some() {
+                return Err("Field 'vlan' not supported for 'from-dhcp' config.");
+            }
+            if !network.bond.is_empty() {
+                return Err("Field 'bond' not supported for 'from-dhcp' config.");
+            }
+            if !network.bridge_ports.is_empty() {
+                return Err("Field 'bridge_ports' not supported for 'from-dhcp' config.");
+            }
+            if !network.vlan_interface.is_empty() {
+                return Err("Field 'vlan_interface' not supported for 'from-dhcp' config.");
+            }
+
+            Ok(Network {
+                network_settings: NetworkSettings::FromDhcp,
+            })
+        }
+    }
+}
+
+/// Bond names must be valid interface names and must not clash with the management bridge or
+/// VLAN sub-interfaces.
+fn verify_bond_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > 15 {
+        return Err("Bond names must be between 1 and 15 characters long.");
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(
+            "Bond names must start with a letter and only contain letters, digits, '_' and '-'.",
+        );
+    }
+    if name.starts_with("vmbr") {
+        return Err("Bond names must not start with 'vmbr'.");
+    }
+    Ok(())
+}
+
+/// Interfaces referenced in `bridge_ports` or `vlan_interface` must either be a non-empty udev
+/// filter or name a bond defined in the answer file.
+fn verify_interface_ref(
+    interface: &NetworkInterface,
+    bonds: &[NetworkBond],
+) -> Result<(), &'static str> {
+    match interface {
+        NetworkInterface::Filter(filter) if filter.is_empty() => {
+            Err("Interface filters must not be empty.")
+        }
+        NetworkInterface::Bond(name) if !bonds.iter().any(|bond| &bond.name == name) => {
+            Err("Interface names must refer to a bond defined in 'bond'.")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "DiskSetup", deny_unknown_fields)]
+pub struct Disks {
+    pub fs_type: FsType,
+    pub disk_selection: DiskSelection,
+    pub fs_options: FsOptions,
+    pub allow_overwrite: OverwritePolicy,
+}
+
+#[derive(Clone, Debug)]
+pub enum DiskSelection {
+    Selection(Vec<String>),
+    Filter(DiskFilter),
+}
+
+/// Selects disks by their udev properties, size and block size.
+#[derive(Clone, Debug)]
+pub struct DiskFilter {
+    /// Glob pattern per udev property, negated if prefixed with '!'
+    pub udev: BTreeMap<String, String>,
+    pub filter_match: FilterMatch,
+    /// Disk size in GiB
+    pub size: Option<NumericFilter>,
+    /// Logical block size in bytes
+    pub block_size: Option<NumericFilter>,
+    pub take: Option<DiskTake>,
+}
+
+/// Only use the given number of the smallest or largest matching disks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiskTake {
+    Smallest(usize),
+    Largest(usize),
+}
+
+impl DiskTake {
+    pub fn count(&self) -> usize {
+        match self {
+            DiskTake::Smallest(count) | DiskTake::Largest(count) => *count,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FsOptions {
+    BTRFS(BtrfsOptions),
+    LVM(LvmOptions),
+    ZFS(ZfsOptions),
+}
+
+impl TryFrom<DiskSetup> for Disks {
+    type Error = &'static str;
+
+    fn try_from(source: DiskSetup) -> Result<Self, Self::Error> {
+        let has_filter = source.filter.is_some()
+            || source.filter_size.is_some()
+            || source.filter_block_size.is_some();
+
+        if source.disk_list.is_empty() && !has_filter {
+            return Err("Need either 'disk_list' or 'filter' set");
+        }
+        if !source.disk_list.is_empty() && has_filter {
+            return Err("Cannot use both, 'disk_list' and 'filter'");
+        }
+
+        let take = match (source.take_smallest, source.take_largest) {
+            (Some(_), Some(_)) => {
+                return Err("Cannot use both, 'take_smallest' and 'take_largest'")
+            }
+            (Some(0), None) | (None, Some(0)) => {
+                return Err("'take_smallest' and 'take_largest' must be at least 1")
+            }
+            (Some(count), None) => Some(DiskTake::Smallest(count)),
+            (None, Some(count)) => Some(DiskTake::Largest(count)),
+            (None, None) => None,
+        };
+        if take.is_some() && !has_filter {
+            return Err("'take_smallest' and 'take_largest' can only be used with 'filter'");
+        }
+
+        let disk_selection = if !source.disk_list.is_empty() {
+            DiskSelection::Selection(source.disk_list.clone())
+        } else {
+            DiskSelection::Filter(DiskFilter {
+                udev: source.filter.clone().unwrap_or_default(),
+                filter_match: source.filter_match.clone().unwrap_or(FilterMatch::Any),
+                size: source.filter_size,
+                block_size: source.filter_block_size,
+                take,
+            })
+        };
+
+        let lvm_checks = |source: &DiskSetup| -> Result<(), Self::Error> {
+            if source.zfs.is_some() || source.btrfs.is_some() {
+                return Err("make sure only 'lvm' options are set");
+            }
+            if source.disk_list.len() > 1 {
+                return Err("make sure to define only one disk for ext4 and xfs");
+            }
+            Ok(())
+        };
+        // TODO: improve checks for foreign FS options. E.g. less verbose and handling new FS types
+        // automatically
+        let (fs, fs_options) = match source.filesystem {
+            Filesystem::Xfs => {
+                lvm_checks(&source)?;
+                (FsType::Xfs, FsOptions::LVM(source.lvm.unwrap_or_default()))
+            }
+            Filesystem::Ext4 => {
+                lvm_checks(&source)?;
+                (FsType::Ext4, FsOptions::LVM(source.lvm.unwrap_or_default()))
+            }
+            Filesystem::Zfs => {
+                if source.lvm.is_some() || source.btrfs.is_some() {
+                    return Err("make sure only 'zfs' options are set");
+                }
+                match source.zfs {
+                    None | Some(ZfsOptions { raid: None, .. }) => {
+                        return Err("ZFS raid level 'zfs.raid' must be set")
+                    }
+                    Some(opts) => (FsType::Zfs(opts.raid.unwrap()), FsOptions::ZFS(opts)),
+                }
+            }
+            Filesystem::Btrfs => {
+                if source.zfs.is_some() || source.lvm.is_some() {
+                    return Err("make sure only 'btrfs' options are set");
+                }
+                match source.btrfs {
+                    None | Some(BtrfsOptions { raid: None, .. }) => {
+                        return Err("BTRFS raid level 'btrfs.raid' must be set")
+                    }
+                    Some(opts) => (FsType::Btrfs(opts.raid.unwrap()), FsOptions::BTRFS(opts)),
+                }
+            }
+        };
+
+        let res = Disks {
+            fs_type: fs,
+            disk_selection,
+            fs_options,
+            allow_overwrite: source.allow_overwrite.unwrap_or_default(),
+        };
+        Ok(res)
+    }
+}
+
+serde_plain::derive_display_from_serialize!(KeyboardLayout);
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use crate::options::{BtrfsRaidLevel, Disk, ZfsRaidLevel};
 use crate::setup::BootType;
@@ -135,6 +135,27 @@ pub fn check_btrfs_raid_config(level: BtrfsRaidLevel, disks: &[Disk]) -> Result<
     Ok(())
 }
 
+/// Describes the data udev found on a disk, i.e. a partition table or a filesystem, LVM or ZFS
+/// signature on the whole disk. Empty if the disk holds no recognizable data.
+pub fn get_existing_disk_data(udev_props: Option<&BTreeMap<String, String>>) -> Vec<String> {
+    let props = match udev_props {
+        Some(props) => props,
+        None => return vec!["unknown contents".to_owned()],
+    };
+
+    let mut data = vec![];
+    if let Some(table_type) = props.get("ID_PART_TABLE_TYPE") {
+        data.push(format!("{table_type} partition table"));
+    }
+    if let Some(fs_type) = props.get("ID_FS_TYPE") {
+        match props.get("ID_FS_LABEL") {
+            Some(label) => data.push(format!("{fs_type} signature labeled '{label}'")),
+            None => data.push(format!("{fs_type} signature")),
+        }
+    }
+    data
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
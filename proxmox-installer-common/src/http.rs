@@ -0,0 +1,523 @@
+use anyhow::{bail, format_err, Result};
+use ring::hmac;
+use rustls::ClientConfig;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::BufReader,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use ureq::{Agent, AgentBuilder};
+
+/// Timeout for a single request, if not specified otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Header containing the UNIX timestamp the request was signed at, see [`PostOptions`].
+pub const TIMESTAMP_HEADER: &str = "X-Proxmox-Timestamp";
+/// Header containing the HMAC-SHA256 signature of the request, see [`PostOptions`].
+pub const SIGNATURE_HEADER: &str = "X-Proxmox-Signature";
+/// Maximum difference between the timestamp of a signed request and the current time, see
+/// [`verify_request_signature`].
+pub const MAX_SIGNATURE_AGE: Duration = Duration::from_secs(300);
+
+/// Additional options for [`post_with_options`].
+#[derive(Clone)]
+pub struct PostOptions {
+    /// Timeout for the whole request, including connecting and the TLS handshake
+    pub timeout: Duration,
+    /// PEM-encoded client certificate chain and private key, to authenticate via mutual TLS
+    pub client_cert: Option<(Vec<u8>, Vec<u8>)>,
+    /// Shared secret to sign the request with. The signature is sent as hex-encoded HMAC-SHA256
+    /// over the timestamp, a newline and the payload, as `sha256=<signature>` in the
+    /// [`SIGNATURE_HEADER`], the timestamp in the [`TIMESTAMP_HEADER`]. Servers should also check
+    /// that the timestamp is recent, to prevent replaying requests.
+    pub hmac_secret: Option<Vec<u8>>,
+    /// PEM-encoded CA certificates to verify the server certificate against, instead of the
+    /// trusted CAs of the system
+    pub ca_certs: Option<Vec<u8>>,
+    /// SHA256 fingerprint of a CA certificate, which the server must send along with its
+    /// certificate. The server certificate is then verified against this CA.
+    pub ca_fingerprint: Option<String>,
+    /// Name the server certificate must be valid for, instead of the host of the URL
+    pub server_name: Option<String>,
+}
+
+impl Default for PostOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            client_cert: None,
+            hmac_secret: None,
+            ca_certs: None,
+            ca_fingerprint: None,
+            server_name: None,
+        }
+    }
+}
+
+/// Body and headers of a response to a request.
+pub struct Response {
+    pub body: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Response {
+    /// Returns the value of the header, the name is matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Issues a POST request with the payload (JSON). Optionally a SHA256 fingerprint can be used to
+/// check the cert against it, instead of the regular cert validation.
+/// To gather the sha256 fingerprint you can use the following command:
+/// ```no_compile
+/// openssl s_client -connect <host>:443 < /dev/null 2>/dev/null | openssl x509 -fingerprint -sha256  -noout -in /dev/stdin
+/// ```
+///
+/// # Arguments
+/// * `url` - URL to call
+/// * `fingerprint` - SHA256 cert fingerprint if certificate pinning should be used. Optional.
+/// * `payload` - The payload to send to the server. Expected to be a JSON formatted string.
+pub fn post(url: &str, fingerprint: Option<&str>, payload: String) -> Result<String> {
+    Ok(post_with_options(url, fingerprint, payload, &PostOptions::default())?.body)
+}
+
+/// Issues a POST request like [`post`], with a custom timeout, custom CAs and optional client
+/// authentication. A certificate fingerprint still takes precedence over any CA set in the options.
+/// Returns the headers of the response along with its body.
+pub fn post_with_options(
+    url: &str,
+    fingerprint: Option<&str>,
+    payload: String,
+    options: &PostOptions,
+) -> Result<Response> {
+    Client::new(fingerprint, options.clone())?.post(url, payload)
+}
+
+/// Client for issuing several POST requests with the same settings, which only sets up the TLS
+/// configuration once instead of for every request.
+pub struct Client {
+    agent: Agent,
+    options: PostOptions,
+}
+
+impl Client {
+    /// Creates a new client, see [`post_with_options`] for the meaning of the arguments.
+    pub fn new(fingerprint: Option<&str>, options: PostOptions) -> Result<Self> {
+        Ok(Self {
+            agent: build_agent(fingerprint, &options)?,
+            options,
+        })
+    }
+
+    /// Issues a POST request with the payload (JSON), like [`post_with_options`].
+    pub fn post(&self, url: &str, payload: String) -> Result<Response> {
+        let mut request = self
+            .agent
+            .post(url)
+            .set("Content-type", "application/json; charset=utf-8")
+            .timeout(self.options.timeout);
+
+        if let Some(secret) = &self.options.hmac_secret {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            request = request.set(TIMESTAMP_HEADER, &timestamp.to_string()).set(
+                SIGNATURE_HEADER,
+                &sign_request(secret, timestamp, payload.as_bytes()),
+            );
+        }
+
+        let response = request.send_string(&payload)?;
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_owned();
+                Some((name, value))
+            })
+            .collect();
+
+        Ok(Response {
+            body: response.into_string()?,
+            headers,
+        })
+    }
+}
+
+/// Issues a GET request and returns the body. The certificate is checked the same way as for
+/// [`post`], the request times out after [`DEFAULT_TIMEOUT`].
+///
+/// # Arguments
+/// * `url` - URL to call
+/// * `fingerprint` - SHA256 cert fingerprint if certificate pinning should be used. Optional.
+pub fn get(url: &str, fingerprint: Option<&str>) -> Result<String> {
+    let answer = build_agent(fingerprint, &PostOptions::default())?
+        .get(url)
+        .timeout(DEFAULT_TIMEOUT)
+        .call()?
+        .into_string()?;
+
+    Ok(answer)
+}
+
+fn build_agent(fingerprint: Option<&str>, options: &PostOptions) -> Result<Agent> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let mut agent = AgentBuilder::new();
+
+    let builder = if let Some(fingerprint) = fingerprint {
+        builder.with_custom_certificate_verifier(VerifyCertFingerprint::new(fingerprint)?)
+    } else if options.ca_certs.is_some()
+        || options.ca_fingerprint.is_some()
+        || options.server_name.is_some()
+    {
+        builder.with_custom_certificate_verifier(VerifyCa::new(options)?)
+    } else {
+        agent = agent.tls_connector(Arc::new(native_tls::TlsConnector::new()?));
+        let verifier = rustls::client::WebPkiVerifier::new(native_roots()?, None);
+        builder.with_custom_certificate_verifier(Arc::new(verifier))
+    };
+
+    let tls_config = match &options.client_cert {
+        Some((cert, key)) => {
+            let (certs, key) = parse_client_cert(cert, key)?;
+            builder.with_single_cert(certs, key)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(agent.tls_config(Arc::new(tls_config)).build())
+}
+
+fn native_roots() -> Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        roots.add(&rustls::Certificate(cert.0)).unwrap();
+    }
+    Ok(roots)
+}
+
+/// Parses PEM-encoded certificates, e.g. a CA bundle. Other PEM items are skipped.
+pub fn parse_certs(pem: &[u8]) -> Result<Vec<rustls::Certificate>> {
+    let certs: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut BufReader::new(pem))
+        .map_err(|err| format_err!("failed to parse certificates - {err}"))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if certs.is_empty() {
+        bail!("no certificate found");
+    }
+    Ok(certs)
+}
+
+/// Parses the first PEM-encoded private key, in PKCS#8, PKCS#1 or SEC1 format. Other PEM items,
+/// e.g. the certificate in a combined file, are skipped.
+pub fn parse_private_key(pem: &[u8]) -> Result<rustls::PrivateKey> {
+    let mut reader = BufReader::new(pem);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|err| format_err!("failed to parse private key - {err}"))?
+        {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(rustls::PrivateKey(key)),
+            Some(_) => continue,
+            None => bail!("no private key found"),
+        }
+    }
+}
+
+/// Parses a PEM-encoded certificate chain and private key.
+fn parse_client_cert(
+    cert: &[u8],
+    key: &[u8],
+) -> Result<(Vec<rustls::Certificate>, rustls::PrivateKey)> {
+    let certs = parse_certs(cert).map_err(|err| format_err!("client certificate: {err}"))?;
+    let key = parse_private_key(key).map_err(|err| format_err!("client key: {err}"))?;
+
+    Ok((certs, key))
+}
+
+/// Verifies the signature of a request made by [`post_with_options`] with an HMAC secret, given
+/// the values of its [`TIMESTAMP_HEADER`] and [`SIGNATURE_HEADER`]. The signature is compared in
+/// constant time. Requests whose timestamp differs from the current time by more than
+/// [`MAX_SIGNATURE_AGE`] are rejected, to limit replaying them.
+pub fn verify_request_signature(
+    secret: &[u8],
+    timestamp: Option<&str>,
+    signature: Option<&str>,
+    payload: &[u8],
+) -> Result<()> {
+    let (timestamp, signature) = match (timestamp, signature) {
+        (Some(timestamp), Some(signature)) => (timestamp.trim(), signature.trim()),
+        _ => bail!("request is not signed"),
+    };
+    let timestamp: u64 = timestamp
+        .parse()
+        .map_err(|_| format_err!("invalid timestamp '{timestamp}'"))?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if now.abs_diff(timestamp) > MAX_SIGNATURE_AGE.as_secs() {
+        bail!("request timestamp is off by {}s", now.abs_diff(timestamp));
+    }
+
+    let tag = signature
+        .strip_prefix("sha256=")
+        .and_then(|tag| hex::decode(tag).ok())
+        .ok_or_else(|| format_err!("invalid request signature"))?;
+    hmac::verify(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret),
+        &signed_message(timestamp, payload),
+        &tag,
+    )
+    .map_err(|_| format_err!("invalid request signature"))
+}
+
+/// Reads a shared HMAC secret from a file, ignoring trailing whitespace as left by most editors.
+pub fn read_hmac_secret(path: &Path) -> Result<Vec<u8>> {
+    let mut secret = fs::read(path).map_err(|err| format_err!("failed to read {path:?}: {err}"))?;
+    while secret.last().is_some_and(u8::is_ascii_whitespace) {
+        secret.pop();
+    }
+    if secret.is_empty() {
+        bail!("HMAC secret {path:?} is empty");
+    }
+    Ok(secret)
+}
+
+/// Returns the value of the [`SIGNATURE_HEADER`] for the request.
+fn sign_request(secret: &[u8], timestamp: u64, payload: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, &signed_message(timestamp, payload));
+    format!("sha256={}", hex::encode(tag.as_ref()))
+}
+
+/// The message covered by the signature, the timestamp and the payload separated by a newline.
+fn signed_message(timestamp: u64, payload: &[u8]) -> Vec<u8> {
+    [format!("{timestamp}\n").as_bytes(), payload].concat()
+}
+
+struct VerifyCertFingerprint {
+    cert_fingerprint: Vec<u8>,
+}
+
+impl VerifyCertFingerprint {
+    fn new<S: AsRef<str>>(cert_fingerprint: S) -> Result<std::sync::Arc<Self>> {
+        let cert_fingerprint = cert_fingerprint.as_ref();
+        let sanitized = cert_fingerprint.replace(':', "");
+        let decoded = hex::decode(sanitized)?;
+        Ok(std::sync::Arc::new(Self {
+            cert_fingerprint: decoded,
+        }))
+    }
+}
+
+impl rustls::client::ServerCertVerifier for VerifyCertFingerprint {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let mut hasher = Sha256::new();
+        hasher.update(end_entity);
+        let result = hasher.finalize();
+
+        if result.as_slice() == self.cert_fingerprint {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("Fingerprint did not match!".into()))
+        }
+    }
+}
+
+/// Verifies the server certificate against custom CAs, and optionally a fixed server name.
+struct VerifyCa {
+    roots: Option<rustls::RootCertStore>,
+    ca_fingerprint: Option<Vec<u8>>,
+    server_name: Option<rustls::ServerName>,
+}
+
+impl VerifyCa {
+    fn new(options: &PostOptions) -> Result<Arc<Self>> {
+        // a CA fingerprint replaces any other CA, the roots are then built on verification
+        let roots = match (&options.ca_fingerprint, &options.ca_certs) {
+            (Some(_), _) => None,
+            (None, Some(pem)) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in parse_certs(pem).map_err(|err| format_err!("CA bundle: {err}"))? {
+                    roots
+                        .add(&cert)
+                        .map_err(|err| format_err!("invalid CA certificate - {err}"))?;
+                }
+                Some(roots)
+            }
+            (None, None) => Some(native_roots()?),
+        };
+
+        let ca_fingerprint = match &options.ca_fingerprint {
+            Some(fingerprint) => Some(hex::decode(fingerprint.replace(':', ""))?),
+            None => None,
+        };
+
+        let server_name = match &options.server_name {
+            Some(name) => Some(
+                rustls::ServerName::try_from(name.as_str())
+                    .map_err(|_| format_err!("invalid server name '{name}'"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Arc::new(Self {
+            roots,
+            ca_fingerprint,
+            server_name,
+        }))
+    }
+}
+
+impl rustls::client::ServerCertVerifier for VerifyCa {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let roots = match (&self.roots, &self.ca_fingerprint) {
+            (Some(roots), _) => roots.clone(),
+            (None, Some(fingerprint)) => {
+                let ca = intermediates
+                    .iter()
+                    .find(|cert| Sha256::digest(&cert.0).as_slice() == fingerprint)
+                    .ok_or_else(|| {
+                        rustls::Error::General("No CA certificate matching the fingerprint!".into())
+                    })?;
+                let mut roots = rustls::RootCertStore::empty();
+                roots
+                    .add(ca)
+                    .map_err(|err| rustls::Error::General(err.to_string()))?;
+                roots
+            }
+            (None, None) => rustls::RootCertStore::empty(),
+        };
+
+        rustls::client::WebPkiVerifier::new(roots, None).verify_server_cert(
+            end_entity,
+            intermediates,
+            self.server_name.as_ref().unwrap_or(server_name),
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustls::client::ServerCertVerifier;
+
+    const CA_CERT: &[u8] = include_bytes!("../tests/resources/http/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../tests/resources/http/server.pem");
+    // client certificate followed by its key, as one combined file
+    const CLIENT_CERT_AND_KEY: &[u8] = include_bytes!("../tests/resources/http/client.pem");
+
+    fn verify(options: &PostOptions, intermediates: &[rustls::Certificate], name: &str) -> bool {
+        let server_cert = parse_certs(SERVER_CERT).unwrap().remove(0);
+        VerifyCa::new(options)
+            .unwrap()
+            .verify_server_cert(
+                &server_cert,
+                intermediates,
+                &rustls::ServerName::try_from(name).unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn verify_custom_ca() {
+        let ca_cert = parse_certs(CA_CERT).unwrap();
+        let options = PostOptions {
+            ca_certs: Some(CA_CERT.to_vec()),
+            ..Default::default()
+        };
+        assert!(verify(&options, &[], "answer.example.com"));
+        assert!(!verify(&options, &[], "other.example.com"));
+
+        let options = PostOptions {
+            ca_certs: Some(CA_CERT.to_vec()),
+            server_name: Some("answer.example.com".to_owned()),
+            ..Default::default()
+        };
+        assert!(verify(&options, &[], "192.0.2.1"));
+
+        let options = PostOptions {
+            ca_certs: Some(SERVER_CERT.to_vec()),
+            ..Default::default()
+        };
+        assert!(!verify(&options, &[], "answer.example.com"));
+
+        let options = PostOptions {
+            ca_fingerprint: Some(hex::encode(Sha256::digest(&ca_cert[0].0))),
+            ..Default::default()
+        };
+        assert!(verify(&options, &ca_cert, "answer.example.com"));
+        assert!(!verify(&options, &[], "answer.example.com"));
+    }
+
+    #[test]
+    fn parse_combined_client_cert() {
+        let (certs, key) = parse_client_cert(CLIENT_CERT_AND_KEY, CLIENT_CERT_AND_KEY).unwrap();
+        assert_eq!(certs.len(), 1);
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_single_cert(certs, key)
+            .unwrap();
+
+        assert!(parse_private_key(CA_CERT).is_err());
+        assert!(parse_client_cert(CA_CERT, CA_CERT).is_err());
+    }
+
+    #[test]
+    fn verify_signed_request() {
+        let payload = br#"{"dmi": {}}"#;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let verify = |timestamp: u64, signature: &str| {
+            verify_request_signature(
+                b"secret",
+                Some(&timestamp.to_string()),
+                Some(signature),
+                payload,
+            )
+        };
+
+        let signature = sign_request(b"secret", now, payload);
+        verify(now, &signature).unwrap();
+        assert!(verify(now + 1, &signature).is_err());
+        assert!(verify(now, &sign_request(b"other", now, payload)).is_err());
+        assert!(verify(now, &signature[..signature.len() - 1]).is_err());
+
+        let stale = now - MAX_SIGNATURE_AGE.as_secs() - 10;
+        assert!(verify(stale, &sign_request(b"secret", stale, payload)).is_err());
+
+        assert!(verify_request_signature(b"secret", None, None, payload).is_err());
+    }
+}
@@ -1,6 +1,10 @@
+pub mod answer;
 pub mod disk_checks;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod options;
 pub mod setup;
+pub mod udevinfo;
 pub mod utils;
 
 pub const RUNTIME_DIR: &str = "/run/proxmox-installer";
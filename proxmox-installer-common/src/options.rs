@@ -1,5 +1,5 @@
-use serde::Deserialize;
-use std::net::{IpAddr, Ipv4Addr};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use std::{cmp, fmt};
 
 use crate::setup::{
@@ -327,13 +327,75 @@ impl TimezoneOptions {
     }
 }
 
+/// Bonding modes as understood by ifupdown2, see also `bond-mode` in interfaces(5).
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BondMode {
+    #[serde(rename = "balance-rr")]
+    BalanceRr,
+    #[serde(rename = "active-backup")]
+    ActiveBackup,
+    #[serde(rename = "balance-xor")]
+    BalanceXor,
+    #[serde(rename = "broadcast")]
+    Broadcast,
+    #[serde(rename = "802.3ad")]
+    Lacp,
+    #[serde(rename = "balance-tlb")]
+    BalanceTlb,
+    #[serde(rename = "balance-alb")]
+    BalanceAlb,
+}
+
+impl BondMode {
+    /// Whether the transmit hash policy is used by this mode.
+    pub fn uses_xmit_hash_policy(&self) -> bool {
+        matches!(self, BondMode::BalanceXor | BondMode::Lacp)
+    }
+}
+
+impl fmt::Display for BondMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BondMode::*;
+        match self {
+            BalanceRr => write!(f, "balance-rr"),
+            ActiveBackup => write!(f, "active-backup"),
+            BalanceXor => write!(f, "balance-xor"),
+            Broadcast => write!(f, "broadcast"),
+            Lacp => write!(f, "802.3ad"),
+            BalanceTlb => write!(f, "balance-tlb"),
+            BalanceAlb => write!(f, "balance-alb"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BondXmitHashPolicy {
+    #[serde(rename = "layer2")]
+    Layer2,
+    #[serde(rename = "layer2+3")]
+    Layer2_3,
+    #[serde(rename = "layer3+4")]
+    Layer3_4,
+    #[serde(rename = "encap2+3")]
+    Encap2_3,
+    #[serde(rename = "encap3+4")]
+    Encap3_4,
+}
+
+/// Static address configuration of a single address family.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkAddressOptions {
+    pub address: CidrAddress,
+    pub gateway: IpAddr,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct NetworkOptions {
     pub ifname: String,
     pub fqdn: Fqdn,
-    pub address: CidrAddress,
-    pub gateway: IpAddr,
-    pub dns_server: IpAddr,
+    pub ipv4: Option<NetworkAddressOptions>,
+    pub ipv6: Option<NetworkAddressOptions>,
+    pub dns_servers: Vec<IpAddr>,
 }
 
 impl NetworkOptions {
@@ -343,38 +405,40 @@ impl NetworkOptions {
         let mut this = Self {
             ifname: String::new(),
             fqdn: Self::construct_fqdn(network, setup.config.product.default_hostname()),
-            // Safety: The provided mask will always be valid.
-            address: CidrAddress::new(Ipv4Addr::UNSPECIFIED, 0).unwrap(),
-            gateway: Ipv4Addr::UNSPECIFIED.into(),
-            dns_server: Ipv4Addr::UNSPECIFIED.into(),
+            ipv4: None,
+            ipv6: None,
+            dns_servers: network.dns.dns.clone(),
         };
 
-        if let Some(ip) = network.dns.dns.first() {
-            this.dns_server = *ip;
-        }
-
         if let Some(routes) = &network.routes {
-            let mut filled = false;
             if let Some(gw) = &routes.gateway4 {
                 if let Some(iface) = network.interfaces.get(&gw.dev) {
                     this.ifname.clone_from(&iface.name);
                     if let Some(addresses) = &iface.addresses {
                         if let Some(addr) = addresses.iter().find(|addr| addr.is_ipv4()) {
-                            this.gateway = gw.gateway;
-                            this.address = addr.clone();
-                            filled = true;
+                            this.ipv4 = Some(NetworkAddressOptions {
+                                address: addr.clone(),
+                                gateway: gw.gateway,
+                            });
                         }
                     }
                 }
             }
-            if !filled {
-                if let Some(gw) = &routes.gateway6 {
-                    if let Some(iface) = network.interfaces.get(&gw.dev) {
+            if let Some(gw) = &routes.gateway6 {
+                if let Some(iface) = network.interfaces.get(&gw.dev) {
+                    if this.ifname.is_empty() {
+                        this.ifname.clone_from(&iface.name);
+                    }
+                    // only a single management interface is supported, so the IPv6
+                    // configuration must be on the same interface as the IPv4 one
+                    if this.ipv4.is_none() || this.ifname == iface.name {
                         if let Some(addresses) = &iface.addresses {
                             if let Some(addr) = addresses.iter().find(|addr| addr.is_ipv6()) {
                                 this.ifname.clone_from(&iface.name);
-                                this.gateway = gw.gateway;
-                                this.address = addr.clone();
+                                this.ipv6 = Some(NetworkAddressOptions {
+                                    address: addr.clone(),
+                                    gateway: gw.gateway,
+                                });
                             }
                         }
                     }
@@ -385,6 +449,17 @@ impl NetworkOptions {
         this
     }
 
+    /// The address configuration used for e.g. `/etc/hosts`, preferring IPv4 for dual-stack
+    /// setups.
+    pub fn primary(&self) -> Option<&NetworkAddressOptions> {
+        self.ipv4.as_ref().or(self.ipv6.as_ref())
+    }
+
+    /// The IPv6 configuration, if it is configured in addition to the primary IPv4 one.
+    pub fn secondary(&self) -> Option<&NetworkAddressOptions> {
+        self.ipv4.as_ref().and(self.ipv6.as_ref())
+    }
+
     fn construct_fqdn(network: &NetworkInfo, default_hostname: &str) -> Fqdn {
         let hostname = network.hostname.as_deref().unwrap_or(default_hostname);
 
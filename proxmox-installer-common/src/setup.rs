@@ -13,8 +13,8 @@ use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
     options::{
-        BtrfsRaidLevel, Disk, FsType, ZfsBootdiskOptions, ZfsChecksumOption, ZfsCompressOption,
-        ZfsRaidLevel,
+        BondMode, BondXmitHashPolicy, BtrfsRaidLevel, Disk, FsType, ZfsBootdiskOptions,
+        ZfsChecksumOption, ZfsCompressOption, ZfsRaidLevel,
     },
     utils::CidrAddress,
 };
@@ -54,6 +54,9 @@ pub struct ProductConfig {
     pub product: ProxmoxProduct,
     #[serde(deserialize_with = "deserialize_bool_from_int")]
     pub enable_btrfs: bool,
+    /// Whether the management interface is placed on the bridge `vmbr0`
+    #[serde(default, deserialize_with = "deserialize_bool_from_int")]
+    pub bridged_network: bool,
 }
 
 impl ProductConfig {
@@ -63,6 +66,7 @@ impl ProductConfig {
             fullname: String::from("Proxmox VE (mocked)"),
             product: ProxmoxProduct::PVE,
             enable_btrfs: true,
+            bridged_network: true,
         }
     }
 }
@@ -312,6 +316,17 @@ where
     serializer.collect_str(value)
 }
 
+fn serialize_opt_as_display<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: fmt::Display,
+{
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
 #[derive(Clone, Deserialize)]
 pub struct RuntimeInfo {
     /// Whether is system was booted in (legacy) BIOS or UEFI mode.
@@ -500,6 +515,15 @@ pub struct InstallConfig {
     pub root_ssh_keys: Vec<String>,
 
     pub mngmt_nic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mngmt_vlan: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bonds: Vec<InstallBondConfig>,
+    /// Interfaces added to `vmbr0` in addition to `mngmt_nic`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bridge_ports: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vlans: Vec<InstallVlanConfig>,
 
     pub hostname: String,
     pub domain: String,
@@ -507,8 +531,71 @@ pub struct InstallConfig {
     pub cidr: CidrAddress,
     pub gateway: IpAddr,
     pub dns: IpAddr,
+    /// IPv6 configuration for dual-stack setups, in which case `cidr` and `gateway` are IPv4
+    #[serde(
+        default,
+        serialize_with = "serialize_opt_as_display",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cidr6: Option<CidrAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway6: Option<IpAddr>,
+    /// DNS servers in addition to `dns`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dns_additional: Vec<IpAddr>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_boot: Option<InstallFirstBootSetup>,
+}
+
+/// A bond interface, to be written to `/etc/network/interfaces` by the low-level installer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InstallBondConfig {
+    pub name: String,
+    pub mode: BondMode,
+    /// Names of the physical network interfaces enslaved to the bond
+    pub members: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xmit_hash_policy: Option<BondXmitHashPolicy>,
+}
+
+/// A VLAN sub-interface, to be written to `/etc/network/interfaces` by the low-level installer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InstallVlanConfig {
+    /// Name of the interface the VLAN is created on, either a physical interface or a bond
+    pub parent: String,
+    pub id: u16,
+    #[serde(
+        default,
+        serialize_with = "serialize_opt_as_display",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cidr: Option<CidrAddress>,
 }
 
+/// When the first-boot hook should be run on the installed system, relative to the network
+/// being set up.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirstBootHookServiceOrdering {
+    /// Before the network is configured, e.g. to set up the network configuration itself
+    BeforeNetwork,
+    /// After the network is up and online
+    #[default]
+    NetworkOnline,
+}
+
+/// First-boot hook setup passed to the low-level installer. The hook executable itself is placed
+/// in the runtime directory, see [`FIRST_BOOT_HOOK_FILE`].
+#[derive(Clone, Deserialize, Serialize)]
+pub struct InstallFirstBootSetup {
+    pub ordering: FirstBootHookServiceOrdering,
+}
+
+/// Name of the first-boot hook executable in the runtime directory, which gets copied into the
+/// installed system by the low-level installer.
+pub const FIRST_BOOT_HOOK_FILE: &str = "proxmox-first-boot-hook";
+
 fn serialize_disk_opt<S>(value: &Option<Disk>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -0,0 +1,280 @@
+use anyhow::{bail, Context as _, Result};
+use glob::Pattern;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{answer, options::Disk, setup::RuntimeInfo};
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct UdevInfo {
+    // use BTreeMap to have keys sorted
+    pub disks: BTreeMap<String, BTreeMap<String, String>>,
+    pub nics: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+pub fn get_single_udev_index(
+    filter: &BTreeMap<String, String>,
+    udev_list: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<String> {
+    if filter.is_empty() {
+        bail!("no filter defined");
+    }
+    let mut dev_index: Option<String> = None;
+    'outer: for (dev, dev_values) in udev_list {
+        for (filter_key, filter_value) in filter {
+            let filter_pattern =
+                Pattern::new(filter_value).context("invalid glob in disk selection")?;
+            for (udev_key, udev_value) in dev_values {
+                if udev_key == filter_key && filter_pattern.matches(udev_value) {
+                    dev_index = Some(dev.clone());
+                    break 'outer; // take first match
+                }
+            }
+        }
+    }
+    if dev_index.is_none() {
+        bail!("filter did not match any device");
+    }
+
+    Ok(dev_index.unwrap())
+}
+
+pub fn get_matched_udev_indexes(
+    filter: &BTreeMap<String, String>,
+    udev_list: &BTreeMap<String, BTreeMap<String, String>>,
+    match_all: bool,
+) -> Result<Vec<String>> {
+    let mut matches = vec![];
+    for (dev, dev_values) in udev_list {
+        if udev_filter_matches(filter, dev_values, match_all)? {
+            matches.push(dev.clone());
+        }
+    }
+    if matches.is_empty() {
+        bail!("filter did not match any devices");
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Checks the udev properties of a device against the filter. A pattern prefixed with '!' matches
+/// if the property does not match the rest of the pattern, or is not set at all. Other patterns
+/// for properties the device does not have are ignored, also with `match_all`.
+fn udev_filter_matches(
+    filter: &BTreeMap<String, String>,
+    dev_values: &BTreeMap<String, String>,
+    match_all: bool,
+) -> Result<bool> {
+    let mut did_match_once = false;
+    let mut did_match_all = true;
+    for (filter_key, filter_value) in filter {
+        let (negated, pattern) = match filter_value.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, filter_value.as_str()),
+        };
+        let filter_pattern = Pattern::new(pattern).context("invalid glob in disk selection")?;
+
+        let matched = match dev_values.get(filter_key) {
+            Some(udev_value) => filter_pattern.matches(udev_value),
+            None if negated => false,
+            None => continue,
+        };
+        if matched != negated {
+            did_match_once = true;
+        } else {
+            did_match_all = false;
+        }
+    }
+    Ok(if match_all {
+        did_match_all
+    } else {
+        did_match_once
+    })
+}
+
+/// Returns the indexes of all disks matching the filter, sorted by index.
+pub fn get_matched_disks(
+    filter: &answer::DiskFilter,
+    udev_list: &BTreeMap<String, BTreeMap<String, String>>,
+    disks: &[Disk],
+) -> Result<Vec<String>> {
+    let match_all = filter.filter_match == answer::FilterMatch::All;
+
+    let mut matches = vec![];
+    for disk in disks {
+        if !filter.udev.is_empty() {
+            let dev_values = match udev_list.get(&disk.index) {
+                Some(dev_values) => dev_values,
+                None => continue,
+            };
+            if !udev_filter_matches(&filter.udev, dev_values, match_all)? {
+                continue;
+            }
+        }
+        if let Some(size) = &filter.size {
+            if !size.matches(disk.size) {
+                continue;
+            }
+        }
+        if let Some(block_size) = &filter.block_size {
+            match disk.block_size {
+                Some(value) if block_size.matches(value as f64) => {}
+                _ => continue,
+            }
+        }
+        matches.push(disk);
+    }
+
+    if let Some(take) = filter.take {
+        // stable sort, disks of the same size stay in order
+        match take {
+            answer::DiskTake::Smallest(_) => matches.sort_by(|a, b| a.size.total_cmp(&b.size)),
+            answer::DiskTake::Largest(_) => matches.sort_by(|a, b| b.size.total_cmp(&a.size)),
+        }
+        matches.truncate(take.count());
+    }
+
+    let mut indexes: Vec<String> = matches.iter().map(|disk| disk.index.clone()).collect();
+    if indexes.is_empty() {
+        bail!("filter did not match any devices");
+    }
+    indexes.sort();
+    Ok(indexes)
+}
+
+/// Resolves the entries of 'disk_list' to the disks of the system. An entry can be the device
+/// name, e.g. 'sda' or '/dev/sda', a link in '/dev/disk/by-id' or '/dev/disk/by-path', with or
+/// without the directory, a WWN or a serial number.
+pub fn get_disks_from_list(
+    disk_list: &[String],
+    udev_info: &UdevInfo,
+    runtime_info: &RuntimeInfo,
+) -> Result<Vec<Disk>> {
+    let mut disks: Vec<Disk> = vec![];
+    let mut unmatched = vec![];
+
+    for entry in disk_list {
+        let matches: Vec<&Disk> = runtime_info
+            .disks
+            .iter()
+            .filter(|disk| disk_matches_entry(disk, udev_info.disks.get(&disk.index), entry))
+            .collect();
+
+        match matches[..] {
+            [] => unmatched.push(format!("'{entry}'")),
+            [disk] if disks.iter().any(|d| d.index == disk.index) => {
+                bail!("disk '{entry}' in 'disk_list' is selected more than once");
+            }
+            [disk] => disks.push(disk.clone()),
+            _ => {
+                let paths: Vec<&str> = matches.iter().map(|disk| disk.path.as_str()).collect();
+                bail!(
+                    "disk '{entry}' in 'disk_list' is ambiguous, matches {}",
+                    paths.join(", ")
+                );
+            }
+        }
+    }
+
+    if !unmatched.is_empty() {
+        let candidates: Vec<String> = runtime_info
+            .disks
+            .iter()
+            .map(|disk| {
+                let serial = udev_info
+                    .disks
+                    .get(&disk.index)
+                    .and_then(|props| props.get("ID_SERIAL"));
+                match serial {
+                    Some(serial) => format!("{} (serial {serial})", disk.path),
+                    None => disk.path.clone(),
+                }
+            })
+            .collect();
+        bail!(
+            "disks {} in 'disk_list' not found, available disks: {}",
+            unmatched.join(", "),
+            candidates.join(", ")
+        );
+    }
+
+    Ok(disks)
+}
+
+fn disk_matches_entry(
+    disk: &Disk,
+    udev_props: Option<&BTreeMap<String, String>>,
+    entry: &str,
+) -> bool {
+    let name = entry.strip_prefix("/dev/").unwrap_or(entry);
+    if disk.path.strip_prefix("/dev/") == Some(name) {
+        return true;
+    }
+
+    let udev_props = match udev_props {
+        Some(udev_props) => udev_props,
+        None => return false,
+    };
+
+    let devlinks = udev_props
+        .get("DEVLINKS")
+        .map(|links| links.split_whitespace().collect())
+        .unwrap_or(Vec::new());
+    // only allow links with a stable name, unlike e.g. 'by-diskseq'
+    let stable_link = devlinks.iter().any(|link| {
+        ["/dev/disk/by-id/", "/dev/disk/by-path/"]
+            .iter()
+            .filter_map(|dir| link.strip_prefix(dir))
+            .any(|link_name| {
+                *link == entry
+                    || link_name == entry
+                    || entry.strip_prefix("by-id/") == Some(link_name)
+                    || entry.strip_prefix("by-path/") == Some(link_name)
+            })
+    });
+
+    stable_link
+        || [
+            "ID_WWN",
+            "ID_WWN_WITH_EXTENSION",
+            "ID_SERIAL",
+            "ID_SERIAL_SHORT",
+        ]
+        .iter()
+        .any(|key| udev_props.get(*key).map(String::as_str) == Some(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn filter_ignores_missing_properties() {
+        let dev = props(&[("ID_MODEL", "Samsung SSD 970"), ("ID_BUS", "nvme")]);
+
+        let filter = props(&[("ID_MODEL", "Samsung*"), ("ID_SERIAL", "S4*")]);
+        assert!(udev_filter_matches(&filter, &dev, true).unwrap());
+        assert!(udev_filter_matches(&filter, &dev, false).unwrap());
+
+        let filter = props(&[("ID_SERIAL", "S4*")]);
+        assert!(udev_filter_matches(&filter, &dev, true).unwrap());
+        assert!(!udev_filter_matches(&filter, &dev, false).unwrap());
+
+        let filter = props(&[("ID_MODEL", "Samsung*"), ("ID_BUS", "ata")]);
+        assert!(!udev_filter_matches(&filter, &dev, true).unwrap());
+        assert!(udev_filter_matches(&filter, &dev, false).unwrap());
+
+        // negated patterns match properties which are not set
+        let filter = props(&[("ID_BUS", "nvme"), ("ID_FS_TYPE", "!zfs_member")]);
+        assert!(udev_filter_matches(&filter, &dev, true).unwrap());
+        let filter = props(&[("ID_BUS", "!nvme")]);
+        assert!(!udev_filter_matches(&filter, &dev, true).unwrap());
+    }
+}
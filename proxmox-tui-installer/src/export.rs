@@ -0,0 +1,339 @@
+//! Export of the options selected in the TUI as answer file for the automated installer.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::options::InstallerOptions;
+use proxmox_installer_common::{
+    options::{AdvancedBootdiskOptions, FsType},
+    setup::RuntimeInfo,
+};
+
+/// Name of the answer file, as searched for by the automated installer on a `proxmox-ais`
+/// labeled partition.
+pub const ANSWER_FILE_NAME: &str = "answer.toml";
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Answer {
+    global: Global,
+    network: Network,
+    disk_setup: DiskSetup,
+}
+
+#[derive(Serialize)]
+struct Global {
+    keyboard: String,
+    country: String,
+    fqdn: String,
+    mailto: String,
+    timezone: String,
+    root_password_hashed: String,
+}
+
+#[derive(Serialize)]
+struct Network {
+    source: &'static str,
+    cidr: String,
+    gateway: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cidr6: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gateway6: Option<String>,
+    dns: Vec<String>,
+    filter: BTreeMap<String, String>,
+}
+
+#[derive(Default, Serialize)]
+struct DiskSetup {
+    filesystem: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    disk_list: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lvm: Option<LvmOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zfs: Option<ZfsOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    btrfs: Option<BtrfsOptions>,
+}
+
+#[derive(Serialize)]
+struct LvmOptions {
+    hdsize: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    swapsize: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maxroot: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maxvz: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minfree: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct ZfsOptions {
+    raid: String,
+    ashift: usize,
+    arc_max: usize,
+    checksum: String,
+    compress: String,
+    copies: usize,
+    hdsize: f64,
+}
+
+#[derive(Serialize)]
+struct BtrfsOptions {
+    raid: String,
+    hdsize: f64,
+}
+
+/// Builds an answer file from the selected options.
+///
+/// # Arguments
+/// * `options` - The options as selected in the TUI
+/// * `runtime_info` - Runtime information, used to look up the MAC address of the management
+///   interface
+/// * `root_password_hashed` - The root password, already hashed in crypt(3) format
+/// * `disk_serials` - Maps the device paths of the selected disks to their serial, where known.
+///   Disks without a known serial are selected by their device name instead.
+pub fn answer_file(
+    options: &InstallerOptions,
+    runtime_info: &RuntimeInfo,
+    root_password_hashed: &str,
+    disk_serials: &BTreeMap<String, String>,
+) -> Result<String, String> {
+    let network = &options.network;
+    let primary = network
+        .primary()
+        .ok_or("No IP address configured for the management interface.")?;
+    let secondary = network.secondary();
+
+    let mac = match runtime_info.network.interfaces.get(&network.ifname) {
+        Some(iface) => iface.mac.replace(':', "").to_lowercase(),
+        None => {
+            return Err(format!(
+                "Unknown management interface '{}'.",
+                network.ifname
+            ))
+        }
+    };
+
+    let answer = Answer {
+        global: Global {
+            keyboard: options.timezone.kb_layout.clone(),
+            country: options.timezone.country.clone(),
+            fqdn: network.fqdn.to_string(),
+            mailto: options.password.email.clone(),
+            timezone: options.timezone.timezone.clone(),
+            root_password_hashed: root_password_hashed.to_owned(),
+        },
+        network: Network {
+            source: "from-answer",
+            cidr: primary.address.to_string(),
+            gateway: primary.gateway.to_string(),
+            cidr6: secondary.map(|ipv6| ipv6.address.to_string()),
+            gateway6: secondary.map(|ipv6| ipv6.gateway.to_string()),
+            dns: network
+                .dns_servers
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect(),
+            filter: BTreeMap::from([("ID_NET_NAME_MAC".to_owned(), format!("*{mac}"))]),
+        },
+        disk_setup: disk_setup(options, disk_serials),
+    };
+
+    toml::to_string(&answer).map_err(|err| format!("Failed to serialize answer file: {err}"))
+}
+
+fn disk_setup(options: &InstallerOptions, disk_serials: &BTreeMap<String, String>) -> DiskSetup {
+    let bootdisk = &options.bootdisk;
+    let mut setup = DiskSetup::default();
+
+    // A single disk with a known serial can be matched by a filter, multiple disks need to be
+    // listed explicitly.
+    let serials: Vec<Option<&String>> = bootdisk
+        .disks
+        .iter()
+        .map(|disk| disk_serials.get(&disk.path))
+        .collect();
+    match serials.as_slice() {
+        [Some(serial)] => {
+            setup.filter = Some(BTreeMap::from([(
+                "ID_SERIAL".to_owned(),
+                serial.to_string(),
+            )]));
+        }
+        _ => {
+            setup.disk_list = bootdisk
+                .disks
+                .iter()
+                .zip(serials)
+                .map(|(disk, serial)| match serial {
+                    Some(serial) => serial.clone(),
+                    None => disk.path.trim_start_matches("/dev/").to_owned(),
+                })
+                .collect();
+        }
+    }
+
+    setup.filesystem = match bootdisk.fstype {
+        FsType::Ext4 => "ext4",
+        FsType::Xfs => "xfs",
+        FsType::Zfs(_) => "zfs",
+        FsType::Btrfs(_) => "btrfs",
+    }
+    .to_owned();
+
+    match (&bootdisk.advanced, bootdisk.fstype) {
+        (AdvancedBootdiskOptions::Lvm(lvm), _) => {
+            setup.lvm = Some(LvmOptions {
+                hdsize: lvm.total_size,
+                swapsize: lvm.swap_size,
+                maxroot: lvm.max_root_size,
+                maxvz: lvm.max_data_size,
+                minfree: lvm.min_lvm_free,
+            });
+        }
+        (AdvancedBootdiskOptions::Zfs(zfs), FsType::Zfs(level)) => {
+            setup.zfs = Some(ZfsOptions {
+                raid: level.to_string().to_lowercase(),
+                ashift: zfs.ashift,
+                arc_max: zfs.arc_max,
+                checksum: zfs.checksum.to_string(),
+                compress: zfs.compress.to_string(),
+                copies: zfs.copies,
+                hdsize: zfs.disk_size,
+            });
+        }
+        (AdvancedBootdiskOptions::Btrfs(btrfs), FsType::Btrfs(level)) => {
+            setup.btrfs = Some(BtrfsOptions {
+                raid: level.to_string().to_lowercase(),
+                hdsize: btrfs.disk_size,
+            });
+        }
+        // the advanced options always match the filesystem type
+        _ => {}
+    }
+
+    setup
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::PasswordOptions,
+        test_utils::{dummy_disk, dummy_runtime_info},
+    };
+    use proxmox_installer_common::{
+        answer::{Answer, DiskSelection, NetworkInterface, NetworkSettings},
+        options::{
+            BootdiskOptions, NetworkAddressOptions, NetworkOptions, TimezoneOptions,
+            ZfsBootdiskOptions, ZfsChecksumOption, ZfsCompressOption, ZfsRaidLevel,
+        },
+        utils::{CidrAddress, Fqdn},
+    };
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    fn dummy_options(bootdisk: BootdiskOptions) -> InstallerOptions {
+        InstallerOptions {
+            bootdisk,
+            timezone: TimezoneOptions {
+                country: "at".to_owned(),
+                timezone: "Europe/Vienna".to_owned(),
+                kb_layout: "de-ch".to_owned(),
+            },
+            password: PasswordOptions {
+                email: "root@example.com".to_owned(),
+                root_password: "123456".to_owned(),
+            },
+            network: NetworkOptions {
+                ifname: "eno1".to_owned(),
+                fqdn: Fqdn::from("pve.example.com").unwrap(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2), 64)
+                        .unwrap(),
+                    gateway: IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+                }),
+                dns_servers: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))],
+            },
+            autoreboot: true,
+        }
+    }
+
+    #[test]
+    fn export_single_disk_by_serial() {
+        let disk = dummy_disk("0", "sda");
+        let runtime_info = dummy_runtime_info(vec![disk.clone()]);
+        let options = dummy_options(BootdiskOptions::defaults_from(&disk));
+        let serials = BTreeMap::from([("/dev/sda".to_owned(), "S2HRNX0J403335".to_owned())]);
+
+        let contents = answer_file(&options, &runtime_info, "$6$salt$hash", &serials).unwrap();
+        let answer: Answer = toml::from_str(&contents).unwrap();
+
+        assert_eq!(answer.global.fqdn.to_string(), "pve.example.com");
+        assert_eq!(answer.global.root_password_hashed.unwrap(), "$6$salt$hash");
+        assert!(answer.global.root_password.is_none());
+        assert_eq!(answer.disks.fs_type, FsType::Ext4);
+        match answer.disks.disk_selection {
+            DiskSelection::Filter(filter) => {
+                assert_eq!(filter.udev["ID_SERIAL"], "S2HRNX0J403335");
+            }
+            DiskSelection::Selection(_) => panic!("expected disk filter"),
+        }
+
+        match answer.network.network_settings {
+            NetworkSettings::Manual(network) => {
+                assert_eq!(network.ipv4, options.network.ipv4);
+                assert_eq!(network.ipv6, options.network.ipv6);
+                assert_eq!(network.dns, options.network.dns_servers);
+                match network.interface {
+                    NetworkInterface::Filter(filter) => {
+                        assert_eq!(filter["ID_NET_NAME_MAC"], "*a0369f0ab382");
+                    }
+                    NetworkInterface::Bond(_) => panic!("expected interface filter"),
+                }
+            }
+            NetworkSettings::FromDhcp => panic!("expected manual network configuration"),
+        }
+    }
+
+    #[test]
+    fn export_zfs_disk_list() {
+        let disks = vec![dummy_disk("0", "sda"), dummy_disk("1", "sdb")];
+        let runtime_info = dummy_runtime_info(disks.clone());
+        let zfs = ZfsBootdiskOptions {
+            ashift: 13,
+            compress: ZfsCompressOption::Lz4,
+            checksum: ZfsChecksumOption::Sha256,
+            copies: 2,
+            arc_max: 1024,
+            disk_size: 80.,
+            selected_disks: vec![0, 1],
+        };
+        let options = dummy_options(BootdiskOptions {
+            disks,
+            fstype: FsType::Zfs(ZfsRaidLevel::RaidZ),
+            advanced: AdvancedBootdiskOptions::Zfs(zfs),
+        });
+        let serials = BTreeMap::from([("/dev/sdb".to_owned(), "S2HRNX0J403335".to_owned())]);
+
+        let contents = answer_file(&options, &runtime_info, "$6$salt$hash", &serials).unwrap();
+        let answer: Answer = toml::from_str(&contents).unwrap();
+
+        assert_eq!(answer.disks.fs_type, FsType::Zfs(ZfsRaidLevel::RaidZ));
+        match answer.disks.disk_selection {
+            DiskSelection::Selection(list) => assert_eq!(list, ["sda", "S2HRNX0J403335"]),
+            DiskSelection::Filter(_) => panic!("expected disk list"),
+        }
+        assert!(contents.contains("compress = \"lz4\""));
+        assert!(contents.contains("checksum = \"sha256\""));
+    }
+}
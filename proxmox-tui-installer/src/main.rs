@@ -15,11 +15,17 @@ use cursive::{
 
 use regex::Regex;
 
+use proxmox_installer_common::disk_checks::get_existing_disk_data;
+
+mod export;
+
 mod options;
 use options::{InstallerOptions, PasswordOptions};
 
+mod preseed;
+
 use proxmox_installer_common::{
-    options::{BootdiskOptions, NetworkOptions, TimezoneOptions},
+    options::{BootdiskOptions, NetworkAddressOptions, NetworkOptions, TimezoneOptions},
     setup::{installer_setup, LocaleInfo, ProxmoxProduct, RuntimeInfo, SetupInfo},
     utils::Fqdn,
 };
@@ -28,6 +34,9 @@ mod setup;
 
 mod system;
 
+#[cfg(test)]
+mod test_utils;
+
 mod views;
 use views::{
     BootdiskOptionsView, CidrAddressEditView, FormView, InstallProgressView, TableView,
@@ -165,14 +174,23 @@ fn main() {
     siv.clear_global_callbacks(Event::CtrlChar('c'));
     siv.set_on_pre_event(Event::CtrlChar('c'), trigger_abort_install_dialog);
 
+    let mut options = InstallerOptions {
+        bootdisk: BootdiskOptions::defaults_from(&runtime_info.disks[0]),
+        timezone: TimezoneOptions::defaults_from(&runtime_info, &locales),
+        password: Default::default(),
+        network: NetworkOptions::defaults_from(&setup_info, &runtime_info.network),
+        autoreboot: true,
+    };
+    let preseed_message = preseed_options(
+        &mut options,
+        &setup_info,
+        &runtime_info,
+        &locales,
+        in_test_mode,
+    );
+
     siv.set_user_data(InstallerState {
-        options: InstallerOptions {
-            bootdisk: BootdiskOptions::defaults_from(&runtime_info.disks[0]),
-            timezone: TimezoneOptions::defaults_from(&runtime_info, &locales),
-            password: Default::default(),
-            network: NetworkOptions::defaults_from(&setup_info, &runtime_info.network),
-            autoreboot: true,
-        },
+        options,
         setup_info,
         runtime_info,
         locales,
@@ -181,9 +199,50 @@ fn main() {
     });
 
     switch_to_next_screen(&mut siv, InstallerStep::Licence, &license_dialog);
+    if let Some(message) = preseed_message {
+        siv.add_layer(Dialog::info(message).title("Answer file"));
+    }
     siv.run();
 }
 
+/// Pre-fills the options from an answer file, if one is found.
+///
+/// Returns a message for the user about the answer file used and any values which could not be
+/// applied.
+fn preseed_options(
+    options: &mut InstallerOptions,
+    setup_info: &SetupInfo,
+    runtime_info: &RuntimeInfo,
+    locales: &LocaleInfo,
+    in_test_mode: bool,
+) -> Option<String> {
+    let (source, answer) = match preseed::load_answer(setup_info, in_test_mode) {
+        Ok(Some(found)) => found,
+        Ok(None) => return None,
+        Err(err) => return Some(err),
+    };
+
+    let udev_info = system::read_udev_info(in_test_mode).ok();
+    let ctx = preseed::PreseedContext {
+        setup_info,
+        runtime_info,
+        locales,
+        udev_info: udev_info.as_ref(),
+    };
+
+    let mut message =
+        format!("The installer options were pre-filled from the answer file on {source}.");
+    let warnings = preseed::apply(&answer, options, &ctx);
+    if !warnings.is_empty() {
+        message.push_str("\n\nThe following values could not be used:\n");
+        for warning in warnings {
+            message.push_str(&format!("\n- {warning}"));
+        }
+    }
+
+    Some(message)
+}
+
 /// Anything that can be done late in the setup and will not result in fatal errors.
 fn installer_setup_late(siv: &mut Cursive) {
     let state = siv.user_data::<InstallerState>().cloned().unwrap();
@@ -423,8 +482,14 @@ fn password_dialog(siv: &mut Cursive) -> InstallerView {
     let options = &state.options.password;
 
     let inner = FormView::new()
-        .child("Root password", EditView::new().secret())
-        .child("Confirm root password", EditView::new().secret())
+        .child(
+            "Root password",
+            EditView::new().secret().content(&options.root_password),
+        )
+        .child(
+            "Confirm root password",
+            EditView::new().secret().content(&options.root_password),
+        )
         .child(
             "Administrator email",
             EditView::new().content(&options.email),
@@ -508,17 +573,26 @@ fn network_dialog(siv: &mut Cursive) -> InstallerView {
             "Hostname (FQDN)",
             EditView::new().content(options.fqdn.to_string()),
         )
+        .child("IPv4 address (CIDR)", cidr_edit_view(options.ipv4.as_ref()))
         .child(
-            "IP address (CIDR)",
-            CidrAddressEditView::new().content(options.address.clone()),
+            "IPv4 gateway address",
+            EditView::new().content(gateway_content(options.ipv4.as_ref())),
         )
+        .child("IPv6 address (CIDR)", cidr_edit_view(options.ipv6.as_ref()))
         .child(
-            "Gateway address",
-            EditView::new().content(options.gateway.to_string()),
+            "IPv6 gateway address",
+            EditView::new().content(gateway_content(options.ipv6.as_ref())),
         )
         .child(
-            "DNS server address",
-            EditView::new().content(options.dns_server.to_string()),
+            "DNS server addresses",
+            EditView::new().content(
+                options
+                    .dns_servers
+                    .iter()
+                    .map(|dns| dns.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
         )
         .with_name("network-options");
 
@@ -537,35 +611,38 @@ fn network_dialog(siv: &mut Cursive) -> InstallerView {
                     .parse::<Fqdn>()
                     .map_err(|err| format!("hostname does not look valid:\n\n{err}"))?;
 
-                let address = view
-                    .get_value::<CidrAddressEditView, _>(2)
-                    .ok_or("failed to retrieve host address")?;
-
-                let gateway = view
-                    .get_value::<EditView, _>(3)
-                    .ok_or("failed to retrieve gateway address")?
-                    .parse::<IpAddr>()
-                    .map_err(|err| err.to_string())?;
-
-                let dns_server = view
-                    .get_value::<EditView, _>(4)
-                    .ok_or("failed to retrieve DNS server address")?
-                    .parse::<IpAddr>()
-                    .map_err(|err| err.to_string())?;
-
-                if address.addr().is_ipv4() != gateway.is_ipv4() {
-                    Err("host and gateway IP address version must not differ".to_owned())
-                } else if address.addr().is_ipv4() != dns_server.is_ipv4() {
-                    Err("host and DNS IP address version must not differ".to_owned())
+                let ipv4 = get_network_address_options(view, 2, "IPv4")?;
+                let ipv6 = get_network_address_options(view, 4, "IPv6")?;
+
+                let dns_servers = view
+                    .get_value::<EditView, _>(6)
+                    .ok_or("failed to retrieve DNS server addresses")?
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse::<IpAddr>().map_err(|err| format!("{s}: {err}")))
+                    .collect::<Result<Vec<IpAddr>, String>>()?;
+
+                if ipv4.is_none() && ipv6.is_none() {
+                    Err("either an IPv4 or an IPv6 address must be set".to_owned())
+                } else if matches!(&ipv4, Some(ipv4)
+                    if !ipv4.address.addr().is_ipv4() || !ipv4.gateway.is_ipv4())
+                {
+                    Err("IPv4 host and gateway address must both be IPv4 addresses".to_owned())
+                } else if matches!(&ipv6, Some(ipv6)
+                    if !ipv6.address.addr().is_ipv6() || !ipv6.gateway.is_ipv6())
+                {
+                    Err("IPv6 host and gateway address must both be IPv6 addresses".to_owned())
+                } else if dns_servers.is_empty() {
+                    Err("at least one DNS server address must be set".to_owned())
                 } else if fqdn.to_string().ends_with(".invalid") {
                     Err("hostname does not look valid".to_owned())
                 } else {
                     Ok(NetworkOptions {
                         ifname,
                         fqdn,
-                        address,
-                        gateway,
-                        dns_server,
+                        ipv4,
+                        ipv6,
+                        dns_servers,
                     })
                 }
             });
@@ -586,6 +663,54 @@ fn network_dialog(siv: &mut Cursive) -> InstallerView {
     )
 }
 
+fn cidr_edit_view(options: Option<&NetworkAddressOptions>) -> CidrAddressEditView {
+    match options {
+        Some(options) => CidrAddressEditView::new().content(options.address.clone()),
+        None => CidrAddressEditView::new(),
+    }
+}
+
+fn gateway_content(options: Option<&NetworkAddressOptions>) -> String {
+    options
+        .map(|options| options.gateway.to_string())
+        .unwrap_or_default()
+}
+
+/// Retrieves the optional address configuration of one address family from the network dialog,
+/// with the address at `index` and the gateway directly after it. Both must either be set or be
+/// left empty.
+fn get_network_address_options(
+    view: &FormView,
+    index: usize,
+    family: &str,
+) -> Result<Option<NetworkAddressOptions>, String> {
+    let address_empty = view
+        .get_child::<CidrAddressEditView>(index)
+        .ok_or_else(|| format!("failed to retrieve host {family} address"))?
+        .is_empty();
+
+    let gateway = view
+        .get_value::<EditView, _>(index + 1)
+        .ok_or_else(|| format!("failed to retrieve {family} gateway address"))?;
+    let gateway = gateway.trim();
+
+    match (address_empty, gateway.is_empty()) {
+        (true, true) => Ok(None),
+        (true, false) => Err(format!("{family} gateway set without host address")),
+        (false, true) => Err(format!("{family} host address set without gateway")),
+        (false, false) => {
+            let address = view
+                .get_value::<CidrAddressEditView, _>(index)
+                .ok_or_else(|| format!("host {family} address does not look valid"))?;
+            let gateway = gateway
+                .parse::<IpAddr>()
+                .map_err(|err| format!("{family} gateway: {err}"))?;
+
+            Ok(Some(NetworkAddressOptions { address, gateway }))
+        }
+    }
+}
+
 pub struct SummaryOption {
     name: &'static str,
     value: String,
@@ -616,14 +741,14 @@ fn summary_dialog(siv: &mut Cursive) -> InstallerView {
 
     let mut bbar = LinearLayout::horizontal()
         .child(abort_install_button())
+        .child(DummyView)
+        .child(Button::new("Export answer file", export_answer_dialog))
         .child(DummyView.full_width())
         .child(Button::new("Previous", switch_to_prev_screen))
         .child(DummyView)
-        .child(Button::new("Install", |siv| {
-            switch_to_next_screen(siv, InstallerStep::Install, &install_progress_dialog);
-        }));
+        .child(Button::new("Install", confirm_overwrite_dialog));
 
-    let _ = bbar.set_focus_index(2); // ignore errors
+    let _ = bbar.set_focus_index(4); // ignore errors
 
     let autoreboot_checkbox =
         Checkbox::new()
@@ -663,6 +788,123 @@ fn summary_dialog(siv: &mut Cursive) -> InstallerView {
     InstallerView::with_raw(state, inner)
 }
 
+/// Warns about existing data on the selected disks, if any, before starting the installation.
+fn confirm_overwrite_dialog(siv: &mut Cursive) {
+    let state = siv.user_data::<InstallerState>().unwrap();
+
+    // without udev information, there is nothing to warn about
+    let existing_data = match system::read_udev_info(state.in_test_mode) {
+        Ok(udev_info) => state
+            .options
+            .bootdisk
+            .disks
+            .iter()
+            .filter_map(|disk| {
+                let data = get_existing_disk_data(udev_info.disks.get(&disk.index));
+                (!data.is_empty()).then(|| format!("{}: {}", disk.path, data.join(", ")))
+            })
+            .collect::<Vec<String>>(),
+        Err(_) => vec![],
+    };
+
+    let start_install = |siv: &mut Cursive| {
+        switch_to_next_screen(siv, InstallerStep::Install, &install_progress_dialog);
+    };
+
+    if existing_data.is_empty() {
+        start_install(siv);
+        return;
+    }
+
+    prompt_dialog(
+        siv,
+        "Overwrite existing data?",
+        &format!(
+            "The following disks already hold data, which will be irrevocably destroyed by the \
+             installation:\n\n{}\n\nContinue with the installation?",
+            existing_data.join("\n")
+        ),
+        "Install",
+        Box::new(start_install),
+        "Cancel",
+        Box::new(|_| {}),
+    );
+}
+
+fn export_answer_dialog(siv: &mut Cursive) {
+    let state = siv.user_data::<InstallerState>().cloned().unwrap();
+
+    let answer = system::hash_password(&state.options.password.root_password).and_then(|hash| {
+        let disk_serials = state
+            .options
+            .bootdisk
+            .disks
+            .iter()
+            .filter_map(|disk| Some((disk.path.clone(), system::disk_serial(&disk.path)?)))
+            .collect();
+
+        export::answer_file(&state.options, &state.runtime_info, &hash, &disk_serials)
+    });
+
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(err) => {
+            siv.add_layer(Dialog::info(format!("Failed to export answer file: {err}")));
+            return;
+        }
+    };
+
+    let target = FormView::new()
+        .child(
+            "Target device",
+            EditView::new().content("/dev/disk/by-label/PROXMOX-AIS"),
+        )
+        .with_name("export-target");
+
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "The answer file can be used to install further nodes with the same \
+                     settings using the automated installer. The disks and the management \
+                     interface are matched by their serial and MAC address, adapt them as needed.",
+                ))
+                .child(DummyView)
+                .child(Panel::new(ScrollView::new(TextView::new(answer.clone()))).max_height(15))
+                .child(DummyView)
+                .child(target),
+        )
+        .title("Export answer file")
+        .button("Cancel", |siv| {
+            siv.pop_layer();
+        })
+        .button("Save", move |siv| {
+            // in test mode, just write it to the current working directory
+            let result = if state.in_test_mode {
+                std::fs::write(export::ANSWER_FILE_NAME, &answer).map_err(|err| err.to_string())
+            } else {
+                let device = siv
+                    .call_on_name("export-target", |view: &mut FormView| {
+                        view.get_value::<EditView, _>(0)
+                    })
+                    .flatten()
+                    .unwrap_or_default();
+
+                system::write_file_to_device(&device, export::ANSWER_FILE_NAME, &answer)
+            };
+
+            siv.pop_layer();
+            match result {
+                Ok(()) => siv.add_layer(Dialog::info("Answer file exported successfully.")),
+                Err(err) => {
+                    siv.add_layer(Dialog::info(format!("Failed to export answer file: {err}")))
+                }
+            }
+        })
+        .max_width(80),
+    );
+}
+
 fn install_progress_dialog(siv: &mut Cursive) -> InstallerView {
     let state = siv.user_data::<InstallerState>().cloned().unwrap();
     InstallerView::with_raw(&state, InstallProgressView::new(siv))
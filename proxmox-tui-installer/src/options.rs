@@ -56,7 +56,7 @@ impl InstallerOptions {
             .map(|l| &l.name)
             .unwrap_or(&self.timezone.kb_layout);
 
-        vec![
+        let mut summary = vec![
             SummaryOption::new("Bootdisk filesystem", self.bootdisk.fstype.to_string()),
             SummaryOption::new(
                 "Bootdisk(s)",
@@ -72,10 +72,34 @@ impl InstallerOptions {
             SummaryOption::new("Administrator email", &self.password.email),
             SummaryOption::new("Management interface", &self.network.ifname),
             SummaryOption::new("Hostname", self.network.fqdn.to_string()),
-            SummaryOption::new("Host IP (CIDR)", self.network.address.to_string()),
-            SummaryOption::new("Gateway", self.network.gateway.to_string()),
-            SummaryOption::new("DNS", self.network.dns_server.to_string()),
-        ]
+        ];
+
+        if let Some(ipv4) = &self.network.ipv4 {
+            summary.push(SummaryOption::new(
+                "Host IPv4 (CIDR)",
+                ipv4.address.to_string(),
+            ));
+            summary.push(SummaryOption::new("IPv4 gateway", ipv4.gateway.to_string()));
+        }
+        if let Some(ipv6) = &self.network.ipv6 {
+            summary.push(SummaryOption::new(
+                "Host IPv6 (CIDR)",
+                ipv6.address.to_string(),
+            ));
+            summary.push(SummaryOption::new("IPv6 gateway", ipv6.gateway.to_string()));
+        }
+
+        summary.push(SummaryOption::new(
+            "DNS",
+            self.network
+                .dns_servers
+                .iter()
+                .map(|dns| dns.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+        ));
+
+        summary
     }
 }
 
@@ -83,13 +107,14 @@ impl InstallerOptions {
 mod tests {
     use super::*;
     use proxmox_installer_common::{
+        options::NetworkAddressOptions,
         setup::{
             Dns, Gateway, Interface, InterfaceState, IsoInfo, IsoLocations, NetworkInfo,
             ProductConfig, ProxmoxProduct, Routes, SetupInfo,
         },
         utils::{CidrAddress, Fqdn},
     };
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use std::{collections::BTreeMap, path::PathBuf};
 
     fn dummy_setup_info() -> SetupInfo {
@@ -98,6 +123,7 @@ mod tests {
                 fullname: "Proxmox VE".to_owned(),
                 product: ProxmoxProduct::PVE,
                 enable_btrfs: true,
+                bridged_network: true,
             },
             iso_info: IsoInfo {
                 release: String::new(),
@@ -148,9 +174,12 @@ mod tests {
             NetworkOptions {
                 ifname: "eth0".to_owned(),
                 fqdn: Fqdn::from("foo.bar.com").unwrap(),
-                address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
-                gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
-                dns_server: Ipv4Addr::UNSPECIFIED.into(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: None,
+                dns_servers: vec![],
             }
         );
 
@@ -160,9 +189,12 @@ mod tests {
             NetworkOptions {
                 ifname: "eth0".to_owned(),
                 fqdn: Fqdn::from("pve.bar.com").unwrap(),
-                address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
-                gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
-                dns_server: Ipv4Addr::UNSPECIFIED.into(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: None,
+                dns_servers: vec![],
             }
         );
 
@@ -172,9 +204,12 @@ mod tests {
             NetworkOptions {
                 ifname: "eth0".to_owned(),
                 fqdn: Fqdn::from("pve.example.invalid").unwrap(),
-                address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
-                gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
-                dns_server: Ipv4Addr::UNSPECIFIED.into(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: None,
+                dns_servers: vec![],
             }
         );
 
@@ -184,10 +219,52 @@ mod tests {
             NetworkOptions {
                 ifname: "eth0".to_owned(),
                 fqdn: Fqdn::from("foo.example.invalid").unwrap(),
-                address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
-                gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
-                dns_server: Ipv4Addr::UNSPECIFIED.into(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: None,
+                dns_servers: vec![],
             }
         );
+
+        info.interfaces.get_mut("eth0").unwrap().addresses = Some(vec![
+            CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+            CidrAddress::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2), 64).unwrap(),
+        ]);
+        info.routes.as_mut().unwrap().gateway6 = Some(Gateway {
+            dev: "eth0".to_owned(),
+            gateway: IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        });
+        info.dns.dns = vec![
+            IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        ];
+        assert_eq!(
+            NetworkOptions::defaults_from(&setup, &info),
+            NetworkOptions {
+                ifname: "eth0".to_owned(),
+                fqdn: Fqdn::from("foo.example.invalid").unwrap(),
+                ipv4: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv4Addr::new(192, 168, 0, 2), 24).unwrap(),
+                    gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                }),
+                ipv6: Some(NetworkAddressOptions {
+                    address: CidrAddress::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2), 64)
+                        .unwrap(),
+                    gateway: IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                }),
+                dns_servers: vec![
+                    IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+                    IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                ],
+            }
+        );
+
+        // DHCP without a lease, the interface is still used as default
+        info.interfaces.get_mut("eth0").unwrap().addresses = None;
+        let options = NetworkOptions::defaults_from(&setup, &info);
+        assert_eq!(options.ifname, "eth0");
+        assert!(options.ipv4.is_none() && options.ipv6.is_none());
     }
 }
@@ -0,0 +1,620 @@
+//! Pre-seeding of the installer options from a (partial) answer file.
+//!
+//! The answer file uses the same format as for the automated installer, but all sections and the
+//! fields of the `[global]` section are optional. Every field that is set is used as the initial
+//! value for the respective option, which the user still has to confirm on each screen.
+
+use std::{fs, net::IpAddr, path::Path, process::Command};
+
+use crate::options::InstallerOptions;
+use proxmox_installer_common::{
+    answer::{
+        DiskFilter, DiskSetup, DiskTake, Filesystem, FilterMatch, NetworkConfigMode,
+        NetworkInAnswer, PartialAnswer, PartialGlobal,
+    },
+    http,
+    options::{
+        AdvancedBootdiskOptions, BtrfsBootdiskOptions, BtrfsRaidLevel, Disk, FsType,
+        LvmBootdiskOptions, NetworkAddressOptions, ZfsBootdiskOptions, ZfsRaidLevel,
+    },
+    setup::{LocaleInfo, RuntimeInfo, SetupInfo},
+    udevinfo::{get_disks_from_list, get_matched_disks, get_single_udev_index, UdevInfo},
+    utils::CidrAddress,
+};
+
+const ANSWER_FILE: &str = "answer.toml";
+const ANSWER_MP: &str = "/mnt/answer";
+// FAT can only handle 11 characters, so shorten Automated Installer Source to AIS
+const PARTLABELS: &[&str] = &["proxmox-ais", "PROXMOX-AIS"];
+const DISK_BY_LABEL_PATH: &str = "/dev/disk/by-label";
+/// Kernel command line parameters to fetch the answer file from, e.g.
+/// `proxmox-answer-url=https://example.com/answer.toml`
+const CMDLINE_URL_PARAM: &str = "proxmox-answer-url=";
+const CMDLINE_FINGERPRINT_PARAM: &str = "proxmox-answer-cert-fingerprint=";
+
+/// Information about the system, needed to resolve the values of the answer file.
+pub struct PreseedContext<'a> {
+    pub setup_info: &'a SetupInfo,
+    pub runtime_info: &'a RuntimeInfo,
+    pub locales: &'a LocaleInfo,
+    /// Only needed for filters, so it is optional
+    pub udev_info: Option<&'a UdevInfo>,
+}
+
+/// Looks for an answer file, in order, on the ISO, on a partition labeled `proxmox-ais` and at
+/// the URL given on the kernel command line.
+///
+/// Returns where the answer file was found, together with its parsed contents.
+pub fn load_answer(
+    setup_info: &SetupInfo,
+    in_test_mode: bool,
+) -> Result<Option<(String, PartialAnswer)>, String> {
+    let (source, contents) = match find_answer(setup_info, in_test_mode)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let answer = toml::from_str(&contents)
+        .map_err(|err| format!("Failed to parse answer file from {source}: {err}"))?;
+
+    Ok(Some((source, answer)))
+}
+
+fn find_answer(
+    setup_info: &SetupInfo,
+    in_test_mode: bool,
+) -> Result<Option<(String, String)>, String> {
+    let iso_path = setup_info.locations.iso.join(ANSWER_FILE);
+    if iso_path.exists() {
+        let contents = fs::read_to_string(&iso_path)
+            .map_err(|err| format!("Failed to read {}: {err}", iso_path.display()))?;
+        return Ok(Some(("the ISO".to_owned(), contents)));
+    }
+
+    // both need the actual hardware, so do not try them in test mode
+    if in_test_mode {
+        return Ok(None);
+    }
+
+    if let Some(contents) = read_from_partition()? {
+        return Ok(Some(("the 'proxmox-ais' partition".to_owned(), contents)));
+    }
+
+    let cmdline = fs::read_to_string("/proc/cmdline").unwrap_or_default();
+    let param = |prefix: &str| {
+        cmdline
+            .split_whitespace()
+            .find_map(|param| param.strip_prefix(prefix))
+    };
+    if let Some(url) = param(CMDLINE_URL_PARAM) {
+        let contents = http::get(url, param(CMDLINE_FINGERPRINT_PARAM))
+            .map_err(|err| format!("Failed to fetch answer file from {url}: {err}"))?;
+        return Ok(Some((url.to_owned(), contents)));
+    }
+
+    Ok(None)
+}
+
+fn read_from_partition() -> Result<Option<String>, String> {
+    let device = match PARTLABELS
+        .iter()
+        .map(|label| Path::new(DISK_BY_LABEL_PATH).join(label))
+        .find(|path| path.exists())
+    {
+        Some(device) => device,
+        None => return Ok(None),
+    };
+
+    fs::create_dir_all(ANSWER_MP).map_err(|err| err.to_string())?;
+    let output = Command::new("mount")
+        .args(["-o", "ro"])
+        .arg(&device)
+        .arg(ANSWER_MP)
+        .output()
+        .map_err(|err| format!("Failed to mount {}: {err}", device.display()))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to mount {}: {}",
+            device.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let result = match fs::read_to_string(Path::new(ANSWER_MP).join(ANSWER_FILE)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("Failed to read answer file from partition: {err}")),
+    };
+
+    let _ = Command::new("umount").arg(ANSWER_MP).output();
+
+    result
+}
+
+/// Overrides the options with all values set in the answer file.
+///
+/// Returns a list of problems for values which could not be used, the respective options are left
+/// untouched in that case.
+pub fn apply(
+    answer: &PartialAnswer,
+    options: &mut InstallerOptions,
+    ctx: &PreseedContext,
+) -> Vec<String> {
+    let mut warnings = vec![];
+
+    apply_global(&answer.global, options, ctx.locales, &mut warnings);
+    apply_network(&answer.network, options, ctx, &mut warnings);
+    if let Some(disk_setup) = &answer.disks {
+        if let Err(err) = apply_disk_setup(disk_setup, options, ctx) {
+            warnings.push(format!("Ignoring disk setup: {err}"));
+        }
+    }
+
+    warnings
+}
+
+fn apply_global(
+    global: &PartialGlobal,
+    options: &mut InstallerOptions,
+    locales: &LocaleInfo,
+    warnings: &mut Vec<String>,
+) {
+    let timezone = &mut options.timezone;
+
+    if let Some(country) = &global.country {
+        match locales.countries.get(country) {
+            Some(info) => {
+                // reset the timezone and keyboard layout to the defaults of the country, like the
+                // timezone screen does when selecting it
+                timezone.country.clone_from(country);
+                if let Some(zone) = locales.cczones.get(country).and_then(|z| z.first()) {
+                    timezone.timezone.clone_from(zone);
+                }
+                if !info.kmap.is_empty() {
+                    timezone.kb_layout.clone_from(&info.kmap);
+                }
+            }
+            None => warnings.push(format!("Unknown country '{country}'.")),
+        }
+    }
+
+    if let Some(zone) = &global.timezone {
+        if zone == "UTC" || locales.cczones.values().any(|zones| zones.contains(zone)) {
+            timezone.timezone.clone_from(zone);
+        } else {
+            warnings.push(format!("Unknown timezone '{zone}'."));
+        }
+    }
+
+    if let Some(keyboard) = &global.keyboard {
+        let keyboard = keyboard.to_string();
+        if locales.kmap.contains_key(&keyboard) {
+            timezone.kb_layout = keyboard;
+        } else {
+            warnings.push(format!("Unknown keyboard layout '{keyboard}'."));
+        }
+    }
+
+    if let Some(mailto) = &global.mailto {
+        options.password.email.clone_from(mailto);
+    }
+
+    if let Some(password) = &global.root_password {
+        options.password.root_password.clone_from(password);
+    } else if global.root_password_hashed.is_some() {
+        warnings.push(
+            "A hashed root password cannot be used for interactive installations.".to_owned(),
+        );
+    }
+
+    if let Some(fqdn) = &global.fqdn {
+        options.network.fqdn = fqdn.clone();
+    }
+}
+
+fn apply_network(
+    network: &NetworkInAnswer,
+    options: &mut InstallerOptions,
+    ctx: &PreseedContext,
+    warnings: &mut Vec<String>,
+) {
+    if network.source != NetworkConfigMode::FromAnswer {
+        return;
+    }
+
+    if network.interface.is_some()
+        || network.vlan.is_some()
+        || !network.bond.is_empty()
+        || !network.bridge_ports.is_empty()
+        || !network.vlan_interface.is_empty()
+    {
+        warnings.push(
+            "Bonds and VLANs are not supported for interactive installations, ignoring them."
+                .to_owned(),
+        );
+    }
+
+    if let Some(filter) = &network.filter {
+        let ifname = match ctx.udev_info {
+            Some(udev_info) => {
+                get_single_udev_index(filter, &udev_info.nics).map_err(|err| err.to_string())
+            }
+            None => Err("no udev information available".to_owned()),
+        };
+        match ifname {
+            Ok(ifname) => options.network.ifname = ifname,
+            Err(err) => warnings.push(format!("Ignoring network interface filter: {err}")),
+        }
+    }
+
+    let address = |cidr: &Option<CidrAddress>, gateway: &Option<IpAddr>| match (cidr, gateway) {
+        (Some(address), Some(gateway)) => Some(NetworkAddressOptions {
+            address: address.clone(),
+            gateway: *gateway,
+        }),
+        _ => None,
+    };
+
+    if let Some(primary) = address(&network.cidr, &network.gateway) {
+        let secondary = address(&network.cidr6, &network.gateway6);
+        let network = &mut options.network;
+
+        if primary.address.addr().is_ipv4() != primary.gateway.is_ipv4() {
+            warnings.push("Fields 'cidr' and 'gateway' must be of the same family.".to_owned());
+        } else if primary.address.addr().is_ipv4() {
+            network.ipv4 = Some(primary);
+            network.ipv6 = secondary;
+        } else {
+            network.ipv4 = None;
+            network.ipv6 = Some(primary);
+        }
+    }
+
+    if let Some(dns) = &network.dns {
+        let dns: Vec<IpAddr> = dns.clone().into();
+        if !dns.is_empty() {
+            options.network.dns_servers = dns;
+        }
+    }
+}
+
+fn apply_disk_setup(
+    setup: &DiskSetup,
+    options: &mut InstallerOptions,
+    ctx: &PreseedContext,
+) -> Result<(), String> {
+    let runtime_info = ctx.runtime_info;
+    let bootdisk = &mut options.bootdisk;
+
+    let disks = selected_disks(setup, ctx)?;
+
+    let fstype = match &setup.filesystem {
+        Filesystem::Ext4 => FsType::Ext4,
+        Filesystem::Xfs => FsType::Xfs,
+        Filesystem::Zfs => FsType::Zfs(
+            setup
+                .zfs
+                .and_then(|zfs| zfs.raid)
+                .unwrap_or(ZfsRaidLevel::Raid0),
+        ),
+        Filesystem::Btrfs => {
+            if !ctx.setup_info.config.enable_btrfs {
+                return Err("Btrfs is not supported by this product".to_owned());
+            }
+            FsType::Btrfs(
+                setup
+                    .btrfs
+                    .and_then(|btrfs| btrfs.raid)
+                    .unwrap_or(BtrfsRaidLevel::Raid0),
+            )
+        }
+    };
+
+    // Maps the selected disks to the selection slots of the RAID setup, with all remaining slots
+    // set to "do not use".
+    let selection = |disks: &Option<Vec<Disk>>, default: Vec<usize>| match disks {
+        Some(disks) => {
+            let mut selection: Vec<usize> = disks
+                .iter()
+                .filter_map(|disk| runtime_info.disks.iter().position(|d| d == disk))
+                .collect();
+            selection.resize(runtime_info.disks.len(), runtime_info.disks.len());
+            selection
+        }
+        None => default,
+    };
+
+    let advanced = match fstype {
+        FsType::Ext4 | FsType::Xfs => {
+            let disk = match &disks {
+                Some(disks) if disks.len() > 1 => {
+                    return Err(format!("{fstype} can only be installed on a single disk"))
+                }
+                Some(disks) => disks[0].clone(),
+                None => bootdisk.disks[0].clone(),
+            };
+
+            let mut lvm = LvmBootdiskOptions::defaults_from(&disk);
+            if let Some(opts) = setup.lvm {
+                lvm.total_size = opts.hdsize.unwrap_or(lvm.total_size);
+                lvm.swap_size = opts.swapsize.or(lvm.swap_size);
+                lvm.max_root_size = opts.maxroot.or(lvm.max_root_size);
+                lvm.max_data_size = opts.maxvz.or(lvm.max_data_size);
+                lvm.min_lvm_free = opts.minfree.or(lvm.min_lvm_free);
+            }
+
+            bootdisk.disks = vec![disk];
+            AdvancedBootdiskOptions::Lvm(lvm)
+        }
+        FsType::Zfs(_) => {
+            let mut zfs = ZfsBootdiskOptions::defaults_from(runtime_info, &ctx.setup_info.config);
+            zfs.selected_disks = selection(&disks, zfs.selected_disks);
+            if let Some(opts) = setup.zfs {
+                zfs.ashift = opts.ashift.unwrap_or(zfs.ashift);
+                zfs.arc_max = opts.arc_max.unwrap_or(zfs.arc_max);
+                zfs.checksum = opts.checksum.unwrap_or(zfs.checksum);
+                zfs.compress = opts.compress.unwrap_or(zfs.compress);
+                zfs.copies = opts.copies.unwrap_or(zfs.copies);
+                zfs.disk_size = opts.hdsize.unwrap_or(zfs.disk_size);
+            }
+
+            bootdisk.disks = disks.unwrap_or_else(|| runtime_info.disks.clone());
+            AdvancedBootdiskOptions::Zfs(zfs)
+        }
+        FsType::Btrfs(_) => {
+            let mut btrfs = BtrfsBootdiskOptions::defaults_from(&runtime_info.disks);
+            btrfs.selected_disks = selection(&disks, btrfs.selected_disks);
+            if let Some(opts) = setup.btrfs {
+                btrfs.disk_size = opts.hdsize.unwrap_or(btrfs.disk_size);
+            }
+
+            bootdisk.disks = disks.unwrap_or_else(|| runtime_info.disks.clone());
+            AdvancedBootdiskOptions::Btrfs(btrfs)
+        }
+    };
+
+    bootdisk.fstype = fstype;
+    bootdisk.advanced = advanced;
+
+    Ok(())
+}
+
+/// Resolves the disk list or filter of the answer file, if any is set.
+fn selected_disks(setup: &DiskSetup, ctx: &PreseedContext) -> Result<Option<Vec<Disk>>, String> {
+    let has_filter =
+        setup.filter.is_some() || setup.filter_size.is_some() || setup.filter_block_size.is_some();
+
+    if !setup.disk_list.is_empty() && has_filter {
+        return Err("cannot use both, 'disk_list' and 'filter'".to_owned());
+    }
+    if setup.disk_list.is_empty() && !has_filter {
+        return Ok(None);
+    }
+
+    let udev_info = ctx
+        .udev_info
+        .ok_or("no udev information available to select the disks")?;
+
+    let disks = if !setup.disk_list.is_empty() {
+        get_disks_from_list(&setup.disk_list, udev_info, ctx.runtime_info)
+            .map_err(|err| err.to_string())?
+    } else {
+        let take = match (setup.take_smallest, setup.take_largest) {
+            (Some(_), Some(_)) => {
+                return Err("cannot use both, 'take_smallest' and 'take_largest'".to_owned())
+            }
+            (Some(count), None) => Some(DiskTake::Smallest(count)),
+            (None, Some(count)) => Some(DiskTake::Largest(count)),
+            (None, None) => None,
+        };
+        let filter = DiskFilter {
+            udev: setup.filter.clone().unwrap_or_default(),
+            filter_match: setup.filter_match.clone().unwrap_or(FilterMatch::Any),
+            size: setup.filter_size,
+            block_size: setup.filter_block_size,
+            take,
+        };
+
+        let indices = get_matched_disks(&filter, &udev_info.disks, &ctx.runtime_info.disks)
+            .map_err(|err| err.to_string())?;
+        indices
+            .iter()
+            .filter_map(|index| ctx.runtime_info.disks.iter().find(|d| &d.index == index))
+            .cloned()
+            .collect()
+    };
+
+    if disks.is_empty() {
+        return Err("no disk matched".to_owned());
+    }
+
+    Ok(Some(disks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::PasswordOptions,
+        test_utils::{dummy_disk, dummy_runtime_info, dummy_setup_info},
+    };
+    use proxmox_installer_common::{
+        options::{BootdiskOptions, NetworkOptions, TimezoneOptions},
+        setup::{CountryInfo, KeyboardMapping},
+    };
+    use serde_json::json;
+    use std::{collections::HashMap, net::Ipv4Addr};
+
+    fn dummy_context() -> (SetupInfo, RuntimeInfo, LocaleInfo, UdevInfo) {
+        let setup_info = dummy_setup_info();
+        let runtime_info = dummy_runtime_info(vec![dummy_disk("0", "sda"), dummy_disk("1", "sdb")]);
+
+        let country = |name: &str, kmap: &str| CountryInfo {
+            name: name.to_owned(),
+            zone: String::new(),
+            kmap: kmap.to_owned(),
+        };
+        let kmap = |name: &str| KeyboardMapping {
+            name: name.to_owned(),
+            id: String::new(),
+            xkb_layout: String::new(),
+            xkb_variant: String::new(),
+        };
+        let locales = LocaleInfo {
+            cczones: HashMap::from([
+                ("at".to_owned(), vec!["Europe/Vienna".to_owned()]),
+                ("ch".to_owned(), vec!["Europe/Zurich".to_owned()]),
+            ]),
+            countries: HashMap::from([
+                ("at".to_owned(), country("Austria", "de")),
+                ("ch".to_owned(), country("Switzerland", "de-ch")),
+            ]),
+            kmap: HashMap::from([
+                ("de".to_owned(), kmap("German")),
+                ("de-ch".to_owned(), kmap("Swiss-German")),
+                ("en-us".to_owned(), kmap("U.S. English")),
+            ]),
+        };
+
+        let udev_info: UdevInfo = serde_json::from_value(json!({
+            "disks": {
+                "0": { "DEVNAME": "/dev/sda", "ID_SERIAL": "SERIAL_A" },
+                "1": { "DEVNAME": "/dev/sdb", "ID_SERIAL": "SERIAL_B" },
+            },
+            "nics": {
+                "eno1": { "ID_NET_NAME_MAC": "enxa0369f0ab382" },
+                "eno2": { "ID_NET_NAME_MAC": "enxa0369f0ab383" },
+            },
+        }))
+        .unwrap();
+
+        (setup_info, runtime_info, locales, udev_info)
+    }
+
+    fn default_options(setup_info: &SetupInfo, runtime_info: &RuntimeInfo) -> InstallerOptions {
+        InstallerOptions {
+            bootdisk: BootdiskOptions::defaults_from(&runtime_info.disks[0]),
+            timezone: TimezoneOptions {
+                country: "at".to_owned(),
+                timezone: "Europe/Vienna".to_owned(),
+                kb_layout: "de".to_owned(),
+            },
+            password: PasswordOptions::default(),
+            network: NetworkOptions::defaults_from(setup_info, &runtime_info.network),
+            autoreboot: true,
+        }
+    }
+
+    fn apply_answer(answer: &str) -> (InstallerOptions, Vec<String>) {
+        let (setup_info, runtime_info, locales, udev_info) = dummy_context();
+        let mut options = default_options(&setup_info, &runtime_info);
+        let ctx = PreseedContext {
+            setup_info: &setup_info,
+            runtime_info: &runtime_info,
+            locales: &locales,
+            udev_info: Some(&udev_info),
+        };
+
+        let answer: PartialAnswer = toml::from_str(answer).unwrap();
+        let warnings = apply(&answer, &mut options, &ctx);
+        (options, warnings)
+    }
+
+    #[test]
+    fn preseed_global_and_network() {
+        let (options, warnings) = apply_answer(
+            r#"
+            [global]
+            country = "ch"
+            mailto = "admin@example.com"
+            root_password_hashed = "$6$salt$hash"
+
+            [network]
+            source = "from-answer"
+            cidr = "192.168.1.10/24"
+            gateway = "192.168.1.1"
+            dns = "192.168.1.1"
+            filter.ID_NET_NAME_MAC = "*a0369f0ab383"
+            "#,
+        );
+
+        assert_eq!(options.timezone.country, "ch");
+        assert_eq!(options.timezone.timezone, "Europe/Zurich");
+        assert_eq!(options.timezone.kb_layout, "de-ch");
+        assert_eq!(options.password.email, "admin@example.com");
+        assert_eq!(options.network.ifname, "eno2");
+        assert_eq!(
+            options.network.ipv4,
+            Some(NetworkAddressOptions {
+                address: CidrAddress::new(Ipv4Addr::new(192, 168, 1, 10), 24).unwrap(),
+                gateway: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+            })
+        );
+        assert_eq!(
+            options.network.dns_servers,
+            vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))]
+        );
+        // a hashed password cannot be pre-filled
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn preseed_partial_values() {
+        let (options, warnings) = apply_answer(
+            r#"
+            [global]
+            keyboard = "fr"
+            fqdn = "pve1.example.com"
+            "#,
+        );
+
+        assert_eq!(options.timezone.kb_layout, "de");
+        assert_eq!(options.network.fqdn.to_string(), "pve1.example.com");
+        assert_eq!(options.bootdisk.fstype, FsType::Ext4);
+        assert_eq!(warnings, ["Unknown keyboard layout 'fr'."]);
+    }
+
+    #[test]
+    fn preseed_rejects_unknown_keys() {
+        let parse = |answer: &str| toml::from_str::<PartialAnswer>(answer);
+
+        assert!(parse("[global]\nmail_to = \"admin@example.com\"\n").is_err());
+        assert!(parse("[network]\nsource = \"from-answer\"\ncdir = \"10.0.0.2/24\"\n").is_err());
+        assert!(parse("[disk-setup]\nfilesystem = \"ext4\"\ndisklist = [\"sda\"]\n").is_err());
+        assert!(parse("[global]\nkeyboard = \"xx\"\n").is_err());
+    }
+
+    #[test]
+    fn preseed_disk_setup() {
+        let (options, warnings) = apply_answer(
+            r#"
+            [disk-setup]
+            filesystem = "zfs"
+            zfs.raid = "raid1"
+            zfs.ashift = 13
+            disk_list = ["SERIAL_B", "sda"]
+            "#,
+        );
+
+        assert!(warnings.is_empty());
+        assert_eq!(options.bootdisk.fstype, FsType::Zfs(ZfsRaidLevel::Raid1));
+        assert_eq!(options.bootdisk.disks[0].path, "/dev/sdb");
+        match &options.bootdisk.advanced {
+            AdvancedBootdiskOptions::Zfs(zfs) => {
+                assert_eq!(zfs.ashift, 13);
+                assert_eq!(zfs.selected_disks, [1, 0]);
+            }
+            _ => panic!("expected ZFS options"),
+        }
+
+        let (options, warnings) = apply_answer(
+            r#"
+            [disk-setup]
+            filesystem = "xfs"
+            filter.ID_SERIAL = "SERIAL_*"
+            "#,
+        );
+        assert_eq!(options.bootdisk.fstype, FsType::Ext4);
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -6,8 +6,30 @@ use proxmox_installer_common::{
     setup::{InstallConfig, InstallRootPassword},
 };
 
-impl From<InstallerOptions> for InstallConfig {
-    fn from(options: InstallerOptions) -> Self {
+impl TryFrom<InstallerOptions> for InstallConfig {
+    type Error = String;
+
+    fn try_from(options: InstallerOptions) -> Result<Self, Self::Error> {
+        // The TUI network panel only lets the user continue if at least one of the IPv4 or IPv6
+        // configuration, as well as a DNS server is set, but pre-seeded values might not be.
+        let primary = options
+            .network
+            .primary()
+            .ok_or("no IP address configured for the management interface")?
+            .clone();
+        let secondary = options.network.secondary().cloned();
+        let (dns, dns_additional) = options
+            .network
+            .dns_servers
+            .split_first()
+            .ok_or("no DNS server configured")?;
+        let hostname = options
+            .network
+            .fqdn
+            .host()
+            .ok_or("no hostname configured")?
+            .to_owned();
+
         let mut config = Self {
             autoreboot: options.autoreboot as usize,
 
@@ -34,15 +56,21 @@ impl From<InstallerOptions> for InstallConfig {
             root_ssh_keys: vec![],
 
             mngmt_nic: options.network.ifname,
+            mngmt_vlan: None,
+            bonds: vec![],
+            bridge_ports: vec![],
+            vlans: vec![],
 
-            // Safety: At this point, it is know that we have a valid FQDN, as
-            // this is set by the TUI network panel, which only lets the user
-            // continue if a valid FQDN is provided.
-            hostname: options.network.fqdn.host().expect("valid FQDN").to_owned(),
+            hostname,
             domain: options.network.fqdn.domain(),
-            cidr: options.network.address,
-            gateway: options.network.gateway,
-            dns: options.network.dns_server,
+            cidr: primary.address,
+            gateway: primary.gateway,
+            dns: *dns,
+            cidr6: secondary.as_ref().map(|ipv6| ipv6.address.clone()),
+            gateway6: secondary.map(|ipv6| ipv6.gateway),
+            dns_additional: dns_additional.to_vec(),
+
+            first_boot: None,
         };
 
         match &options.bootdisk.advanced {
@@ -75,6 +103,6 @@ impl From<InstallerOptions> for InstallConfig {
             }
         }
 
-        config
+        Ok(config)
     }
 }
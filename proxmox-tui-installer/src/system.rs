@@ -1,6 +1,15 @@
-use std::{fs::OpenOptions, io::Write, process::Command};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
 
-use proxmox_installer_common::setup::KeyboardMapping;
+use proxmox_installer_common::{
+    setup::{read_json, KeyboardMapping},
+    udevinfo::UdevInfo,
+    RUNTIME_DIR,
+};
 
 pub fn set_keyboard_layout(kmap: &KeyboardMapping) -> Result<(), String> {
     Command::new("setxkbmap")
@@ -30,3 +39,91 @@ BACKSPACE="guess"
 
     Ok(())
 }
+
+/// Hashes the password in crypt(3) SHA-512 format, as accepted for `root_password_hashed` in
+/// answer files.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let mut child = Command::new("openssl")
+        .args(["passwd", "-6", "-stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to run openssl: {err}"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{password}").map_err(|err| err.to_string())?;
+    }
+
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to hash password: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Looks up the serial of a disk as reported by udev, if any.
+pub fn disk_serial(path: &str) -> Option<String> {
+    let output = Command::new("udevadm")
+        .args(["info", "--query=property", "--name", path])
+        .output()
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("ID_SERIAL="))
+        .map(|serial| serial.to_owned())
+}
+
+/// Writes a file onto the filesystem of the given device, e.g. a USB flash drive.
+///
+/// The device is mounted temporarily for that and unmounted again afterwards.
+pub fn write_file_to_device(device: &str, name: &str, contents: &str) -> Result<(), String> {
+    let mount_point = Path::new("/tmp/proxmox-export");
+    fs::create_dir_all(mount_point).map_err(|err| err.to_string())?;
+
+    let output = Command::new("mount")
+        .arg(device)
+        .arg(mount_point)
+        .output()
+        .map_err(|err| format!("failed to mount {device}: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to mount {device}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let result = fs::write(mount_point.join(name), contents)
+        .map_err(|err| format!("failed to write {name}: {err}"));
+
+    let output = Command::new("umount")
+        .arg(mount_point)
+        .output()
+        .map_err(|err| format!("failed to unmount {device}: {err}"))?;
+    if result.is_ok() && !output.status.success() {
+        return Err(format!(
+            "failed to unmount {device}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    result
+}
+
+/// Reads the udev properties of all disks and network interfaces, as gathered by the low-level
+/// installer.
+pub fn read_udev_info(in_test_mode: bool) -> Result<UdevInfo, String> {
+    let base_path = if in_test_mode {
+        format!("./testdir/{RUNTIME_DIR}")
+    } else {
+        RUNTIME_DIR.to_owned()
+    };
+
+    read_json(Path::new(&base_path).join("run-env-udev.json"))
+        .map_err(|err| format!("Failed to retrieve udev info details: {err}"))
+}
@@ -0,0 +1,71 @@
+//! Fixtures shared by the unit tests of the individual modules.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use proxmox_installer_common::{
+    options::Disk,
+    setup::{
+        BootType, Dns, Interface, InterfaceState, IsoInfo, IsoLocations, NetworkInfo,
+        ProductConfig, ProxmoxProduct, RuntimeInfo, SetupInfo,
+    },
+};
+
+pub fn dummy_disk(index: &str, name: &str) -> Disk {
+    Disk {
+        index: index.to_owned(),
+        path: format!("/dev/{name}"),
+        model: None,
+        size: 100.,
+        block_size: Some(512),
+    }
+}
+
+pub fn dummy_setup_info() -> SetupInfo {
+    SetupInfo {
+        config: ProductConfig {
+            fullname: "Proxmox VE".to_owned(),
+            product: ProxmoxProduct::PVE,
+            enable_btrfs: true,
+            bridged_network: true,
+        },
+        iso_info: IsoInfo {
+            release: String::new(),
+            isorelease: String::new(),
+        },
+        locations: IsoLocations {
+            iso: PathBuf::new(),
+        },
+    }
+}
+
+/// Runtime info with the given disks and two network interfaces, `eno1` and `eno2`, neither of
+/// them configured.
+pub fn dummy_runtime_info(disks: Vec<Disk>) -> RuntimeInfo {
+    let interface = |name: &str, index, mac: &str| Interface {
+        name: name.to_owned(),
+        index,
+        mac: mac.to_owned(),
+        state: InterfaceState::Up,
+        addresses: None,
+    };
+
+    RuntimeInfo {
+        boot_type: BootType::Efi,
+        country: Some("at".to_owned()),
+        disks,
+        network: NetworkInfo {
+            dns: Dns {
+                domain: None,
+                dns: Vec::new(),
+            },
+            routes: None,
+            interfaces: BTreeMap::from([
+                ("eno1".to_owned(), interface("eno1", 0, "A0:36:9F:0A:B3:82")),
+                ("eno2".to_owned(), interface("eno2", 1, "A0:36:9F:0A:B3:83")),
+            ]),
+            hostname: None,
+        },
+        total_memory: 8192,
+        hvm_supported: true,
+    }
+}
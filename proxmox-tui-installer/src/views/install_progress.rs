@@ -82,7 +82,9 @@ impl InstallProgressView {
 
             let mut writer = child.stdin.take().ok_or("failed to get stdin writer")?;
 
-            serde_json::to_writer(&mut writer, &InstallConfig::from(state.options))
+            let config = InstallConfig::try_from(state.options)
+                .map_err(|err| format!("invalid installer options: {err}"))?;
+            serde_json::to_writer(&mut writer, &config)
                 .map_err(|err| format!("failed to serialize install config: {err}"))?;
             writeln!(writer).map_err(|err| format!("failed to write install config: {err}"))?;
 
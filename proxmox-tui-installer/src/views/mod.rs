@@ -512,6 +512,15 @@ impl CidrAddressEditView {
             .fixed_width(4)
     }
 
+    /// Whether no address was entered at all, e.g. to leave an optional address unset.
+    pub fn is_empty(&self) -> bool {
+        self.view
+            .get_child(0)
+            .and_then(|v| v.downcast_ref::<ResizedView<EditView>>())
+            .map(|v| v.get_inner().get_content().trim().is_empty())
+            .unwrap_or(true)
+    }
+
     fn get_values(&self) -> Option<CidrAddress> {
         let addr = self
             .view
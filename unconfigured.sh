@@ -218,7 +218,8 @@ if [ $start_auto_installer -ne 0 ]; then
     cat >> /etc/dhcp/dhclient.conf <<EOF
 option proxmox-auto-installer-manifest-url code 250 = text;
 option proxmox-auto-installer-cert-fingerprint code 251 = text;
-also request proxmox-auto-installer-manifest-url, proxmox-auto-installer-cert-fingerprint;
+option proxmox-auto-installer-ca-fingerprint code 253 = text;
+also request proxmox-auto-installer-manifest-url, proxmox-auto-installer-cert-fingerprint, proxmox-auto-installer-ca-fingerprint, tftp-server-name;
 EOF
 fi
 